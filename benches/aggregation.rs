@@ -0,0 +1,89 @@
+//! Benchmarks of reading and aggregating input files, over generated
+//! directories of various shapes. Run with `cargo bench`.
+//!
+//! Each case runs `balances --profile-aggregation` a few times and reports the
+//! best wall-clock time of the whole process, and the best aggregation time
+//! reported by the tool itself.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 3;
+
+fn id(n: usize) -> String {
+    format!("m{n:048}")
+}
+
+/// Create a directory of `files` files with `entries` entries each.
+fn generate(name: &str, files: usize, entries: usize) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("after8-bench-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    for f in 0..files {
+        let content = (0..entries)
+            .map(|e| format!("  \"{}\": \"{}.{:09}\"", id(f * entries + e), e % 1000, f))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        std::fs::write(
+            dir.join(format!("{f:06}.json")),
+            format!("{{\n{content}\n}}\n"),
+        )
+        .unwrap();
+    }
+    dir
+}
+
+fn run(dir: &Path) -> (Duration, Duration) {
+    let start = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_many-after8"))
+        .args(["--read-only", "--profile-aggregation", "--dir"])
+        .arg(dir)
+        .arg("balances")
+        .output()
+        .unwrap();
+    let wall = start.elapsed();
+    assert!(output.status.success());
+
+    // The tool prints its total aggregation time with Debug formatting.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let total = stderr
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("total:"))
+        .map(|t| parse_duration(t.trim()))
+        .unwrap_or_default();
+    (wall, total)
+}
+
+fn parse_duration(s: &str) -> Duration {
+    let split = s
+        .find(|c: char| c.is_alphabetic() || c == 'µ')
+        .unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value = value.parse::<f64>().unwrap_or(0.0);
+    let seconds = match unit {
+        "ns" => value / 1e9,
+        "µs" => value / 1e6,
+        "ms" => value / 1e3,
+        _ => value,
+    };
+    Duration::from_secs_f64(seconds)
+}
+
+fn main() {
+    let cases = [
+        ("10-files", 10, 100),
+        ("1k-files", 1_000, 100),
+        ("100k-files", 100_000, 1),
+        ("large-single-file", 1, 200_000),
+    ];
+
+    println!("{:<20}{:>14}{:>16}", "case", "wall", "aggregation");
+    for (name, files, entries) in cases {
+        let dir = generate(name, files, entries);
+        let (wall, total) = (0..RUNS)
+            .map(|_| run(&dir))
+            .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1)))
+            .unwrap();
+        println!("{:<20}{:>14.2?}{:>16.2?}", name, wall, total);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
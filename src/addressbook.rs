@@ -0,0 +1,228 @@
+//! Export and import of the recipient metadata (`recipients.json`) and the
+//! aliases (`aliases.json`) as a single portable file, so several operators can
+//! keep their metadata in sync without sharing the allocation files themselves.
+//!
+//! Importing merges field by field. Fields only one side has are kept, lists
+//! (e.g. tags) are merged, and fields both sides set to different values are
+//! conflicts, which must be resolved with `--prefer`. Aliases merge the same
+//! way, a name both sides give to different identities being a conflict.
+//! Exports from before aliases were included import without any.
+use crate::aliases::{self, Aliases};
+use crate::ensure_writable;
+use crate::recipients::{load_metadata, save_metadata, Metadata};
+use crate::storage::Storage;
+use clap::Parser;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// Identifies the exported files.
+const FORMAT: &str = "many-after8-addressbook";
+const FORMAT_VERSION: u64 = 1;
+
+#[derive(Debug, Parser)]
+pub struct AddressbookOpt {
+    #[command(subcommand)]
+    subcommand: AddressbookSubcommand,
+}
+
+#[derive(Debug, Parser)]
+enum AddressbookSubcommand {
+    /// Write the recipient metadata and aliases to a portable file.
+    Export {
+        /// Where to write the file. Writes to stdout if missing.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Merge an exported file into the recipient metadata and aliases.
+    Import {
+        /// The exported file.
+        file: PathBuf,
+
+        /// How to resolve fields both sides set to different values. Fails on
+        /// conflicts if missing.
+        #[arg(long, value_enum)]
+        prefer: Option<Prefer>,
+
+        /// Only show what would change.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Prefer {
+    /// Keep the local value.
+    Ours,
+    /// Take the imported value.
+    Theirs,
+}
+
+pub fn addressbook(
+    storage: &dyn Storage,
+    opts: AddressbookOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    match opts.subcommand {
+        AddressbookSubcommand::Export { out } => export(storage, out, read_only),
+        AddressbookSubcommand::Import {
+            file,
+            prefer,
+            dry_run,
+        } => import(storage, file, prefer, dry_run, read_only),
+    }
+}
+
+fn export(
+    storage: &dyn Storage,
+    out: Option<PathBuf>,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    if out.is_some() {
+        ensure_writable(read_only, "export the address book")?;
+    }
+    let metadata = load_metadata(storage)?;
+    let aliases = aliases::load(storage)?;
+    let content = format!(
+        "{}\n",
+        serde_json::to_string_pretty(&json!({
+            "format": FORMAT,
+            "version": FORMAT_VERSION,
+            "recipients": metadata,
+            "aliases": aliases,
+        }))?
+    );
+    match out {
+        Some(out) => {
+            std::fs::write(&out, content)?;
+            eprintln!(
+                "Exported {} recipient(s) and {} alias(es) to {:?}.",
+                metadata.len(),
+                aliases.len(),
+                out
+            );
+        }
+        None => print!("{content}"),
+    }
+    Ok(())
+}
+
+fn read_export(file: &PathBuf) -> Result<(Metadata, Aliases), anyhow::Error> {
+    let content = std::fs::read_to_string(file)
+        .map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", file, e))?;
+    let value: Value = serde_json::from_str(&content)?;
+    if value["format"] != FORMAT {
+        anyhow::bail!("{:?} is not an exported address book.", file);
+    }
+    match value["version"].as_u64() {
+        Some(FORMAT_VERSION) => {}
+        v => anyhow::bail!("Unsupported address book version {:?} in {:?}.", v, file),
+    }
+    let metadata = serde_json::from_value(value["recipients"].clone())
+        .map_err(|e| anyhow::anyhow!("Invalid recipients in {:?}: {}", file, e))?;
+    let aliases = match &value["aliases"] {
+        Value::Null => Aliases::new(),
+        names => aliases::parse(names.clone(), &format!("aliases in {file:?}"))?,
+    };
+    Ok((metadata, aliases))
+}
+
+fn import(
+    storage: &dyn Storage,
+    file: PathBuf,
+    prefer: Option<Prefer>,
+    dry_run: bool,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    if !dry_run {
+        ensure_writable(read_only, "import recipient metadata (use --dry-run)")?;
+    }
+    let (theirs, their_aliases) = read_export(&file)?;
+    let mut metadata = load_metadata(storage)?;
+    let mut aliases = aliases::load(storage)?;
+
+    let (mut added, mut updated, mut conflicts) = (0, 0, 0);
+    for (id, fields) in theirs {
+        let Some(ours) = metadata.get_mut(&id) else {
+            println!("{id}: new recipient");
+            metadata.insert(id, fields);
+            added += 1;
+            continue;
+        };
+        for (field, value) in fields {
+            let Some(current) = ours.get_mut(&field) else {
+                println!("{id}: {field} set to {value}");
+                ours.insert(field, value);
+                updated += 1;
+                continue;
+            };
+            if *current == value {
+                continue;
+            }
+            match (current, value) {
+                (Value::Array(current), Value::Array(new)) => {
+                    for item in new {
+                        if !current.contains(&item) {
+                            println!("{id}: {field} gains {item}");
+                            current.push(item);
+                            updated += 1;
+                        }
+                    }
+                }
+                (current, value) => match prefer {
+                    Some(Prefer::Ours) => {
+                        println!("{id}: {field} kept as {current} (theirs: {value})");
+                    }
+                    Some(Prefer::Theirs) => {
+                        println!("{id}: {field} changed from {current} to {value}");
+                        *current = value;
+                        updated += 1;
+                    }
+                    None => {
+                        println!("{id}: {field} conflicts, ours: {current}, theirs: {value}");
+                        conflicts += 1;
+                    }
+                },
+            }
+        }
+    }
+
+    let mut aliased = 0;
+    for (name, id) in their_aliases {
+        let Some(current) = aliases.get_mut(&name) else {
+            println!("alias {name}: new, for {id}");
+            aliases.insert(name, id);
+            aliased += 1;
+            continue;
+        };
+        if *current == id {
+            continue;
+        }
+        match prefer {
+            Some(Prefer::Ours) => println!("alias {name}: kept as {current} (theirs: {id})"),
+            Some(Prefer::Theirs) => {
+                println!("alias {name}: changed from {current} to {id}");
+                *current = id;
+                aliased += 1;
+            }
+            None => {
+                println!("alias {name}: conflicts, ours: {current}, theirs: {id}");
+                conflicts += 1;
+            }
+        }
+    }
+
+    eprintln!(
+        "{added} recipient(s) added, {updated} field(s) updated, {aliased} alias(es) added or changed, {conflicts} conflict(s)."
+    );
+    if conflicts > 0 {
+        anyhow::bail!("Found {conflicts} conflict(s), resolve them with --prefer ours|theirs.");
+    }
+    if !dry_run && added + updated > 0 {
+        save_metadata(storage, &metadata)?;
+    }
+    if !dry_run && aliased > 0 {
+        aliases::save(storage, &aliases)?;
+    }
+    Ok(())
+}
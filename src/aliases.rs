@@ -0,0 +1,66 @@
+//! Friendly names for identities. An optional `aliases.json` in the data
+//! directory maps names to the identities they stand for:
+//!
+//! ```json
+//! { "alice": "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f" }
+//! ```
+//!
+//! Input files can then use `alice` wherever the identity is expected. Names
+//! are replaced with the identity when files are read, so balances, mint
+//! files and reports only use identities.
+use crate::identity::{self, Identity};
+use crate::storage::Storage;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const ALIASES_FILE: &str = "aliases.json";
+
+/// The identity (in textual form) of each name.
+pub type Aliases = BTreeMap<String, String>;
+
+pub fn load(storage: &dyn Storage) -> Result<Aliases, anyhow::Error> {
+    let path = Path::new(ALIASES_FILE);
+    if !storage.exists(path) {
+        return Ok(Aliases::new());
+    }
+    let names = serde_json::from_str(&storage.read_to_string(path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid {:?}, expected an object of names: {}", path, e))?;
+    parse(names, &format!("{path:?}"))
+}
+
+/// The aliases of `names`, read from `source`, e.g. an exported address book.
+pub fn parse(names: Value, source: &str) -> Result<Aliases, anyhow::Error> {
+    let names: BTreeMap<String, Value> = serde_json::from_value(names)
+        .map_err(|e| anyhow::anyhow!("Invalid {source}, expected an object of names: {e}"))?;
+
+    let mut aliases = Aliases::new();
+    for (name, id) in names {
+        if name.parse::<Identity>().is_ok() {
+            anyhow::bail!("Invalid {source}: '{name}' is an identity, not a name.");
+        }
+        let identity = id
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid {source}: '{name}' must be a string."))?
+            .parse::<Identity>()
+            .map_err(|e| anyhow::anyhow!("Invalid {source}: '{name}' is {id}: {e}."))?;
+        aliases.insert(name, identity.to_string());
+    }
+    Ok(aliases)
+}
+
+pub fn save(storage: &dyn Storage, aliases: &Aliases) -> Result<(), anyhow::Error> {
+    storage.write(
+        Path::new(ALIASES_FILE),
+        format!("{}\n", serde_json::to_string_pretty(aliases)?).as_bytes(),
+    )
+}
+
+/// The identity (in textual form) `key` stands for: the identity of an alias,
+/// an identity in any form, or `key` itself if it is neither.
+pub fn resolve(aliases: &Aliases, key: &str) -> String {
+    aliases
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| identity::normalize(key))
+}
@@ -0,0 +1,67 @@
+//! How reports display amounts. Each report takes its own `--amounts` and
+//! `--ticker` flags, so the same numbers can be shown in full precision for
+//! finance and rounded for the operator console.
+use crate::{format_tokens, DENOMINATOR};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AmountStyle {
+    /// All 9 decimals, e.g. `1234.500000000`.
+    #[default]
+    Full,
+    /// Rounded to 2 decimals, e.g. `1234.50`.
+    Rounded,
+    /// Engineering notation, with an exponent that is a multiple of 3, e.g.
+    /// `1.235e3`.
+    Engineering,
+}
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct AmountFormat {
+    /// How to display amounts.
+    #[arg(long = "amounts", value_enum, default_value = "full")]
+    pub style: AmountStyle,
+
+    /// A ticker to display after amounts, e.g. `MFX`.
+    #[arg(long)]
+    pub ticker: Option<String>,
+}
+
+impl AmountFormat {
+    /// Format an amount in base units.
+    pub fn format(&self, units: i128) -> String {
+        let amount = match self.style {
+            AmountStyle::Full => format_tokens(units),
+            AmountStyle::Rounded => rounded(units),
+            AmountStyle::Engineering => engineering(units),
+        };
+        match &self.ticker {
+            Some(ticker) => format!("{amount} {ticker}"),
+            None => amount,
+        }
+    }
+}
+
+/// Round to 2 decimals, half away from zero.
+fn rounded(units: i128) -> String {
+    let cent = DENOMINATOR as u128 / 100;
+    let cents = (units.unsigned_abs() + cent / 2) / cent;
+    let sign = if units < 0 && cents > 0 { "-" } else { "" };
+    format!("{sign}{}.{:02}", cents / 100, cents % 100)
+}
+
+/// Engineering notation with 3 decimals. It is only for display, so going
+/// through a float is fine.
+fn engineering(units: i128) -> String {
+    let tokens = units as f64 / DENOMINATOR as f64;
+    if tokens == 0.0 {
+        return "0.000e0".to_string();
+    }
+    let mut exponent = (tokens.abs().log10() / 3.0).floor() as i32 * 3;
+    let mut mantissa = tokens / 10f64.powi(exponent);
+    // Rounding to 3 decimals can carry the mantissa to 1000.
+    if format!("{:.3}", mantissa.abs()).starts_with("1000") {
+        exponent += 3;
+        mantissa /= 1000.0;
+    }
+    format!("{mantissa:.3}e{exponent}")
+}
@@ -0,0 +1,213 @@
+//! Checks over the local mint history, to catch mistakes or fraud early.
+use crate::restate;
+use crate::storage::Storage;
+use crate::{format_tokens, input_files, is_mint_file, parse_tokens, run_date};
+use chrono::{Datelike, Timelike, Weekday};
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// How far above its maximum a randomized amount can go, in percent.
+const MAX_JITTER_PERCENT: i128 = 120;
+
+#[derive(Debug, Parser)]
+pub struct AuditOpt {
+    #[command(subcommand)]
+    subcommand: AuditSubcommand,
+}
+
+#[derive(Debug, Parser)]
+enum AuditSubcommand {
+    /// Flag suspicious patterns in past mint runs.
+    Anomalies(AnomaliesOpt),
+}
+
+#[derive(Debug, Parser)]
+pub struct AnomaliesOpt {
+    /// Flag amounts more than this many times the recipient's median amount
+    /// in its other runs.
+    #[arg(long, default_value = "3")]
+    factor: f64,
+
+    /// Only compare against a recipient's history once it has at least this
+    /// many other runs.
+    #[arg(long, default_value = "2")]
+    min_history: usize,
+
+    /// The maximum amount per run that mints are configured with. Amounts
+    /// above it (plus the randomization jitter) are flagged.
+    #[arg(long, default_value = "100", value_parser = tokens_arg)]
+    max: i128,
+
+    /// The first hour of business hours, in local time.
+    #[arg(long, default_value = "9")]
+    business_start: u32,
+
+    /// The hour business hours end, in local time.
+    #[arg(long, default_value = "18")]
+    business_end: u32,
+}
+
+fn tokens_arg(s: &str) -> Result<i128, String> {
+    parse_tokens(s)
+        .filter(|t| *t > 0)
+        .ok_or_else(|| format!("expected a positive amount of tokens, got '{s}'"))
+}
+
+pub fn audit(storage: &dyn Storage, opts: AuditOpt) -> Result<(), anyhow::Error> {
+    match opts.subcommand {
+        AuditSubcommand::Anomalies(opts) => anomalies(storage, opts),
+    }
+}
+
+fn median(amounts: &mut [i128]) -> i128 {
+    amounts.sort();
+    let mid = amounts.len() / 2;
+    if amounts.len().is_multiple_of(2) {
+        (amounts[mid - 1] + amounts[mid]) / 2
+    } else {
+        amounts[mid]
+    }
+}
+
+fn tokens(amount: i128) -> String {
+    format_tokens(amount)
+}
+
+/// A suspicious pattern in a run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    /// The run was outside business hours, or on a weekend.
+    OffHours { date: String },
+    /// An identity was minted more than the configured max allows.
+    AboveMax { id: String, amount: i128, max: i128 },
+    /// An identity was minted more than `factor` times its median amount.
+    AboveUsual {
+        id: String,
+        amount: i128,
+        factor: f64,
+        median: i128,
+    },
+}
+
+impl std::fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anomaly::OffHours { date } => write!(f, "run outside business hours ({date})"),
+            Anomaly::AboveMax { id, amount, max } => write!(
+                f,
+                "{}: {} is above the configured max of {}",
+                id,
+                tokens(*amount),
+                tokens(*max)
+            ),
+            Anomaly::AboveUsual {
+                id,
+                amount,
+                factor,
+                median,
+            } => write!(
+                f,
+                "{}: {} is more than {}x its usual {}",
+                id,
+                tokens(*amount),
+                factor,
+                tokens(*median)
+            ),
+        }
+    }
+}
+
+fn anomalies(storage: &dyn Storage, opts: AnomaliesOpt) -> Result<(), anyhow::Error> {
+    let (runs, found) = find_anomalies(storage, &opts)?;
+    for (path, anomaly) in &found {
+        println!("{}: {}", path.display(), anomaly);
+    }
+    eprintln!("{} runs checked, {} anomalies.", runs, found.len());
+    if !found.is_empty() {
+        anyhow::bail!("Found {} anomalies.", found.len());
+    }
+    Ok(())
+}
+
+/// Check the past runs. Returns the number of runs checked, and the
+/// anomalies found in each, oldest run first.
+pub fn find_anomalies(
+    storage: &dyn Storage,
+    opts: &AnomaliesOpt,
+) -> Result<(usize, Vec<(PathBuf, Anomaly)>), anyhow::Error> {
+    let mut runs = Vec::new();
+    let restatements = restate::load(storage)?;
+    for path in input_files(storage)?
+        .into_iter()
+        .filter(|p| is_mint_file(p))
+    {
+        let date = run_date(storage, &path)?;
+        // Mint files hold the negative of what was minted.
+        let amounts = restate::run_amounts(storage, &path, &restatements)?
+            .into_iter()
+            .map(|(id, amount)| (id, -amount))
+            .collect::<BTreeMap<_, _>>();
+        runs.push((date, path, amounts));
+    }
+    runs.sort_by_key(|(date, _, _)| *date);
+
+    let mut history = BTreeMap::<&str, Vec<(&PathBuf, i128)>>::new();
+    for (_, path, amounts) in &runs {
+        for (id, amount) in amounts {
+            history.entry(id).or_default().push((path, *amount));
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut flag = |path: &PathBuf, anomaly| found.push((path.clone(), anomaly));
+
+    let max = opts.max * MAX_JITTER_PERCENT / 100;
+    for (date, path, amounts) in &runs {
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        if weekend || !(opts.business_start..opts.business_end).contains(&date.hour()) {
+            flag(
+                path,
+                Anomaly::OffHours {
+                    date: date.format("%a %H:%M").to_string(),
+                },
+            );
+        }
+
+        for (id, amount) in amounts {
+            if *amount > max {
+                flag(
+                    path,
+                    Anomaly::AboveMax {
+                        id: id.clone(),
+                        amount: *amount,
+                        max: opts.max,
+                    },
+                );
+            }
+
+            let mut others = history[id.as_str()]
+                .iter()
+                .filter(|(p, _)| *p != path)
+                .map(|(_, a)| *a)
+                .collect::<Vec<_>>();
+            if others.len() < opts.min_history.max(1) {
+                continue;
+            }
+            let median = median(&mut others);
+            if median > 0 && *amount as f64 > median as f64 * opts.factor {
+                flag(
+                    path,
+                    Anomaly::AboveUsual {
+                        id: id.clone(),
+                        amount: *amount,
+                        factor: opts.factor,
+                        median,
+                    },
+                );
+            }
+        }
+    }
+
+    Ok((runs.len(), found))
+}
@@ -0,0 +1,193 @@
+//! Bundles of past mint runs, for auditors: everything about a run in one
+//! JSON file, `bundles/<run>.json`.
+//!
+//! - `plan`: what the run minted, in base units, by identity.
+//! - `files`: the content and SHA-256 of the run's mint file(s), manifest and
+//!   receipts.
+//! - `runs_log`: the run's entries in `runs.log`.
+//! - `inputs`: the SHA-256 of the allocation files, and of the mint files up
+//!   to the run, as they are when bundling.
+//!
+//! Bundles are reproducible: bundling the same run of the same directory gives
+//! the same bytes. With `--pem`, the bundle is signed with `openssl pkeyutl`,
+//! and the signature written next to it as `bundles/<run>.json.sig`. It can
+//! be checked with `openssl pkeyutl -verify -pubin -inkey <public key> -rawin
+//! -in <run>.json -sigfile <run>.json.sig`.
+use crate::history::RUNS_LOG;
+use crate::receipts::RECEIPTS_DIR;
+use crate::signatures;
+use crate::storage::Storage;
+use crate::{ensure_writable, input_files, is_mint_file, read_json, run_date, sha256};
+use crate::{MANIFEST_EXTENSION, RESERVED_FILES};
+use clap::Parser;
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+/// The directory bundles are written to. It isn't the data directory itself,
+/// where they would be read as allocation files.
+pub const BUNDLES_DIR: &str = "bundles";
+
+/// The value of the `schema` field.
+pub const SCHEMA: &str = "many-after8/bundle";
+
+/// The version of the schema.
+pub const VERSION: u64 = 1;
+
+#[derive(Debug, Parser)]
+pub struct BundleOpt {
+    /// The run to bundle: its mint file, or its timestamp as in
+    /// `20240101-120000`.
+    run: String,
+
+    /// The directory receipts were written to (`receipts --out`).
+    #[arg(long, default_value = RECEIPTS_DIR)]
+    receipts: PathBuf,
+
+    /// Sign the bundle with this key, using `openssl`.
+    #[arg(long)]
+    pem: Option<PathBuf>,
+
+    /// The `openssl` binary to sign with.
+    #[arg(long, default_value = "openssl", requires = "pem")]
+    openssl: PathBuf,
+}
+
+/// The timestamp of a run, from its mint file or the timestamp itself.
+fn stamp(run: &str) -> String {
+    let name = Path::new(run)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(run);
+    let name = name.strip_prefix("mint-").unwrap_or(name);
+    let name = name.strip_suffix(".json").unwrap_or(name);
+    match name.split_once("-part") {
+        Some((stamp, _)) => stamp.to_string(),
+        None => name.to_string(),
+    }
+}
+
+fn file_entry(storage: &dyn Storage, path: &Path) -> Result<Value, anyhow::Error> {
+    let content = storage.read(path)?;
+    Ok(json!({
+        "sha256": sha256::hex_digest(&content),
+        "content": String::from_utf8_lossy(&content),
+    }))
+}
+
+/// The bundle of the run with timestamp `stamp`.
+pub fn build(storage: &dyn Storage, stamp: &str, receipts: &Path) -> Result<Value, anyhow::Error> {
+    let inputs = input_files(storage)?;
+    let runs = inputs
+        .iter()
+        .filter(|p| {
+            let name = p.to_string_lossy();
+            is_mint_file(p)
+                && (name == format!("mint-{stamp}.json")
+                    || name.starts_with(&format!("mint-{stamp}-part")))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if runs.is_empty() {
+        anyhow::bail!("No mint run {stamp}.");
+    }
+    let date = run_date(storage, &runs[0])?;
+
+    let mut plan = Map::new();
+    let mut total = 0i128;
+    let mut files = Map::new();
+    for path in &runs {
+        // Mint files hold the negative of what was minted.
+        for (id, amount) in read_json(storage, path)? {
+            plan.insert(id, Value::from((-amount).to_string()));
+            total -= amount;
+        }
+        files.insert(path.display().to_string(), file_entry(storage, path)?);
+    }
+    let manifest = PathBuf::from(format!("mint-{stamp}.{MANIFEST_EXTENSION}"));
+    if storage.exists(&manifest) {
+        files.insert(
+            manifest.display().to_string(),
+            file_entry(storage, &manifest)?,
+        );
+    }
+    for path in &runs {
+        let dir = receipts.join(path.file_stem().unwrap_or_default());
+        for receipt in storage.list(&dir)? {
+            files.insert(
+                receipt.display().to_string(),
+                file_entry(storage, &receipt)?,
+            );
+        }
+    }
+
+    let names = runs
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>();
+    let runs_log = if storage.exists(Path::new(RUNS_LOG)) {
+        storage
+            .read_to_string(Path::new(RUNS_LOG))?
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .filter(|entry| {
+                entry["run"]
+                    .as_str()
+                    .is_some_and(|r| names.iter().any(|n| n == r))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut manifest = Vec::new();
+    for path in &inputs {
+        let reserved = RESERVED_FILES.iter().any(|r| path == Path::new(r));
+        if reserved || (is_mint_file(path) && run_date(storage, path)? > date) {
+            continue;
+        }
+        manifest.push(json!({
+            "file": path.display().to_string(),
+            "sha256": sha256::hex_digest(&storage.read(path)?),
+        }));
+    }
+
+    Ok(json!({
+        "schema": SCHEMA,
+        "version": VERSION,
+        "run": stamp,
+        "date": date.to_rfc3339(),
+        "plan": plan,
+        "total": total.to_string(),
+        "files": files,
+        "runs_log": runs_log,
+        "inputs": manifest,
+    }))
+}
+
+pub fn bundle(
+    storage: &dyn Storage,
+    opts: BundleOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    ensure_writable(read_only, "write a bundle")?;
+
+    let stamp = stamp(&opts.run);
+    let bundle = build(storage, &stamp, &opts.receipts)?;
+    let content = format!("{}\n", serde_json::to_string_pretty(&bundle)?);
+    let output = Path::new(BUNDLES_DIR).join(format!("{stamp}.json"));
+    storage.write(&output, content.as_bytes())?;
+    eprintln!("Bundled the run in {}.", output.display());
+    println!(
+        "{}  {}",
+        sha256::hex_digest(content.as_bytes()),
+        output.display()
+    );
+
+    if let Some(pem) = &opts.pem {
+        let signature = signatures::sign(&opts.openssl, pem, content.as_bytes())?;
+        let path = PathBuf::from(format!("{}.sig", output.display()));
+        storage.write(&path, &signature)?;
+        eprintln!("Signed it in {}.", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Blackout dates. An optional `calendar.yaml` in the data directory lists
+//! windows during which no mint should run, e.g. quarter-end freezes or legal
+//! holds:
+//!
+//! ```yaml
+//! blackouts:
+//!   - start: 2024-03-25
+//!     end: 2024-04-05
+//!     reason: Q1 freeze
+//!   - date: 2024-12-25
+//! ```
+//!
+//! Both ends of a window are inclusive. Only this subset of YAML is supported:
+//! a list of flat mappings with scalar values, and `#` comments.
+use crate::flat::scalar;
+use crate::storage::Storage;
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const CALENDAR_FILE: &str = "calendar.yaml";
+
+#[derive(Debug, Clone)]
+pub struct Blackout {
+    start: NaiveDate,
+    end: NaiveDate,
+    reason: Option<String>,
+}
+
+impl std::fmt::Display for Blackout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "blackout on {}", self.start)?;
+        } else {
+            write!(f, "blackout from {} to {}", self.start, self.end)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, " ({reason})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the list of mappings under `blackouts:`.
+fn parse(content: &str) -> Result<Vec<BTreeMap<String, String>>, anyhow::Error> {
+    let mut items = Vec::new();
+    let mut in_list = false;
+    for (number, line) in content.lines().enumerate() {
+        let number = number + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(' ') && !line.starts_with('-') {
+            match scalar(trimmed).as_str() {
+                "blackouts:" => in_list = true,
+                _ => anyhow::bail!("Unsupported key on line {number} of {CALENDAR_FILE}."),
+            }
+            continue;
+        }
+        if !in_list {
+            anyhow::bail!("Unexpected line {number} in {CALENDAR_FILE}.");
+        }
+
+        let entry = match trimmed.strip_prefix('-') {
+            Some(rest) => {
+                items.push(BTreeMap::new());
+                rest.trim()
+            }
+            None => trimmed,
+        };
+        let Some(item) = items.last_mut() else {
+            anyhow::bail!("Expected a list item on line {number} of {CALENDAR_FILE}.");
+        };
+        if entry.is_empty() {
+            continue;
+        }
+        let Some((key, value)) = entry.split_once(':') else {
+            anyhow::bail!("Expected 'key: value' on line {number} of {CALENDAR_FILE}.");
+        };
+        item.insert(key.trim().to_string(), scalar(value));
+    }
+    Ok(items)
+}
+
+pub fn load(storage: &dyn Storage) -> Result<Vec<Blackout>, anyhow::Error> {
+    let path = Path::new(CALENDAR_FILE);
+    if !storage.exists(path) {
+        return Ok(Vec::new());
+    }
+
+    let date = |item: &BTreeMap<String, String>, key: &str| {
+        item.get(key)
+            .map(|d| {
+                NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                    .map_err(|_| anyhow::anyhow!("Invalid {key} '{d}' in {CALENDAR_FILE}"))
+            })
+            .transpose()
+    };
+    let mut blackouts = Vec::new();
+    for item in parse(&storage.read_to_string(path)?)? {
+        let (start, end) = match (
+            date(&item, "date")?,
+            date(&item, "start")?,
+            date(&item, "end")?,
+        ) {
+            (Some(date), None, None) => (date, date),
+            (None, Some(start), Some(end)) if start <= end => (start, end),
+            _ => anyhow::bail!(
+                "Each blackout in {CALENDAR_FILE} needs either a date, or a start and an end."
+            ),
+        };
+        blackouts.push(Blackout {
+            start,
+            end,
+            reason: item.get("reason").cloned(),
+        });
+    }
+    Ok(blackouts)
+}
+
+/// The blackout window `date` falls in, if any.
+pub fn active(storage: &dyn Storage, date: NaiveDate) -> Result<Option<Blackout>, anyhow::Error> {
+    Ok(load(storage)?
+        .into_iter()
+        .find(|b| b.start <= date && date <= b.end))
+}
@@ -0,0 +1,107 @@
+//! A minimal CBOR (RFC 8949) encoder, enough to produce the exact byte-level
+//! payload of a MANY `tokens.mint` request.
+use crate::identity::Identity;
+
+/// The CBOR tag MANY uses for identities.
+const IDENTITY_TAG: u64 = 10000;
+
+#[derive(Default)]
+pub struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn header(&mut self, major: u8, value: u64) -> &mut Self {
+        let major = major << 5;
+        match value {
+            0..=23 => self.buffer.push(major | value as u8),
+            24..=0xff => self.buffer.extend([major | 24, value as u8]),
+            0x100..=0xffff => {
+                self.buffer.push(major | 25);
+                self.buffer.extend((value as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                self.buffer.push(major | 26);
+                self.buffer.extend((value as u32).to_be_bytes());
+            }
+            _ => {
+                self.buffer.push(major | 27);
+                self.buffer.extend(value.to_be_bytes());
+            }
+        }
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.header(0, value)
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.header(2, value.len() as u64);
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    pub fn str(&mut self, value: &str) -> &mut Self {
+        self.header(3, value.len() as u64);
+        self.buffer.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    pub fn array(&mut self, len: u64) -> &mut Self {
+        self.header(4, len)
+    }
+
+    pub fn map(&mut self, len: u64) -> &mut Self {
+        self.header(5, len)
+    }
+
+    pub fn tag(&mut self, tag: u64) -> &mut Self {
+        self.header(6, tag)
+    }
+
+    pub fn identity(&mut self, identity: &Identity) -> &mut Self {
+        self.tag(IDENTITY_TAG).bytes(identity.as_bytes())
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Encode the arguments of a `tokens.mint` request. The distribution is
+/// sorted by identity bytes, as the ledger expects a canonical map.
+pub fn mint_args(
+    token: &Identity,
+    distribution: &[(Identity, u64)],
+    memo: Option<&str>,
+) -> Vec<u8> {
+    let mut distribution = distribution.iter().collect::<Vec<_>>();
+    distribution.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+    let mut e = Encoder::new();
+    e.map(if memo.is_some() { 3 } else { 2 });
+    e.u64(0).identity(token);
+    e.u64(1).map(distribution.len() as u64);
+    for (id, amount) in distribution {
+        e.identity(id).u64(*amount);
+    }
+    if let Some(memo) = memo {
+        e.u64(2).array(1).str(memo);
+    }
+    e.into_bytes()
+}
+
+/// Encode the arguments of a `tokens.burn` request. They are those of
+/// `tokens.mint`; `error_on_under_burn` is left to the ledger's default.
+pub fn burn_args(
+    token: &Identity,
+    distribution: &[(Identity, u64)],
+    memo: Option<&str>,
+) -> Vec<u8> {
+    mint_args(token, distribution, memo)
+}
@@ -0,0 +1,103 @@
+//! Failure injection, for integration tests of resuming, rollbacks and
+//! locking. Only built with the `chaos` feature, which adds the hidden
+//! `--chaos <RATE>` option: each storage access and `ledger` submission then
+//! fails with probability `RATE`.
+//!
+//! - Reads return a truncated file, which fails to parse.
+//! - Writes and appends only write the first half of the data, then fail.
+//! - Releasing the lock fails, leaving the lock file behind.
+//! - Submissions fail before running `ledger`, or after it succeeded, as if
+//!   the response was lost.
+//!
+//! `--chaos-seed` makes the failures reproducible.
+use crate::storage::Storage;
+use chrono::{DateTime, Local};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+static CHAOS: Mutex<Option<(f64, StdRng)>> = Mutex::new(None);
+
+/// Start injecting failures with probability `rate`.
+pub fn install(rate: f64, seed: Option<u64>) {
+    let rand = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    *CHAOS.lock().unwrap_or_else(|e| e.into_inner()) = Some((rate, rand));
+}
+
+/// Whether to inject a failure now. Never, unless `install` was called.
+pub fn strike() -> bool {
+    match CHAOS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        Some((rate, rand)) => rand.gen_bool(rate.clamp(0.0, 1.0)),
+        None => false,
+    }
+}
+
+/// Fail with an injected error, if it is time to.
+pub fn inject(what: &str) -> Result<(), anyhow::Error> {
+    if strike() {
+        anyhow::bail!("chaos: injected {what}");
+    }
+    Ok(())
+}
+
+/// Wraps a storage to inject failures into its accesses.
+pub struct Chaotic(pub Box<dyn Storage>);
+
+impl Storage for Chaotic {
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.0.list(dir)
+    }
+
+    fn list_dirs(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.0.list_dirs(dir)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.0.exists(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        let mut data = self.0.read(path)?;
+        if strike() {
+            data.truncate(data.len() / 2);
+        }
+        Ok(data)
+    }
+
+    fn modified(&self, path: &Path) -> Result<DateTime<Local>, anyhow::Error> {
+        self.0.modified(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        if strike() {
+            self.0.write(path, &data[..data.len() / 2])?;
+            anyhow::bail!("chaos: injected partial write of {:?}", path);
+        }
+        self.0.write(path, data)
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        if strike() {
+            self.0.append(path, &data[..data.len() / 2])?;
+            anyhow::bail!("chaos: injected partial append to {:?}", path);
+        }
+        self.0.append(path, data)
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), anyhow::Error> {
+        self.0.remove(path)
+    }
+
+    fn lock(&self) -> Result<(), anyhow::Error> {
+        self.0.lock()
+    }
+
+    fn unlock(&self) -> Result<(), anyhow::Error> {
+        inject("failure to release the lock")?;
+        self.0.unlock()
+    }
+}
@@ -0,0 +1,143 @@
+//! Defaults for command-line options, from an `after8.toml` or `after8.json`
+//! in the data directory, so long command lines don't have to be repeated:
+//!
+//! ```toml
+//! max = "250"
+//! pem = "operator.pem"
+//! memo = "Monthly grants"
+//! url = "https://alberto.app/api"
+//! randomize = true
+//! ```
+//!
+//! Values are the defaults of the options of the same name, in every
+//! subcommand that has one. Options given on the command line, or through
+//! their environment variable, take precedence. As they are defaults, flags
+//! that are turned on in the configuration can't be turned off on the
+//! command line.
+//!
+//! `csv-id-column` and `csv-amount-column` aren't options, they name the
+//! columns of CSV input files (see the `csv` module).
+use crate::storage::Storage;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const CONFIG_TOML: &str = "after8.toml";
+pub const CONFIG_JSON: &str = "after8.json";
+
+/// The options that can be configured.
+pub const KEYS: &[&str] = &[
+    "max",
+    "min",
+    "total-max",
+    "pem",
+    "memo",
+    "memo-locale",
+    "memo-precision",
+    "url",
+    "token",
+    "randomize",
+    "randomize-range",
+    "randomize-dist",
+    "noise",
+    "preserve-total",
+    "recursive",
+    "sign",
+];
+
+/// The keys that configure how files are read, rather than options.
+pub const FILE_KEYS: &[&str] = &[crate::csv::ID_COLUMN_KEY, crate::csv::AMOUNT_COLUMN_KEY];
+
+/// The configured defaults, as they would be given on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    values: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Read the configuration of a directory, which is empty if it has none.
+    pub fn load(storage: &dyn Storage) -> Result<Self, anyhow::Error> {
+        let toml = Path::new(CONFIG_TOML);
+        let json = Path::new(CONFIG_JSON);
+        let (path, values) = match (storage.exists(toml), storage.exists(json)) {
+            (true, true) => {
+                anyhow::bail!("Both {CONFIG_TOML} and {CONFIG_JSON} exist, keep only one.")
+            }
+            (true, false) => (
+                toml,
+                crate::flat::toml(&storage.read_to_string(toml)?)
+                    .map_err(|e| anyhow::anyhow!("Invalid {:?}: {}", toml, e))?
+                    .into_iter()
+                    .map(|(_, key, value)| (key, value))
+                    .collect::<BTreeMap<_, _>>(),
+            ),
+            (false, true) => (
+                json,
+                serde_json::from_str(&storage.read_to_string(json)?).map_err(|e| {
+                    anyhow::anyhow!("Invalid {:?}, expected an object: {}", json, e)
+                })?,
+            ),
+            (false, false) => return Ok(Self::default()),
+        };
+
+        let mut config = Self::default();
+        for (key, value) in values {
+            if !KEYS.contains(&key.as_str()) && !FILE_KEYS.contains(&key.as_str()) {
+                anyhow::bail!(
+                    "Unknown option '{key}' in {:?}, expected one of: {}.",
+                    path,
+                    KEYS.iter()
+                        .chain(FILE_KEYS)
+                        .copied()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            let value = match value {
+                Value::String(s) => s,
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => anyhow::bail!(
+                    "Invalid value {value} for '{key}' in {:?}, expected a string, a number or a boolean.",
+                    path
+                ),
+            };
+            config.values.insert(key, value);
+        }
+        Ok(config)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The configured values, by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Make the configured values the defaults of the options of `command`
+    /// and its subcommands.
+    pub fn apply(&self, mut command: clap::Command) -> clap::Command {
+        for (key, value) in &self.values {
+            let id = key.replace('-', "_");
+            if command.get_arguments().any(|a| a.get_id() == id.as_str()) {
+                // The command is built once per process, the leak is bounded.
+                let value: &'static str = Box::leak(value.clone().into_boxed_str());
+                command = command.mut_arg(id, |arg| arg.default_value(value).required(false));
+            }
+        }
+        let names = command
+            .get_subcommands()
+            .map(|c| c.get_name().to_string())
+            .collect::<Vec<_>>();
+        for name in names {
+            command = command.mut_subcommand(name, |sub| self.apply(sub));
+        }
+        command
+    }
+}
@@ -0,0 +1,204 @@
+//! Allocations given in another token, converted when they are minted:
+//!
+//! ```json
+//! { "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f": { "amount": "100", "in": "USDC" } }
+//! ```
+//!
+//! The rate source is `rates.json`, the number of tokens one unit of each
+//! other token is worth, kept up to date from the feed of choice:
+//!
+//! ```json
+//! { "USDC": "0.25" }
+//! ```
+//!
+//! Until an entry is minted, it is converted at the current rate. The run
+//! that first mints it pins that rate in `conversions.json`, with the
+//! resulting amount, and logs them in `runs.log`. From then on the entry is
+//! converted at its pinned rate, so its value doesn't move with the market.
+use crate::error::ReadError;
+use crate::storage::Storage;
+use crate::{format_tokens, format_tokens_short, parse_tokens, DENOMINATOR, MAX_ENTRY};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const RATES_FILE: &str = "rates.json";
+pub const CONVERSIONS_FILE: &str = "conversions.json";
+
+/// The key of the token an entry is given in, as in
+/// `{"amount": "100", "in": "USDC"}`.
+pub const IN_KEY: &str = "in";
+
+/// An entry converted at the current rate, to be pinned when it is minted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    pub file: PathBuf,
+    pub id: String,
+    pub token: String,
+    /// The amount of the entry, in base units of `token`.
+    pub amount: i128,
+    /// The rate, in base units per token.
+    pub rate: i128,
+    /// The converted amount, in base units.
+    pub units: i128,
+}
+
+impl Conversion {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "file": self.file.display().to_string(),
+            "id": self.id,
+            "in": self.token,
+            "amount": format_tokens_short(self.amount),
+            "rate": format_tokens_short(self.rate),
+            "minted": format_tokens(self.units),
+        })
+    }
+}
+
+/// Split the token off an entry given as an object, leaving the rest of the
+/// entry.
+pub fn entry_token(
+    path: &Path,
+    name: &str,
+    value: Value,
+) -> Result<(Value, Option<String>), ReadError> {
+    let Value::Object(mut object) = value else {
+        return Ok((value, None));
+    };
+    match object.remove(IN_KEY) {
+        None => Ok((Value::Object(object), None)),
+        Some(Value::String(token)) => Ok((Value::Object(object), Some(token))),
+        Some(token) => {
+            object.insert(IN_KEY.to_string(), token);
+            Err(ReadError::InvalidType {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                value: Value::Object(object),
+            })
+        }
+    }
+}
+
+/// The rates entries are converted at.
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+    current: BTreeMap<String, i128>,
+    /// The pinned token and rate of entries, by file and id.
+    pinned: BTreeMap<String, BTreeMap<String, (String, i128)>>,
+}
+
+fn rate(path: &Path, token: &str, value: &Value) -> Result<i128, anyhow::Error> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => String::new(),
+    };
+    parse_tokens(&text)
+        .filter(|r| *r >= 0)
+        .ok_or_else(|| anyhow::anyhow!("{}: invalid rate {value} for {token}", path.display()))
+}
+
+fn read_object(storage: &dyn Storage, path: &Path) -> Result<Map<String, Value>, anyhow::Error> {
+    if !storage.exists(path) {
+        return Ok(Map::new());
+    }
+    serde_json::from_str(&storage.read_to_string(path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid {:?}: {}", path, e))
+}
+
+impl Rates {
+    pub fn load(storage: &dyn Storage) -> Result<Self, anyhow::Error> {
+        let path = Path::new(RATES_FILE);
+        let current = read_object(storage, path)?
+            .iter()
+            .map(|(token, value)| Ok((token.clone(), rate(path, token, value)?)))
+            .collect::<Result<_, anyhow::Error>>()?;
+
+        let path = Path::new(CONVERSIONS_FILE);
+        let mut pinned = BTreeMap::<String, BTreeMap<_, _>>::new();
+        for (file, entries) in read_object(storage, path)? {
+            for (id, pin) in entries.as_object().into_iter().flatten() {
+                let token = pin[IN_KEY].as_str().unwrap_or_default().to_string();
+                let rate = rate(path, &token, &pin["rate"])?;
+                pinned
+                    .entry(file.clone())
+                    .or_default()
+                    .insert(id.clone(), (token, rate));
+            }
+        }
+        Ok(Self { current, pinned })
+    }
+
+    /// Convert `amount` base units of `token` of the entry `name` of `path`.
+    /// Returns the converted amount, and the conversion to pin if it was at
+    /// the current rate.
+    pub fn convert(
+        &self,
+        path: &Path,
+        name: &str,
+        token: &str,
+        amount: i128,
+    ) -> Result<(i128, Option<Conversion>), ReadError> {
+        let pinned = self
+            .pinned
+            .get(&path.display().to_string())
+            .and_then(|pins| pins.get(name))
+            .filter(|(pinned, _)| pinned == token);
+        let rate = match pinned {
+            Some((_, rate)) => *rate,
+            None => *self.current.get(token).ok_or_else(|| ReadError::NoRate {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                token: token.to_string(),
+            })?,
+        };
+        let units = amount
+            .checked_mul(rate)
+            .map(|units| units / DENOMINATOR as i128)
+            .filter(|units| units.abs() <= MAX_ENTRY)
+            .ok_or_else(|| ReadError::AmountTooLarge {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                value: json!({ "amount": format_tokens_short(amount), IN_KEY: token }),
+            })?;
+        let conversion = pinned.is_none().then(|| Conversion {
+            file: path.to_path_buf(),
+            id: name.to_string(),
+            token: token.to_string(),
+            amount,
+            rate,
+            units,
+        });
+        Ok((units, conversion))
+    }
+}
+
+/// Pin the rates of the conversions minted by `run`.
+pub fn pin(
+    storage: &dyn Storage,
+    run: &Path,
+    conversions: &[&Conversion],
+) -> Result<(), anyhow::Error> {
+    if conversions.is_empty() {
+        return Ok(());
+    }
+    let path = Path::new(CONVERSIONS_FILE);
+    let mut pins = read_object(storage, path)?;
+    for conversion in conversions {
+        let mut pin = conversion.to_json();
+        if let Value::Object(pin) = &mut pin {
+            pin.remove("file");
+            pin.remove("id");
+            pin.insert("run".to_string(), json!(run.display().to_string()));
+        }
+        let file = pins
+            .entry(conversion.file.display().to_string())
+            .or_insert_with(|| json!({}));
+        file[&conversion.id] = pin;
+    }
+    storage.write(
+        path,
+        format!("{}\n", serde_json::to_string_pretty(&pins)?).as_bytes(),
+    )
+}
@@ -0,0 +1,125 @@
+//! CSV input files, as exported by spreadsheets. The first line names the
+//! columns, and each following line is an entry:
+//!
+//! ```csv
+//! id,amount,status
+//! maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f,"1,250.5",approved
+//! ```
+//!
+//! Column names are matched case-insensitively, other columns are ignored.
+//! The id and amount columns default to `id` and `amount`, which the
+//! `csv-id-column` and `csv-amount-column` keys of the configuration file
+//! change. The `status` column is optional. Fields may be quoted, with `""`
+//! for a quote, but each entry must be on a single line.
+use crate::config::Config;
+use crate::error::ReadError;
+use crate::storage::Storage;
+use crate::{AMOUNT_KEY, STATUS_KEY};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const EXTENSION: &str = "csv";
+
+/// The configuration keys naming the id and amount columns.
+pub const ID_COLUMN_KEY: &str = "csv-id-column";
+pub const AMOUNT_COLUMN_KEY: &str = "csv-amount-column";
+
+pub fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == EXTENSION)
+}
+
+/// Split a line into its fields.
+fn fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted field".to_string()),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err("unexpected text after a quoted field".to_string());
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                field.push(c);
+            }
+        }
+        fields.push(field.trim().to_string());
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// The entries of a CSV file, with their line numbers (1-based). Each entry
+/// is given as it would be in a JSON allocation file.
+pub fn entries(
+    storage: &dyn Storage,
+    path: &Path,
+    text: &str,
+) -> Result<Vec<(usize, String, Value)>, ReadError> {
+    let invalid = |line: usize, reason: String| ReadError::InvalidLine {
+        path: path.to_path_buf(),
+        line,
+        reason,
+    };
+    let config = Config::load(storage).map_err(|e| ReadError::Unreadable {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    })?;
+    let id_column = config.get(ID_COLUMN_KEY).unwrap_or("id");
+    let amount_column = config.get(AMOUNT_COLUMN_KEY).unwrap_or(AMOUNT_KEY);
+
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty());
+    let Some((header_line, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let header = fields(header).map_err(|e| invalid(header_line, e))?;
+    let column = |name: &str| header.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let id = column(id_column)
+        .ok_or_else(|| invalid(header_line, format!("no '{id_column}' column")))?;
+    let amount = column(amount_column)
+        .ok_or_else(|| invalid(header_line, format!("no '{amount_column}' column")))?;
+    let status = column(STATUS_KEY);
+
+    let mut entries = Vec::new();
+    let mut seen = BTreeMap::new();
+    for (line, text) in lines {
+        let row = fields(text).map_err(|e| invalid(line, e))?;
+        if row.len() != header.len() {
+            return Err(invalid(
+                line,
+                format!("{} field(s), expected {}", row.len(), header.len()),
+            ));
+        }
+        let key = row[id].clone();
+        if let Some(first) = seen.insert(key.clone(), line) {
+            return Err(invalid(line, format!("'{key}' is already on line {first}")));
+        }
+        let value = match status.map(|s| &row[s]).filter(|s| !s.is_empty()) {
+            Some(status) => Value::Object(Map::from_iter([
+                (AMOUNT_KEY.to_string(), Value::from(row[amount].as_str())),
+                (STATUS_KEY.to_string(), Value::from(status.as_str())),
+            ])),
+            None => Value::from(row[amount].as_str()),
+        };
+        entries.push((line, key, value));
+    }
+    Ok(entries)
+}
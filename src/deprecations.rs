@@ -0,0 +1,41 @@
+//! Deprecated spellings of flags and options. They keep working, but print a
+//! warning (once per invocation) with the replacement to use.
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+pub struct Deprecation {
+    /// The deprecated spelling.
+    pub old: &'static str,
+    /// What to use instead.
+    pub new: &'static str,
+    /// The version in which the old spelling was deprecated.
+    pub since: &'static str,
+}
+
+pub const DEPRECATIONS: &[Deprecation] = &[Deprecation {
+    old: "mint --json",
+    new: "mint --format json",
+    since: "0.1.0",
+}];
+
+static WARNED: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Warn that `old` is deprecated. Only warns the first time for each spelling.
+pub fn warn(old: &'static str) {
+    let Some(d) = DEPRECATIONS.iter().find(|d| d.old == old) else {
+        return;
+    };
+    if WARNED.lock().unwrap().insert(d.old) {
+        eprintln!(
+            "warning: `{}` is deprecated since {}, use `{}` instead.",
+            d.old, d.since, d.new
+        );
+    }
+}
+
+/// Print all deprecated spellings and their replacements.
+pub fn list() {
+    for d in DEPRECATIONS {
+        println!("{}\t-> {}\t(since {})", d.old, d.new, d.since);
+    }
+}
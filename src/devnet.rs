@@ -0,0 +1,76 @@
+//! `mint --devnet`, to exercise the whole pipeline against a ledger running
+//! locally: it submits to `http://localhost:8000`, mints a throwaway token
+//! created on first use, and doesn't stop for blackout windows or the first
+//! run confirmation. It is meant for scratch directories: the runs are
+//! recorded like any other.
+//!
+//! The token is created with `ledger <url> token create` and remembered in
+//! `devnet.token`, so later runs mint the same one.
+use crate::identity::Identity;
+use crate::storage::Storage;
+use std::path::Path;
+use std::process::Command;
+
+/// The endpoint of a ledger running locally.
+pub const DEVNET_URL: &str = "http://localhost:8000";
+
+/// The file holding the address of the throwaway token.
+pub const TOKEN_FILE: &str = "devnet.token";
+
+/// The name, ticker and decimals of the throwaway token.
+const TOKEN_ARGS: [&str; 3] = ["many-after8 devnet", "DEV", "9"];
+
+/// The address of the devnet token, creating it with `binary` if there is
+/// none yet.
+pub fn token(
+    storage: &dyn Storage,
+    binary: &Path,
+    pem: &Path,
+    read_only: bool,
+) -> Result<String, anyhow::Error> {
+    let path = Path::new(TOKEN_FILE);
+    if storage.exists(path) {
+        let token = storage.read_to_string(path)?;
+        return token
+            .trim()
+            .parse::<Identity>()
+            .map(|id| id.to_string())
+            .map_err(|e| anyhow::anyhow!("Invalid token in {:?}: {}", path, e));
+    }
+    crate::ensure_writable(read_only, "create a devnet token")?;
+
+    eprintln!("Creating a devnet token on {DEVNET_URL}...");
+    let output = Command::new(binary)
+        .arg("--pem")
+        .arg(pem)
+        .args([DEVNET_URL, "token", "create"])
+        .args(TOKEN_ARGS)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Could not run {:?}: {}", binary, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{:?} failed ({}), no devnet token was created: {}",
+            binary,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // The address of the new token is the first identity of the output.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let token = stdout
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| word.starts_with('m'))
+        .find_map(|word| word.parse::<Identity>().ok())
+        .filter(Identity::is_addressable)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{:?} didn't print the address of the token: {}",
+                binary,
+                stdout.trim()
+            )
+        })?
+        .to_string();
+    storage.write(path, format!("{token}\n").as_bytes())?;
+    eprintln!("Created {token}, saved in {TOKEN_FILE}.");
+    Ok(token)
+}
@@ -0,0 +1,114 @@
+//! Disabling an allocation entry without deleting it. `disable` flags the
+//! entry with `"disabled": true`, so it is kept in its file, amount, status
+//! and history included, but isn't counted. `enable` removes the flag.
+//!
+//! Only JSON allocation files are rewritten, keeping the order of their
+//! entries and their comments (see the `managed` module). Mint files and
+//! the corrections of `restate` record what was minted and can't be
+//! disabled.
+use crate::managed::Entries;
+use crate::restate;
+use crate::storage::Storage;
+use crate::{aliases, ensure_writable, is_mint_file, periods, state, AMOUNT_KEY, DISABLED_KEY};
+use clap::Parser;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub struct DisableOpt {
+    /// The identity (or alias) of the entry.
+    identity: String,
+
+    /// The allocation file of the entry, relative to the directory.
+    #[arg(long = "in", value_name = "FILE")]
+    file: PathBuf,
+}
+
+pub fn disable(
+    storage: &dyn Storage,
+    opts: DisableOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    set_disabled(storage, opts, read_only, true)
+}
+
+pub fn enable(
+    storage: &dyn Storage,
+    opts: DisableOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    set_disabled(storage, opts, read_only, false)
+}
+
+fn set_disabled(
+    storage: &dyn Storage,
+    opts: DisableOpt,
+    read_only: bool,
+    disabled: bool,
+) -> Result<(), anyhow::Error> {
+    let verb = if disabled { "disable" } else { "enable" };
+    ensure_writable(read_only, &format!("{verb} entries"))?;
+
+    let loaded = state::current(storage)?;
+    let path = opts.file;
+    if !storage.exists(&path) {
+        anyhow::bail!("No such file: {:?}", path);
+    }
+    if is_mint_file(&path) {
+        anyhow::bail!(
+            "{:?} is a mint file, only allocation entries can be {verb}d.",
+            path
+        );
+    }
+    if restate::is_restatement(&path) {
+        anyhow::bail!(
+            "{:?} restates a run, only allocation entries can be {verb}d.",
+            path
+        );
+    }
+    if path.extension().is_some_and(|ext| ext != "json") {
+        anyhow::bail!(
+            "{:?} isn't JSON and isn't rewritten, edit it by hand.",
+            path
+        );
+    }
+    if let Some(period) = periods::frozen_in(storage, &path)? {
+        anyhow::bail!(
+            "{:?} is frozen in closed period {}. Reopen it first.",
+            path,
+            period
+        );
+    }
+
+    let mut entries = Entries::read(storage, &path)?;
+    // The entry may be written as an alias, hex or a DID.
+    let aliases = aliases::load(storage)?;
+    let id = aliases::resolve(&aliases, &opts.identity);
+    let (key, entry) = entries
+        .iter_mut()
+        .find(|(key, _)| aliases::resolve(&aliases, key) == id)
+        .ok_or_else(|| anyhow::anyhow!("{:?} has no entry for {}.", path, opts.identity))?;
+    let key = key.clone();
+
+    let mut object = match std::mem::take(entry) {
+        Value::Object(object) => object,
+        amount => Map::from_iter([(AMOUNT_KEY.to_string(), amount)]),
+    };
+    let was_disabled = object.remove(DISABLED_KEY) == Some(Value::Bool(true));
+    if was_disabled == disabled {
+        anyhow::bail!("{} is already {verb}d in {:?}.", key, path);
+    }
+    *entry = if disabled {
+        object.insert(DISABLED_KEY.to_string(), Value::Bool(true));
+        Value::Object(object)
+    } else if object.len() == 1 && object.contains_key(AMOUNT_KEY) {
+        object.remove(AMOUNT_KEY).unwrap_or_default()
+    } else {
+        Value::Object(object)
+    };
+
+    state::commit(storage, loaded)?;
+    entries.write(storage, &path)?;
+    eprintln!("{}: {} {verb}d.", path.display(), key);
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Errors reading allocation files. They name the file, and the key and value
+//! when there is one, so a bad entry can be found and fixed directly.
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ReadError {
+    /// The file couldn't be read.
+    Unreadable { path: PathBuf, reason: String },
+    /// The file isn't a JSON object.
+    InvalidJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A line of a CSV, YAML or TOML file can't be read.
+    InvalidLine {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    /// The key of an entry isn't a MANY identity that can hold tokens.
+    InvalidId {
+        path: PathBuf,
+        key: String,
+        reason: String,
+    },
+    /// The same identity is given twice in a file, in different forms.
+    DuplicateId {
+        path: PathBuf,
+        key: String,
+        first: String,
+        second: String,
+    },
+    /// The `effective` date isn't a `YYYY-MM-DD` string.
+    InvalidDate { path: PathBuf, value: Value },
+    /// The value of an entry is neither a number, a string nor an object
+    /// with an amount.
+    InvalidType {
+        path: PathBuf,
+        key: String,
+        value: Value,
+    },
+    /// The status of an entry isn't one of the review states.
+    InvalidStatus {
+        path: PathBuf,
+        key: String,
+        value: Value,
+    },
+    /// The disabled flag of an entry isn't a boolean.
+    InvalidDisabled {
+        path: PathBuf,
+        key: String,
+        value: Value,
+    },
+    /// The value of an entry isn't an amount of tokens.
+    InvalidAmount {
+        path: PathBuf,
+        key: String,
+        value: Value,
+    },
+    /// The value of an entry is too large to be right, most likely because a
+    /// decimal point is missing.
+    AmountTooLarge {
+        path: PathBuf,
+        key: String,
+        value: Value,
+    },
+    /// The entry is given in a token `rates.json` has no rate for.
+    NoRate {
+        path: PathBuf,
+        key: String,
+        token: String,
+    },
+    /// The balance of an identity, across all files, doesn't fit in base
+    /// units.
+    BalanceTooLarge { key: String, balance: i128 },
+}
+
+impl ReadError {
+    /// The file the error is in, if it is in a single file.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Unreadable { path, .. }
+            | Self::InvalidJson { path, .. }
+            | Self::InvalidLine { path, .. }
+            | Self::InvalidId { path, .. }
+            | Self::DuplicateId { path, .. }
+            | Self::InvalidDate { path, .. }
+            | Self::InvalidType { path, .. }
+            | Self::InvalidStatus { path, .. }
+            | Self::InvalidDisabled { path, .. }
+            | Self::InvalidAmount { path, .. }
+            | Self::AmountTooLarge { path, .. }
+            | Self::NoRate { path, .. } => Some(path),
+            Self::BalanceTooLarge { .. } => None,
+        }
+    }
+
+    /// The key of the entry the error is about, if there is one.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Self::InvalidDate { .. } => Some(crate::EFFECTIVE_KEY),
+            Self::InvalidId { key, .. }
+            | Self::DuplicateId { key, .. }
+            | Self::InvalidType { key, .. }
+            | Self::InvalidStatus { key, .. }
+            | Self::InvalidDisabled { key, .. }
+            | Self::InvalidAmount { key, .. }
+            | Self::AmountTooLarge { key, .. }
+            | Self::NoRate { key, .. }
+            | Self::BalanceTooLarge { key, .. } => Some(key),
+            Self::Unreadable { .. } | Self::InvalidJson { .. } | Self::InvalidLine { .. } => None,
+        }
+    }
+
+    /// The error, without the path of the file.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Unreadable { reason, .. } => format!("could not read the file: {reason}"),
+            Self::InvalidJson { source, .. } => format!("not a valid JSON object: {source}"),
+            Self::InvalidLine { line, reason, .. } => format!("line {line}: {reason}"),
+            Self::InvalidId { key, reason, .. } => format!(
+                "'{key}' is not a valid identity ({reason}), tokens minted to it would be lost. Use --allow-unknown-ids to read it anyway"
+            ),
+            Self::DuplicateId {
+                key, first, second, ..
+            } => format!("'{key}' is given twice, as '{first}' and as '{second}'"),
+            Self::InvalidDate { value, .. } => format!(
+                "'{}': invalid date {value}, expected \"YYYY-MM-DD\"",
+                crate::EFFECTIVE_KEY
+            ),
+            Self::InvalidType { key, value, .. } => format!(
+                "'{key}': invalid value {value}, expected a number, a string, {{\"{}\": ...}} or {{\"{}\": ...}}",
+                crate::AMOUNT_KEY,
+                crate::MINOR_KEY
+            ),
+            Self::InvalidStatus { key, value, .. } => format!(
+                "'{key}': invalid status {value}, expected \"draft\", \"approved\" or \"paused\""
+            ),
+            Self::InvalidDisabled { key, value, .. } => format!(
+                "'{key}': invalid \"{}\" flag {value}, expected true or false",
+                crate::DISABLED_KEY
+            ),
+            Self::InvalidAmount { key, value, .. } => format!(
+                "'{key}': invalid amount {value}, expected a number of tokens such as \"12.5\""
+            ),
+            Self::AmountTooLarge { key, value, .. } => {
+                format!("'{key}': amount {value} is too large, is a decimal point missing?")
+            }
+            Self::NoRate { key, token, .. } => format!(
+                "'{key}': no rate for {token} in {}",
+                crate::convert::RATES_FILE
+            ),
+            Self::BalanceTooLarge { key, balance } => format!(
+                "'{key}': balance of {} tokens across all files is too large",
+                crate::format_tokens(*balance)
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message()),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidJson { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
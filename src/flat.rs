@@ -0,0 +1,263 @@
+//! Readers for the subsets of YAML and TOML that allocation files and the
+//! configuration use, for teams that prefer formats with comments:
+//!
+//! ```yaml
+//! effective: 2024-05-01
+//! maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f: 12.5  # Q2 grant
+//! magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e:
+//!   amount: 20
+//!   status: draft
+//! ```
+//!
+//! ```toml
+//! effective = "2024-05-01"
+//! maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f = 12.5  # Q2 grant
+//! magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e = { amount = 20, status = "draft" }
+//! ```
+//!
+//! That is one entry per line, with a scalar value or a single level of
+//! nesting, and `#` comments. Unquoted scalars other than `true` and `false`
+//! are kept as text, so amounts are read exactly. Lists, multi-line strings
+//! and TOML tables aren't supported.
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// The entries of a file, with their line numbers (1-based).
+pub type Entries = Vec<(usize, String, Value)>;
+
+/// A line that can't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// Remove a trailing comment and surrounding quotes from a scalar.
+pub(crate) fn scalar(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.split_once(quote))
+            .map(|(inner, _)| inner)
+        {
+            return inner.to_string();
+        }
+    }
+    match value.find(" #") {
+        Some(i) => value[..i].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// A YAML scalar as a value: booleans, or text.
+fn yaml_value(value: &str) -> Value {
+    let quoted = value.trim_start().starts_with(['"', '\'']);
+    match scalar(value).as_str() {
+        "true" if !quoted => Value::Bool(true),
+        "false" if !quoted => Value::Bool(false),
+        text => Value::from(text),
+    }
+}
+
+/// Split `key: value`, with an optionally quoted key.
+fn yaml_entry(line: &str) -> Option<(String, &str)> {
+    let (key, rest) = match line.strip_prefix(['"', '\'']) {
+        Some(quoted) => {
+            let quote = line.chars().next()?;
+            let (key, rest) = quoted.split_once(quote)?;
+            (key.to_string(), rest.trim_start().strip_prefix(':')?)
+        }
+        None => {
+            let (key, rest) = line.split_once(':')?;
+            (key.trim().to_string(), rest)
+        }
+    };
+    Some((key, rest))
+}
+
+/// Add an entry, refusing duplicate keys.
+fn insert(
+    entries: &mut Entries,
+    seen: &mut BTreeMap<String, usize>,
+    line: usize,
+    key: String,
+    value: Value,
+) -> Result<(), ParseError> {
+    if let Some(first) = seen.insert(key.clone(), line) {
+        return Err(ParseError {
+            line,
+            reason: format!("'{key}' is already on line {first}"),
+        });
+    }
+    entries.push((line, key, value));
+    Ok(())
+}
+
+/// Read a YAML mapping of scalars, or of mappings of scalars.
+pub fn yaml(content: &str) -> Result<Entries, ParseError> {
+    let mut entries = Entries::new();
+    let mut seen = BTreeMap::new();
+    // The key whose nested mapping is being read, with its line.
+    let mut nested: Option<(usize, String, Map<String, Value>)> = None;
+    for (i, raw) in content.lines().enumerate() {
+        let line = i + 1;
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+            continue;
+        }
+        let error = |reason: &str| ParseError {
+            line,
+            reason: reason.to_string(),
+        };
+        if trimmed.starts_with("- ") || trimmed == "-" {
+            return Err(error("lists aren't supported"));
+        }
+        let (key, value) = yaml_entry(trimmed).ok_or_else(|| error("expected `key: value`"))?;
+
+        if raw.starts_with([' ', '\t']) {
+            let Some((_, _, map)) = nested.as_mut() else {
+                return Err(error("unexpected indentation"));
+            };
+            if scalar(value).is_empty() {
+                return Err(error("only one level of nesting is supported"));
+            }
+            map.insert(key, yaml_value(value));
+            continue;
+        }
+
+        if let Some((line, key, map)) = nested.take() {
+            if map.is_empty() {
+                return Err(ParseError {
+                    line,
+                    reason: format!("'{key}' has no value"),
+                });
+            }
+            insert(&mut entries, &mut seen, line, key, Value::Object(map))?;
+        }
+        if scalar(value).is_empty() && !value.trim_start().starts_with(['"', '\'']) {
+            nested = Some((line, key, Map::new()));
+        } else {
+            insert(&mut entries, &mut seen, line, key, yaml_value(value))?;
+        }
+    }
+    if let Some((line, key, map)) = nested {
+        if map.is_empty() {
+            return Err(ParseError {
+                line,
+                reason: format!("'{key}' has no value"),
+            });
+        }
+        insert(&mut entries, &mut seen, line, key, Value::Object(map))?;
+    }
+    Ok(entries)
+}
+
+/// Read a TOML key, bare or quoted, returning it and the rest of the input.
+fn toml_key(input: &str) -> Option<(String, &str)> {
+    let input = input.trim_start();
+    if let Some(quoted) = input.strip_prefix('"') {
+        let (key, rest) = quoted.split_once('"')?;
+        return Some((key.to_string(), rest));
+    }
+    let end = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(input.len());
+    (end > 0).then(|| (input[..end].to_string(), &input[end..]))
+}
+
+/// Read a TOML value, returning it and the rest of the input. Inline tables
+/// are only read when `nested` is allowed.
+fn toml_value(input: &str, nested: bool) -> Result<(Value, &str), String> {
+    let input = input.trim_start();
+    if let Some(quoted) = input.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::from(string), &quoted[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some(c @ ('"' | '\\')) => string.push(c),
+                    _ => return Err("unsupported escape".to_string()),
+                },
+                c => string.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    if let Some(literal) = input.strip_prefix('\'') {
+        let (string, rest) = literal.split_once('\'').ok_or("unterminated string")?;
+        return Ok((Value::from(string), rest));
+    }
+    if let Some(mut rest) = input.strip_prefix('{') {
+        if !nested {
+            return Err("only one level of nesting is supported".to_string());
+        }
+        let mut table = Map::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix('}') {
+                return Ok((Value::Object(table), after));
+            }
+            let (key, after) = toml_key(rest).ok_or("expected a key")?;
+            let after = after
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or("expected `key = value`")?;
+            let (value, after) = toml_value(after, false)?;
+            table.insert(key, value);
+            let after = after.trim_start();
+            rest = after.strip_prefix(',').unwrap_or(after);
+            if !after.starts_with([',', '}']) {
+                return Err("expected `,` or `}` in inline table".to_string());
+            }
+        }
+    }
+    let end = input.find([',', '}', '#']).unwrap_or(input.len());
+    let value = match input[..end].trim() {
+        "" => return Err("expected a value".to_string()),
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        text => Value::from(text),
+    };
+    Ok((value, &input[end..]))
+}
+
+/// Read `key = value` lines of TOML, where values are scalars or inline
+/// tables of scalars.
+pub fn toml(content: &str) -> Result<Entries, ParseError> {
+    let mut entries = Entries::new();
+    let mut seen = BTreeMap::new();
+    for (i, raw) in content.lines().enumerate() {
+        let line = i + 1;
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let error = |reason: String| ParseError { line, reason };
+        if trimmed.starts_with('[') {
+            return Err(error("tables aren't supported".to_string()));
+        }
+        let (key, rest) =
+            toml_key(trimmed).ok_or_else(|| error("expected `key = value`".to_string()))?;
+        let rest = rest
+            .trim_start()
+            .strip_prefix('=')
+            .ok_or_else(|| error("expected `key = value`".to_string()))?;
+        let (value, rest) = toml_value(rest, true).map_err(error)?;
+        let rest = rest.trim();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(error(format!("unexpected '{rest}' after the value")));
+        }
+        insert(&mut entries, &mut seen, line, key, value)?;
+    }
+    Ok(entries)
+}
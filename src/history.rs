@@ -0,0 +1,237 @@
+//! The history of mint runs. Each run written by this tool also appends a
+//! summary to `runs.log`, with the context it ran in: the binary version, the
+//! git commit of the data directory if it is a repository, the host and the
+//! OS. This helps tell which machine produced a given run.
+use crate::amounts::AmountFormat;
+use crate::convert::Conversion;
+use crate::restate;
+use crate::storage::Storage;
+use crate::{input_files, is_mint_file, run_date, session};
+use chrono::NaiveDate;
+use clap::Parser;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+pub const RUNS_LOG: &str = "runs.log";
+
+#[derive(Debug, Parser)]
+pub struct HistoryOpt {
+    /// Show the context each run was produced in.
+    #[arg(long)]
+    show_context: bool,
+
+    /// Also show the session each run was produced by, from `sessions.log`:
+    /// the command, the host and the version.
+    #[arg(long)]
+    verbose: bool,
+
+    /// Also list the amount minted to each identity in each run.
+    #[arg(long)]
+    per_id: bool,
+
+    /// Only list runs on or after this date (YYYY-MM-DD).
+    #[arg(long)]
+    since: Option<NaiveDate>,
+
+    /// Only list runs on or before this date (YYYY-MM-DD).
+    #[arg(long)]
+    until: Option<NaiveDate>,
+
+    /// Only list runs that minted to this identity, and only count its
+    /// amounts. Can be repeated.
+    #[arg(long = "id")]
+    ids: Vec<String>,
+
+    #[command(flatten)]
+    amounts: AmountFormat,
+}
+
+/// The git commit checked out in the data directory, if it is the root of a
+/// git repository.
+fn data_commit(storage: &dyn Storage) -> Option<String> {
+    let head = storage.read_to_string(Path::new(".git/HEAD")).ok()?;
+    let head = head.trim();
+    let Some(reference) = head.strip_prefix("ref:").map(str::trim) else {
+        // A detached HEAD holds the commit itself.
+        return Some(head.to_string());
+    };
+    if let Ok(commit) = storage.read_to_string(&Path::new(".git").join(reference)) {
+        return Some(commit.trim().to_string());
+    }
+    // The reference may only be in packed-refs, as "<commit> <reference>".
+    storage
+        .read_to_string(Path::new(".git/packed-refs"))
+        .ok()?
+        .lines()
+        .find_map(|line| match line.split_once(' ') {
+            Some((commit, name)) if name == reference => Some(commit.to_string()),
+            _ => None,
+        })
+}
+
+/// The environment of the current run.
+pub fn context(storage: &dyn Storage) -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "data_commit": data_commit(storage),
+        "host": session::hostname(),
+        "os": format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+    })
+}
+
+/// Append the summary of a run to the log. `amounts` are the minted amounts,
+/// in base units, `seed` the seed they were randomized with, if they were,
+/// and `conversions` the entries in other tokens the run pinned the rate of.
+pub fn record(
+    storage: &dyn Storage,
+    run: &Path,
+    amounts: &BTreeMap<String, u64>,
+    seed: Option<u64>,
+    conversions: &[&Conversion],
+) -> Result<(), anyhow::Error> {
+    let mut entry = json!({
+        "run": run.display().to_string(),
+        "date": chrono::Local::now().to_rfc3339(),
+        "recipients": amounts.len(),
+        "total": amounts.values().sum::<u64>(),
+        "context": context(storage),
+    });
+    if let Some(seed) = seed {
+        entry["seed"] = json!(seed);
+    }
+    if !conversions.is_empty() {
+        entry["conversions"] = conversions.iter().map(|c| c.to_json()).collect();
+    }
+    storage.append(Path::new(RUNS_LOG), format!("{entry}\n").as_bytes())
+}
+
+/// The recorded entry of each run, by mint file. Runs recorded more than
+/// once keep their latest entry.
+fn entries(storage: &dyn Storage) -> Result<BTreeMap<String, Value>, anyhow::Error> {
+    let path = Path::new(RUNS_LOG);
+    if !storage.exists(path) {
+        return Ok(BTreeMap::new());
+    }
+    let mut contexts = BTreeMap::new();
+    for (i, line) in storage.read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Value>(line) {
+            Ok(entry) => {
+                if let Some(run) = entry["run"].as_str() {
+                    contexts.insert(run.to_string(), entry);
+                }
+            }
+            Err(e) => eprintln!("warning: {RUNS_LOG}:{}: invalid entry: {e}", i + 1),
+        }
+    }
+    Ok(contexts)
+}
+
+fn describe(context: &Value) -> String {
+    let field = |name: &str| context[name].as_str().unwrap_or("unknown").to_string();
+    format!(
+        "version {}, data commit {}, host {}, os {}",
+        field("version"),
+        context["data_commit"].as_str().unwrap_or("none"),
+        field("host"),
+        field("os"),
+    )
+}
+
+/// List the mint runs in the directory, followed by a summary of them.
+pub fn history(storage: &dyn Storage, opts: HistoryOpt) -> Result<(), anyhow::Error> {
+    let entries = if opts.show_context || opts.verbose {
+        entries(storage)?
+    } else {
+        BTreeMap::new()
+    };
+    let sessions = if opts.verbose {
+        session::load(storage)?
+    } else {
+        Vec::new()
+    };
+    let tokens = |amount| opts.amounts.format(amount);
+    let restatements = restate::load(storage)?;
+
+    let (mut runs, mut minted, mut recipients) = (0, 0, BTreeSet::new());
+    for path in input_files(storage)?
+        .into_iter()
+        .filter(|p| is_mint_file(p))
+    {
+        let date = run_date(storage, &path)?;
+        if opts.since.is_some_and(|since| date.date_naive() < since)
+            || opts.until.is_some_and(|until| date.date_naive() > until)
+        {
+            continue;
+        }
+        // Mint files hold the negative of what was minted.
+        let amounts = restate::run_amounts(storage, &path, &restatements)?
+            .into_iter()
+            .filter(|(id, _)| opts.ids.is_empty() || opts.ids.contains(id))
+            .map(|(id, amount)| (id, -amount))
+            .collect::<BTreeMap<_, _>>();
+        if amounts.is_empty() && !opts.ids.is_empty() {
+            continue;
+        }
+
+        let total = amounts.values().sum::<i128>();
+        println!(
+            "{}: {}, {} recipient(s), {} minted",
+            path.display(),
+            date.format("%Y-%m-%d %H:%M:%S"),
+            amounts.len(),
+            tokens(total)
+        );
+        let entry = entries.get(&path.display().to_string());
+        if opts.show_context {
+            match entry {
+                Some(entry) => println!("  {}", describe(&entry["context"])),
+                None => println!("  (no context recorded)"),
+            }
+        }
+        if opts.verbose {
+            // Runs are logged when they are written, else dated by their
+            // mint file.
+            let recorded = entry
+                .and_then(|entry| entry["date"].as_str())
+                .and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok())
+                .unwrap_or_else(|| date.into());
+            match session::at(&sessions, recorded) {
+                Some(session) => println!("  session: {session}"),
+                None => println!("  (no session recorded)"),
+            }
+        }
+        for restatement in restatements
+            .get(&path.display().to_string())
+            .into_iter()
+            .flatten()
+        {
+            match &restatement.reason {
+                Some(reason) => {
+                    println!("  restated by {}: {}", restatement.file.display(), reason)
+                }
+                None => println!("  restated by {}", restatement.file.display()),
+            }
+        }
+        if opts.per_id {
+            for (id, amount) in &amounts {
+                println!("  {}: {}", id, tokens(*amount));
+            }
+        }
+
+        runs += 1;
+        minted += total;
+        recipients.extend(amounts.into_keys());
+    }
+
+    println!(
+        "Total: {} run(s), {} minted to {} recipient(s)",
+        runs,
+        tokens(minted),
+        recipients.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,214 @@
+//! Parsing of MANY identities in their textual form: an `m` prefix, the
+//! lowercase base32 (RFC4648, no padding) encoding of the identity bytes, and
+//! two characters of base32-encoded CRC-16 checksum.
+//!
+//! Other systems export identities differently, so they are also parsed from
+//! the hex encoding of their bytes (with or without `0x`), and as DIDs
+//! (`did:many:` followed by either form). They are always displayed in the
+//! textual form.
+use std::fmt;
+
+const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// The prefix of identities given as DIDs.
+pub const DID_PREFIX: &str = "did:many:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityKind {
+    Anonymous,
+    PublicKey,
+    Subresource(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentityError {
+    MissingPrefix,
+    TooShort,
+    InvalidCharacter(char),
+    InvalidLength(usize),
+    InvalidChecksum,
+    UnknownTag(u8),
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentityError::MissingPrefix => write!(
+                f,
+                "identity must start with 'm', or be hex or a '{DID_PREFIX}' DID"
+            ),
+            IdentityError::TooShort => write!(f, "identity is too short"),
+            IdentityError::InvalidCharacter(c) => write!(f, "invalid base32 character '{c}'"),
+            IdentityError::InvalidLength(l) => write!(f, "invalid identity length ({l} bytes)"),
+            IdentityError::InvalidChecksum => write!(f, "checksum mismatch"),
+            IdentityError::UnknownTag(t) => write!(f, "unknown identity tag 0x{t:02x}"),
+        }
+    }
+}
+
+impl std::error::Error for IdentityError {}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for b in data {
+        buffer = (buffer << 8) | *b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(s: &str) -> Result<Vec<u8>, IdentityError> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.chars() {
+        let value = ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_lowercase())
+            .ok_or(IdentityError::InvalidCharacter(c))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// Decode hex, if `s` is only hex digits, of which there is an even number.
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if s.is_empty() || !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// CRC-16/ARC, the checksum used by MANY textual identities.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, b| {
+        crc ^= *b as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+        crc
+    })
+}
+
+fn checksum(data: &[u8]) -> String {
+    base32_encode(&crc16(data).to_be_bytes())[..2].to_string()
+}
+
+impl Identity {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, IdentityError> {
+        match (bytes.first(), bytes.len()) {
+            (Some(0), 1) | (Some(1), 29) => {}
+            (Some(t), 32) if *t >= 0x80 => {}
+            (Some(t), _) if *t == 0 || *t == 1 || *t >= 0x80 => {
+                return Err(IdentityError::InvalidLength(bytes.len()))
+            }
+            (Some(t), _) => return Err(IdentityError::UnknownTag(*t)),
+            (None, _) => return Err(IdentityError::TooShort),
+        }
+        Ok(Self { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn kind(&self) -> IdentityKind {
+        match self.bytes[0] {
+            0 => IdentityKind::Anonymous,
+            1 => IdentityKind::PublicKey,
+            t => {
+                let b = &self.bytes[29..32];
+                IdentityKind::Subresource(
+                    ((t & 0x7f) as u32) << 24
+                        | (b[0] as u32) << 16
+                        | (b[1] as u32) << 8
+                        | b[2] as u32,
+                )
+            }
+        }
+    }
+
+    /// Whether tokens sent to this identity can ever be spent.
+    pub fn is_addressable(&self) -> bool {
+        self.kind() != IdentityKind::Anonymous
+    }
+}
+
+impl std::str::FromStr for Identity {
+    type Err = IdentityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix(DID_PREFIX).unwrap_or(s);
+        let hex = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+        if let Some(bytes) = hex_decode(hex) {
+            return Self::from_bytes(bytes);
+        }
+        let rest = s.strip_prefix('m').ok_or(IdentityError::MissingPrefix)?;
+        if rest == "aa" {
+            return Self::from_bytes(vec![0]);
+        }
+        if rest.len() < 3 || !rest.is_char_boundary(rest.len() - 2) {
+            return Err(IdentityError::TooShort);
+        }
+
+        let (data, crc) = rest.split_at(rest.len() - 2);
+        let bytes = base32_decode(data)?;
+        base32_decode(crc)?;
+        if checksum(&bytes) != crc.to_ascii_lowercase() {
+            return Err(IdentityError::InvalidChecksum);
+        }
+        Self::from_bytes(bytes)
+    }
+}
+
+/// The textual form of `id` if it is an identity in any form, or `id` as it
+/// is otherwise.
+pub fn normalize(id: &str) -> String {
+    match id.parse::<Identity>() {
+        Ok(identity) => identity.to_string(),
+        Err(_) => id.to_string(),
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.kind() == IdentityKind::Anonymous {
+            return write!(f, "maa");
+        }
+        write!(
+            f,
+            "m{}{}",
+            base32_encode(&self.bytes),
+            checksum(&self.bytes)
+        )
+    }
+}
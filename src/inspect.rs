@@ -0,0 +1,110 @@
+use crate::storage::Storage;
+use crate::{format_tokens, input_files, is_mint_file, read_allocation, run_date};
+use chrono::{DateTime, Local};
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub struct InspectOpt {
+    /// Only show allocation files added or modified since the last mint run,
+    /// and their net effect on each identity's remaining balance.
+    #[arg(long)]
+    since_last_run: bool,
+}
+
+fn format_delta(tokens: i128) -> String {
+    if tokens < 0 {
+        format_tokens(tokens)
+    } else {
+        format!("+{}", format_tokens(tokens))
+    }
+}
+
+fn format_remaining(tokens: i128) -> String {
+    format_tokens(tokens.max(0))
+}
+
+pub fn inspect(storage: &dyn Storage, opts: InspectOpt) -> Result<(), anyhow::Error> {
+    let files = input_files(storage)?;
+
+    let mut last_run: Option<(DateTime<Local>, &PathBuf)> = None;
+    for path in files.iter().filter(|p| is_mint_file(p)) {
+        let date = run_date(storage, path)?;
+        if last_run.is_none_or(|(d, _)| date > d) {
+            last_run = Some((date, path));
+        }
+    }
+
+    let since = match (opts.since_last_run, last_run) {
+        (true, Some((date, path))) => {
+            println!("Last mint run: {} ({})", date.to_rfc2822(), path.display());
+            Some(date)
+        }
+        (true, None) => {
+            println!("No mint run found, showing all allocation files.");
+            None
+        }
+        (false, _) => None,
+    };
+
+    let mut totals = BTreeMap::<String, i128>::new();
+    let mut deltas = BTreeMap::<String, i128>::new();
+
+    println!("Files:");
+    let now = Local::now();
+    for path in &files {
+        let file = read_allocation(storage, path)?;
+        let pending = file.effective.filter(|_| !file.is_effective(now));
+        let amounts = file.amounts;
+        if let Some(effective) = pending {
+            println!(
+                "  {}	takes effect on {}	{} ids	{} (not counted yet)",
+                path.display(),
+                effective,
+                amounts.len(),
+                format_delta(amounts.values().sum()),
+            );
+            continue;
+        }
+        for (id, tokens) in &amounts {
+            *totals.entry(id.clone()).or_default() += tokens;
+        }
+
+        if is_mint_file(path) {
+            continue;
+        }
+        let mtime = storage.modified(path)?;
+        if since.is_some_and(|since| mtime <= since) {
+            continue;
+        }
+
+        for (id, tokens) in &amounts {
+            *deltas.entry(id.clone()).or_default() += tokens;
+        }
+        println!(
+            "  {}\tmodified {}\t{} ids\t{}",
+            path.display(),
+            mtime.format("%Y-%m-%d %H:%M:%S"),
+            amounts.len(),
+            format_delta(amounts.values().sum()),
+        );
+    }
+
+    if opts.since_last_run {
+        println!();
+        println!("Net effect on remaining balances:");
+        for (id, delta) in deltas.iter().filter(|(_, d)| **d != 0) {
+            let after = totals.get(id).copied().unwrap_or(0);
+            println!(
+                "  {}\t{}\t{} -> {}",
+                id,
+                format_delta(*delta),
+                format_remaining(after - delta),
+                format_remaining(after),
+            );
+        }
+    }
+
+    Ok(())
+}
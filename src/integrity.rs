@@ -0,0 +1,235 @@
+//! Checksums of the inputs of each run, to tell whether history was rewritten.
+//! Each run written by this tool records in `manifest.json` the SHA-256 of the
+//! input files it was computed from, as they were before the run, and of its
+//! own mint files:
+//!
+//! ```json
+//! {
+//!   "mint-20240101-120000": {
+//!     "date": "2024-01-01T12:00:00+00:00",
+//!     "inputs": { "grants.json": "6cf52734..." },
+//!     "files": { "mint-20240101-120000.json": "4a829f18..." }
+//!   }
+//! }
+//! ```
+//!
+//! `verify-integrity` follows each file through the runs that recorded it,
+//! then to its current content. A file that changed or disappeared after a
+//! run recorded it was modified after that run was committed. That includes
+//! the edits of `disable` and `enable`, which are for the auditor to match
+//! with their records.
+//!
+//! `prune` records the files it rewrote or removed in a `prune-<date>`
+//! entry, under `pruned`, with `null` for removed files. Its changes aren't
+//! reported, but any change after it is.
+use crate::storage::Storage;
+use crate::{input_files, sha256};
+use chrono::{DateTime, Local};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The section of the entries of `prune`.
+const PRUNED: &str = "pruned";
+
+/// The run a mint file belongs to: its name, without the extension and the
+/// part number of split runs.
+fn run_of(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    stem.split_once("-part")
+        .map_or(&*stem, |(run, _)| run)
+        .to_string()
+}
+
+fn load(storage: &dyn Storage) -> Result<Map<String, Value>, anyhow::Error> {
+    let path = Path::new(MANIFEST_FILE);
+    if !storage.exists(path) {
+        return Ok(Map::new());
+    }
+    serde_json::from_str(&storage.read_to_string(path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid {:?}: {}", path, e))
+}
+
+/// Record the mint file `path` of a run, and with the first one of the run,
+/// the inputs it was computed from.
+pub fn record(storage: &dyn Storage, path: &Path) -> Result<(), anyhow::Error> {
+    let run = run_of(path);
+    let mut manifest = load(storage)?;
+    if !manifest.contains_key(&run) {
+        let mut inputs = Map::new();
+        for input in input_files(storage)? {
+            if run_of(&input) != run {
+                let digest = sha256::hex_digest(&storage.read(&input)?);
+                inputs.insert(input.display().to_string(), json!(digest));
+            }
+        }
+        manifest.insert(
+            run.clone(),
+            json!({ "date": chrono::Local::now().to_rfc3339(), "inputs": inputs, "files": {} }),
+        );
+    }
+    manifest[&run]["files"][path.display().to_string()] =
+        json!(sha256::hex_digest(&storage.read(path)?));
+    storage.write(
+        Path::new(MANIFEST_FILE),
+        format!("{}\n", serde_json::to_string_pretty(&manifest)?).as_bytes(),
+    )
+}
+
+/// Record the files `prune` rewrote or removed, as they are now, so their
+/// changes aren't reported.
+pub fn record_prune(
+    storage: &dyn Storage,
+    date: DateTime<Local>,
+    files: &[PathBuf],
+) -> Result<(), anyhow::Error> {
+    let mut manifest = load(storage)?;
+    let mut pruned = Map::new();
+    for path in files {
+        let digest = if storage.exists(path) {
+            json!(sha256::hex_digest(&storage.read(path)?))
+        } else {
+            Value::Null
+        };
+        pruned.insert(path.display().to_string(), digest);
+    }
+    manifest.insert(
+        format!("prune-{}", date.format("%Y%m%d-%H%M%S")),
+        json!({ "date": date.to_rfc3339(), PRUNED: pruned }),
+    );
+    storage.write(
+        Path::new(MANIFEST_FILE),
+        format!("{}\n", serde_json::to_string_pretty(&manifest)?).as_bytes(),
+    )
+}
+
+/// A checksum of a file recorded in the manifest.
+struct Observation<'a> {
+    entry: &'a str,
+    /// `None` if the file was removed.
+    digest: Option<&'a str>,
+    pruned: bool,
+}
+
+/// The checksums of each file, in the order they were recorded.
+fn observations(manifest: &Map<String, Value>) -> BTreeMap<String, Vec<Observation<'_>>> {
+    let mut entries = manifest.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(_, entry)| {
+        entry["date"]
+            .as_str()
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+    });
+    let mut observed = BTreeMap::<String, Vec<Observation>>::new();
+    for (name, entry) in entries {
+        for section in ["inputs", "files", PRUNED] {
+            for (file, digest) in entry[section].as_object().into_iter().flatten() {
+                observed.entry(file.clone()).or_default().push(Observation {
+                    entry: name,
+                    digest: match section {
+                        PRUNED => digest.as_str(),
+                        _ => Some(digest.as_str().unwrap_or_default()),
+                    },
+                    pruned: section == PRUNED,
+                });
+            }
+        }
+    }
+    observed
+}
+
+fn current_digest(storage: &dyn Storage, path: &Path) -> Result<Option<String>, anyhow::Error> {
+    if !storage.exists(path) {
+        return Ok(None);
+    }
+    Ok(Some(sha256::hex_digest(&storage.read(path)?)))
+}
+
+/// Whether `prune` was the last to change `path`, and it is as it left it.
+pub fn is_pruned(storage: &dyn Storage, path: &Path) -> Result<bool, anyhow::Error> {
+    let manifest = load(storage)?;
+    let observed = observations(&manifest);
+    let Some(last) = observed
+        .get(&path.display().to_string())
+        .and_then(|observations| observations.last())
+    else {
+        return Ok(false);
+    };
+    Ok(last.pruned && current_digest(storage, path)?.as_deref() == last.digest)
+}
+
+/// A file that changed after a run recorded it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub file: String,
+    /// The last run that recorded the file as it was.
+    pub after: String,
+    /// The next run that recorded it, or `None` if it changed since.
+    pub before: Option<String>,
+    pub removed: bool,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let change = if self.removed { "removed" } else { "modified" };
+        write!(f, "{}: {change} after {}", self.file, self.after)?;
+        match &self.before {
+            Some(before) => write!(f, ", before {before}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Check the recorded checksums against each other and the files. Returns
+/// the number of runs recorded, and the changes found.
+pub fn check(storage: &dyn Storage) -> Result<(usize, Vec<Finding>), anyhow::Error> {
+    let manifest = load(storage)?;
+
+    let mut findings = Vec::new();
+    for (file, observations) in &observations(&manifest) {
+        for pair in observations.windows(2) {
+            let (after, before) = (&pair[0], &pair[1]);
+            if after.digest != before.digest && !before.pruned {
+                findings.push(Finding {
+                    file: file.clone(),
+                    after: after.entry.to_string(),
+                    before: Some(before.entry.to_string()),
+                    removed: false,
+                });
+            }
+        }
+        let Some(last) = observations.last() else {
+            continue;
+        };
+        let digest = current_digest(storage, Path::new(file))?;
+        if digest.as_deref() != last.digest {
+            findings.push(Finding {
+                file: file.clone(),
+                after: last.entry.to_string(),
+                before: None,
+                removed: digest.is_none(),
+            });
+        }
+    }
+    let runs = manifest
+        .values()
+        .filter(|entry| entry.get(PRUNED).is_none())
+        .count();
+    Ok((runs, findings))
+}
+
+pub fn verify_integrity(storage: &dyn Storage) -> Result<(), anyhow::Error> {
+    let (runs, findings) = check(storage)?;
+    for finding in &findings {
+        println!("{finding}");
+    }
+    if !findings.is_empty() {
+        anyhow::bail!(
+            "{} change(s) to files after runs recorded them.",
+            findings.len()
+        );
+    }
+    eprintln!("The files recorded by {runs} run(s) are as they were.");
+    Ok(())
+}
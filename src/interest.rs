@@ -0,0 +1,137 @@
+//! Interest on overdue allocations. An optional `interest.json` in the data
+//! directory describes a simple interest rule:
+//!
+//! ```json
+//! { "due": "2024-06-30", "rate": 5.0, "period_days": 365, "ids": ["m..."] }
+//! ```
+//!
+//! Past the due date, each identity's unpaid principal (its allocations minus
+//! what was minted so far) accrues `rate` percent every `period_days`. Mints
+//! pay down the principal first. `ids` is optional and limits the rule to
+//! these identities.
+use crate::storage::Storage;
+use crate::{input_files, is_mint_file, read_allocation, run_date, Status};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+pub const INTEREST_FILE: &str = "interest.json";
+
+#[derive(Debug)]
+pub struct InterestRule {
+    due: DateTime<Local>,
+    rate: f64,
+    period_days: f64,
+    ids: Option<BTreeSet<String>>,
+}
+
+pub fn load(storage: &dyn Storage) -> Result<Option<InterestRule>, anyhow::Error> {
+    let path = Path::new(INTEREST_FILE);
+    if !storage.exists(path) {
+        return Ok(None);
+    }
+    let value: Value = serde_json::from_str(&storage.read_to_string(path)?)?;
+
+    let due = value["due"]
+        .as_str()
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .and_then(|d| {
+            Local
+                .from_local_datetime(&d.and_hms_opt(0, 0, 0)?)
+                .earliest()
+        })
+        .ok_or_else(|| anyhow::anyhow!("Invalid or missing 'due' date in {:?}", path))?;
+    let rate = value["rate"]
+        .as_f64()
+        .ok_or_else(|| anyhow::anyhow!("Invalid or missing 'rate' in {:?}", path))?;
+    let period_days = match &value["period_days"] {
+        Value::Null => 365.0,
+        v => v
+            .as_f64()
+            .filter(|d| *d > 0.0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid 'period_days' in {:?}", path))?,
+    };
+    let ids = match &value["ids"] {
+        Value::Null => None,
+        Value::Array(ids) => Some(
+            ids.iter()
+                .map(|id| id.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .ok_or_else(|| anyhow::anyhow!("Invalid 'ids' in {:?}", path))?,
+        ),
+        _ => anyhow::bail!("Invalid 'ids' in {:?}", path),
+    };
+
+    Ok(Some(InterestRule {
+        due,
+        rate,
+        period_days,
+        ids,
+    }))
+}
+
+/// Compute the interest accrued by each identity up to `now`, in base units.
+pub fn accrued(
+    storage: &dyn Storage,
+    rule: &InterestRule,
+    now: DateTime<Local>,
+) -> Result<BTreeMap<String, i128>, anyhow::Error> {
+    let mut principal = BTreeMap::<String, i128>::new();
+    let mut runs = Vec::new();
+    for path in input_files(storage)? {
+        let file = read_allocation(storage, &path)?;
+        if is_mint_file(&path) {
+            runs.push((run_date(storage, &path)?, file.amounts));
+        } else if file.is_effective(now) {
+            for (id, tokens) in file.with_status(Status::Approved) {
+                *principal.entry(id).or_default() += tokens;
+            }
+        }
+    }
+    runs.sort_by_key(|(date, _)| *date);
+    principal.retain(|id, _| rule.ids.as_ref().is_none_or(|ids| ids.contains(id)));
+
+    let rate_per_second = rule.rate / 100.0 / (rule.period_days * 86400.0);
+    let mut interest = BTreeMap::<String, f64>::new();
+    let mut since = rule.due;
+    let mut accrue = |principal: &BTreeMap<String, i128>, until: DateTime<Local>| {
+        if until > since {
+            let seconds = (until - since).num_seconds() as f64;
+            for (id, p) in principal.iter().filter(|(_, p)| **p > 0) {
+                *interest.entry(id.clone()).or_default() += *p as f64 * rate_per_second * seconds;
+            }
+            since = until;
+        }
+    };
+
+    for (date, amounts) in runs {
+        accrue(&principal, date);
+        for (id, tokens) in amounts {
+            if let Some(p) = principal.get_mut(&id) {
+                *p += tokens;
+            }
+        }
+    }
+    accrue(&principal, now);
+
+    Ok(interest
+        .into_iter()
+        .map(|(id, i)| (id, i as i128))
+        .filter(|(_, i)| *i > 0)
+        .collect())
+}
+
+/// A short description of the rule, for reports.
+pub fn describe(rule: &InterestRule) -> String {
+    format!(
+        "{}% per {} days past {}{}",
+        rule.rate,
+        rule.period_days,
+        rule.due.format("%Y-%m-%d"),
+        match &rule.ids {
+            Some(ids) => format!(" ({} identities)", ids.len()),
+            None => String::new(),
+        }
+    )
+}
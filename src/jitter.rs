@@ -0,0 +1,139 @@
+//! How `--randomize` varies each identity's maximum: a factor drawn from a
+//! range, `0.8..1.2` by default, uniformly or from a normal distribution.
+//! `--randomize-range` and `--randomize-dist` change it for the run, and an
+//! optional `jitter.json` per identity (or alias):
+//!
+//! ```json
+//! {
+//!   "alice": "0.5..1.5",
+//!   "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f": { "range": "1..1.1", "dist": "normal" }
+//! }
+//! ```
+//!
+//! The normal distribution is centered on the middle of the range, with the
+//! range four standard deviations wide, and clamped to it.
+use crate::aliases;
+use crate::storage::Storage;
+use rand::Rng;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const JITTER_FILE: &str = "jitter.json";
+
+/// The range of the factor applied to the maximum, as in `0.8..1.2`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl std::str::FromStr for Range {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected a range of factors as in '0.8..1.2', got '{s}'");
+        let (low, high) = s.split_once("..").ok_or_else(invalid)?;
+        let factor = |f: &str| f.trim().parse::<f64>().ok().filter(|f| f.is_finite());
+        match (factor(low), factor(high)) {
+            (Some(low), Some(high)) if 0.0 <= low && low <= high => Ok(Self { low, high }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.low, self.high)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Distribution {
+    #[default]
+    Uniform,
+    Normal,
+}
+
+/// How to draw the factor of an identity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Jitter {
+    pub range: Range,
+    pub dist: Distribution,
+}
+
+impl Default for Jitter {
+    fn default() -> Self {
+        Self {
+            range: Range {
+                low: 0.8,
+                high: 1.2,
+            },
+            dist: Distribution::Uniform,
+        }
+    }
+}
+
+impl Jitter {
+    /// Draw a factor.
+    pub fn sample(&self, rand: &mut (impl Rng + ?Sized)) -> f64 {
+        let Range { low, high } = self.range;
+        if low == high {
+            return low;
+        }
+        match self.dist {
+            Distribution::Uniform => rand.gen_range(low..high),
+            Distribution::Normal => {
+                // Box-Muller, from two uniform draws.
+                let u = 1.0 - rand.gen::<f64>();
+                let v = rand.gen::<f64>();
+                let z = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+                ((low + high) / 2.0 + z * (high - low) / 4.0).clamp(low, high)
+            }
+        }
+    }
+}
+
+/// Read the overrides of `jitter.json`, by identity. Entries not given there
+/// use `default`, and fields not given in an entry are those of `default`.
+pub fn load(
+    storage: &dyn Storage,
+    default: Jitter,
+) -> Result<BTreeMap<String, Jitter>, anyhow::Error> {
+    let path = Path::new(JITTER_FILE);
+    if !storage.exists(path) {
+        return Ok(BTreeMap::new());
+    }
+    let entries: BTreeMap<String, Value> = serde_json::from_str(&storage.read_to_string(path)?)
+        .map_err(|e| {
+            anyhow::anyhow!("Invalid {:?}, expected an object per identity: {}", path, e)
+        })?;
+    let aliases = aliases::load(storage)?;
+
+    let mut overrides = BTreeMap::new();
+    for (key, value) in entries {
+        let invalid = |reason: String| anyhow::anyhow!("Invalid {:?}: '{key}': {reason}.", path);
+        let (range, dist) = match &value {
+            Value::String(range) => (Some(range.as_str()), None),
+            Value::Object(fields) => (
+                fields.get("range").and_then(Value::as_str),
+                fields.get("dist").and_then(Value::as_str),
+            ),
+            _ => {
+                return Err(invalid(format!(
+                    "expected a range or an object, got {value}"
+                )))
+            }
+        };
+        let mut jitter = default;
+        if let Some(range) = range {
+            jitter.range = range.parse().map_err(invalid)?;
+        }
+        if let Some(dist) = dist {
+            jitter.dist = <Distribution as clap::ValueEnum>::from_str(dist, false)
+                .map_err(|_| invalid(format!("unknown distribution '{dist}'")))?;
+        }
+        overrides.insert(aliases::resolve(&aliases, &key), jitter);
+    }
+    Ok(overrides)
+}
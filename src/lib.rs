@@ -0,0 +1,1603 @@
+//! The logic behind many-after8: reading the allocation files of a directory
+//! into balances, planning mint runs, and generating the payloads and commands
+//! to submit them. The `many-after8` binary is a command line interface over
+//! this crate.
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use error::ReadError;
+use progress::Progress;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_json::Value;
+use shell::Shell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use storage::Storage;
+
+pub mod addressbook;
+pub mod aliases;
+pub mod amounts;
+pub mod audit;
+pub mod bundle;
+pub mod calendar;
+pub mod cbor;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod config;
+pub mod convert;
+pub mod csv;
+pub mod deprecations;
+pub mod devnet;
+pub mod disable;
+pub mod error;
+pub mod flat;
+pub mod history;
+pub mod identity;
+pub mod inspect;
+pub mod integrity;
+pub mod interest;
+pub mod jitter;
+pub mod managed;
+pub mod memo;
+pub mod periods;
+pub mod plan;
+pub mod preview;
+pub mod priority;
+pub mod progress;
+pub mod prune;
+pub mod receipts;
+pub mod recipients;
+pub mod report;
+pub mod restate;
+pub mod review;
+pub mod rng;
+pub mod rollback;
+pub mod run_report;
+pub mod scan;
+pub mod search;
+pub mod select;
+pub mod session;
+pub mod sha256;
+pub mod shell;
+pub mod signatures;
+pub mod state;
+pub mod storage;
+pub mod supply;
+pub mod totals;
+pub mod treasury;
+pub mod trickle;
+pub mod validate;
+pub mod verify;
+pub mod version;
+
+/// The number of base units in one token.
+pub const DENOMINATOR: u64 = 1_000_000_000;
+
+/// The number of decimals of a token.
+pub const DECIMALS: u32 = 9;
+
+/// The largest amount an entry can have, in base units. Anything larger is most
+/// likely missing a decimal point.
+pub(crate) const MAX_ENTRY: i128 = DENOMINATOR as i128 * DENOMINATOR as i128;
+
+/// The default ledger endpoint the mint requests are submitted to.
+pub const LEDGER_URL: &str = "https://alberto.app/api";
+
+/// The address of the token to mint by default.
+pub const TOKEN: &str = "mqbh742x4s356ddaryrxaowt4wxtlocekzpufodvowrirfrqaaaaa3l";
+
+/// Where to mint: the ledger endpoint and the token address.
+#[derive(Debug, Clone, PartialEq, Eq, clap::Args)]
+pub struct Ledger {
+    /// The ledger endpoint to submit to.
+    #[arg(long, env = "MANY_AFTER8_URL", default_value = LEDGER_URL)]
+    pub url: String,
+
+    /// The address of the token.
+    #[arg(long, env = "MANY_AFTER8_TOKEN", default_value = TOKEN, value_parser = token_arg)]
+    pub token: String,
+}
+
+/// Check that a token address is a valid MANY identity, in its textual form.
+fn token_arg(s: &str) -> Result<String, String> {
+    s.parse::<identity::Identity>()
+        .map(|id| id.to_string())
+        .map_err(|e| format!("invalid token address '{s}': {e}"))
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self {
+            url: LEDGER_URL.to_string(),
+            token: TOKEN.to_string(),
+        }
+    }
+}
+
+/// The key of an allocation file that holds the date it takes effect on.
+pub const EFFECTIVE_KEY: &str = "effective";
+
+/// Files in the directory that are not allocation files.
+pub const RESERVED_FILES: &[&str] = &[
+    aliases::ALIASES_FILE,
+    calendar::CALENDAR_FILE,
+    config::CONFIG_JSON,
+    config::CONFIG_TOML,
+    convert::CONVERSIONS_FILE,
+    convert::RATES_FILE,
+    integrity::MANIFEST_FILE,
+    interest::INTEREST_FILE,
+    jitter::JITTER_FILE,
+    periods::PERIODS_FILE,
+    priority::PRIORITY_FILE,
+    recipients::RECIPIENTS_FILE,
+    totals::TOTALS_FILE,
+];
+
+/// List all the input files in the directory (JSON, CSV, YAML and TOML),
+/// sorted by name.
+pub fn input_files(storage: &dyn Storage) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = Vec::new();
+    for path in storage.list(Path::new(""))? {
+        let reserved = path
+            .file_name()
+            .is_some_and(|n| RESERVED_FILES.iter().any(|r| n == *r));
+        if is_input(&path) && !reserved {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Whether the path is a mint file generated by this tool.
+pub fn is_mint_file(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with("mint-") && n.ends_with(".json"))
+}
+
+/// The date of a mint run, from the timestamp in its file name. Falls back to
+/// the file modification time if the name doesn't parse.
+pub fn run_date(storage: &dyn Storage, path: &Path) -> Result<DateTime<Local>, anyhow::Error> {
+    let stamp = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.strip_prefix("mint-"))
+        // Parts of a split mint file have a "-part<n>" suffix.
+        .map(|s| s.split_once("-part").map_or(s, |(stamp, _)| stamp))
+        .and_then(|s| NaiveDateTime::parse_from_str(s, "%Y%m%d-%H%M%S").ok())
+        .and_then(|d| Local.from_local_datetime(&d).earliest());
+    match stamp {
+        Some(date) => Ok(date),
+        None => storage.modified(path),
+    }
+}
+
+/// An amount that earlier versions, which parsed amounts through floats, read
+/// differently.
+pub struct PrecisionLoss {
+    pub id: String,
+    pub value: Value,
+    /// The amount as read through a float, in base units.
+    pub parsed: i128,
+    /// The exact amount, in base units.
+    pub exact: i128,
+}
+
+/// Parse a decimal amount of tokens exactly, in base units, e.g. `"1234.5"` or
+/// `"1e-9"`. Digits past the 9th decimal are truncated.
+pub fn parse_tokens(s: &str) -> Option<i128> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i32>().ok()?),
+        None => (s, 0),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let is_digits = |d: &str| d.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return None;
+    }
+
+    // The amount is `digits * 10^scale` base units.
+    let digits = format!("{whole}{fraction}");
+    let scale = (DECIMALS as i32)
+        .checked_add(exponent)?
+        .checked_sub(i32::try_from(fraction.len()).ok()?)?;
+    let units = if scale >= 0 {
+        digits
+            .parse::<i128>()
+            .ok()?
+            .checked_mul(10i128.checked_pow(scale as u32)?)?
+    } else {
+        let kept = i32::try_from(digits.len()).ok()?.saturating_add(scale);
+        if kept <= 0 {
+            0
+        } else {
+            digits[..kept as usize].parse::<i128>().ok()?
+        }
+    };
+    Some(if negative { -units } else { units })
+}
+
+/// Format an amount in base units as tokens, with all 9 decimals.
+pub fn format_tokens(units: i128) -> String {
+    let sign = if units < 0 { "-" } else { "" };
+    let units = units.unsigned_abs();
+    let denominator = DENOMINATOR as u128;
+    format!(
+        "{sign}{}.{:0width$}",
+        units / denominator,
+        units % denominator,
+        width = DECIMALS as usize
+    )
+}
+
+/// Format an amount in base units as tokens, without trailing zeros.
+pub(crate) fn format_tokens_short(units: i128) -> String {
+    format_tokens(units)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// The key of an amount given in base units, as in `{"amount_minor": "1"}`.
+pub const MINOR_KEY: &str = "amount_minor";
+
+/// The prefix of an amount given in base units, as in `"u:1"`.
+pub const MINOR_PREFIX: &str = "u:";
+
+/// An amount given in base units, which is taken as is without any decimal
+/// conversion. `None` if `value` isn't in one of these forms, `Some(None)` if
+/// it is but doesn't hold an integer.
+fn minor_units(value: &Value) -> Option<Option<i128>> {
+    let units = match value {
+        Value::String(s) => s.strip_prefix(MINOR_PREFIX)?.to_string(),
+        Value::Object(o) if o.len() == 1 => match o.get(MINOR_KEY)? {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            _ => return Some(None),
+        },
+        _ => return None,
+    };
+    Some(units.trim().replace(',', "").parse().ok())
+}
+
+/// The key of the amount of an entry given as an object, as in
+/// `{"amount": "12.5", "status": "draft"}`.
+pub const AMOUNT_KEY: &str = "amount";
+
+/// The key of the review status of an entry given as an object.
+pub const STATUS_KEY: &str = "status";
+
+/// The review status of an allocation entry. Only approved entries are
+/// minted; entries without a status are approved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Status {
+    /// Waiting for sign-off.
+    Draft,
+    /// Signed off, to be minted.
+    #[default]
+    Approved,
+    /// Signed off, but on hold.
+    Paused,
+}
+
+/// Split the status off an entry given as an object, leaving its amount.
+pub fn entry_status(path: &Path, name: &str, value: Value) -> Result<(Value, Status), ReadError> {
+    let Value::Object(mut object) = value else {
+        return Ok((value, Status::Approved));
+    };
+    let status = match object.remove(STATUS_KEY) {
+        None => Status::Approved,
+        Some(status) => status
+            .as_str()
+            .and_then(|s| <Status as clap::ValueEnum>::from_str(s, false).ok())
+            .ok_or_else(|| ReadError::InvalidStatus {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                value: status.clone(),
+            })?,
+    };
+    let value = match object.remove(AMOUNT_KEY) {
+        Some(amount) if object.is_empty() => amount,
+        Some(amount) => {
+            object.insert(AMOUNT_KEY.to_string(), amount);
+            Value::Object(object)
+        }
+        None => Value::Object(object),
+    };
+    Ok((value, status))
+}
+
+/// The key of the flag of a disabled entry, as in
+/// `{"amount": "12.5", "disabled": true}`. Disabled entries stay in their file
+/// but aren't counted, until they are enabled again.
+pub const DISABLED_KEY: &str = "disabled";
+
+/// Split the disabled flag off an entry given as an object, leaving the rest
+/// of the entry.
+pub fn entry_disabled(path: &Path, name: &str, value: Value) -> Result<(Value, bool), ReadError> {
+    let Value::Object(mut object) = value else {
+        return Ok((value, false));
+    };
+    let disabled = match object.remove(DISABLED_KEY) {
+        None => false,
+        Some(Value::Bool(disabled)) => disabled,
+        Some(value) => {
+            return Err(ReadError::InvalidDisabled {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                value,
+            })
+        }
+    };
+    Ok((Value::Object(object), disabled))
+}
+
+/// The content of an allocation file.
+pub struct AllocationFile {
+    /// The amount (in base units) for each id.
+    pub amounts: BTreeMap<String, i128>,
+    /// The status of the entries that aren't approved.
+    pub statuses: BTreeMap<String, Status>,
+    /// The entries that are disabled, which aren't in `amounts`.
+    pub disabled: BTreeSet<String>,
+    /// The date the file takes effect on, if it has one. It isn't counted
+    /// before that.
+    pub effective: Option<NaiveDate>,
+    /// The amounts earlier versions read differently.
+    pub losses: Vec<PrecisionLoss>,
+    /// The entries in other tokens converted at the current rate.
+    pub conversions: Vec<convert::Conversion>,
+}
+
+impl AllocationFile {
+    pub fn is_effective(&self, now: DateTime<Local>) -> bool {
+        self.effective.is_none_or(|date| date <= now.date_naive())
+    }
+
+    pub fn status(&self, id: &str) -> Status {
+        self.statuses.get(id).copied().unwrap_or_default()
+    }
+
+    /// The amounts of the entries with `status`.
+    pub fn with_status(self, status: Status) -> BTreeMap<String, i128> {
+        let statuses = self.statuses;
+        self.amounts
+            .into_iter()
+            .filter(|(id, _)| statuses.get(id).copied().unwrap_or_default() == status)
+            .collect()
+    }
+}
+
+/// Read a single JSON file, returning the amount (in base units) for each id.
+pub fn read_json(
+    storage: &dyn Storage,
+    path: &Path,
+) -> Result<BTreeMap<String, i128>, anyhow::Error> {
+    Ok(read_allocation(storage, path)?.amounts)
+}
+
+/// The extensions of YAML and TOML input files.
+pub const YAML_EXTENSIONS: &[&str] = &["yaml", "yml"];
+pub const TOML_EXTENSION: &str = "toml";
+
+/// Whether the path is an input file, by its extension.
+fn is_input(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| {
+            ext == "json"
+                || ext == csv::EXTENSION
+                || ext == TOML_EXTENSION
+                || YAML_EXTENSIONS.contains(&ext)
+        })
+}
+
+/// The entries of an input file of any format, with their line numbers when
+/// the format gives them. Aliases of `aliases.json`, and identities given as
+/// hex or DIDs, are converted to the textual form of the identity.
+pub fn input_entries(
+    storage: &dyn Storage,
+    path: &Path,
+    text: &str,
+) -> Result<Vec<(Option<usize>, String, Value)>, ReadError> {
+    let aliases = aliases::load(storage).map_err(|e| ReadError::Unreadable {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    })?;
+    let mut seen = BTreeMap::new();
+    let mut entries = Vec::new();
+    for (line, key, value) in raw_entries(storage, path, text)? {
+        let id = aliases::resolve(&aliases, &key);
+        if let Some(first) = seen.insert(id.clone(), key.clone()) {
+            return Err(ReadError::DuplicateId {
+                path: path.to_path_buf(),
+                key: id,
+                first,
+                second: key,
+            });
+        }
+        entries.push((line, id, value));
+    }
+    Ok(entries)
+}
+
+/// The entries of an input file of any format, with their keys as written.
+fn raw_entries(
+    storage: &dyn Storage,
+    path: &Path,
+    text: &str,
+) -> Result<Vec<(Option<usize>, String, Value)>, ReadError> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let lines = if ext == csv::EXTENSION {
+        csv::entries(storage, path, text)?
+    } else if ext == TOML_EXTENSION || YAML_EXTENSIONS.contains(&ext) {
+        let entries = if ext == TOML_EXTENSION {
+            flat::toml(text)
+        } else {
+            flat::yaml(text)
+        };
+        entries.map_err(|e| ReadError::InvalidLine {
+            path: path.to_path_buf(),
+            line: e.line,
+            reason: e.reason,
+        })?
+    } else {
+        let data: BTreeMap<String, Value> =
+            serde_json::from_str(text).map_err(|source| ReadError::InvalidJson {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        return Ok(data.into_iter().map(|(k, v)| (None, k, v)).collect());
+    };
+    Ok(lines
+        .into_iter()
+        .map(|(line, k, v)| (Some(line), k, v))
+        .collect())
+}
+
+pub fn read_allocation(
+    storage: &dyn Storage,
+    path: &Path,
+) -> Result<AllocationFile, anyhow::Error> {
+    let mut balance = BTreeMap::<String, i128>::new();
+    let mut losses = Vec::new();
+
+    let data = storage
+        .read_to_string(path)
+        .map_err(|e| ReadError::Unreadable {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+    let mut data = input_entries(storage, path, &data)?
+        .into_iter()
+        .map(|(_, key, value)| (key, value))
+        .collect::<BTreeMap<_, _>>();
+    data.remove(managed::COMMENTS_KEY);
+    data.remove(restate::RESTATES_KEY);
+    let effective = match data.remove(EFFECTIVE_KEY) {
+        None => None,
+        Some(date) => Some(
+            date.as_str()
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+                .ok_or_else(|| ReadError::InvalidDate {
+                    path: path.to_path_buf(),
+                    value: date.clone(),
+                })?,
+        ),
+    };
+    // Only read the rates for files with entries in other tokens.
+    let rates = if data.values().any(|v| v.get(convert::IN_KEY).is_some()) {
+        convert::Rates::load(storage)?
+    } else {
+        convert::Rates::default()
+    };
+    let mut statuses = BTreeMap::new();
+    let mut disabled = BTreeSet::new();
+    let mut conversions = Vec::new();
+    for (name, value) in data {
+        let (value, is_disabled) = entry_disabled(path, &name, value)?;
+        let (value, token) = convert::entry_token(path, &name, value)?;
+        let (value, status) = entry_status(path, &name, value)?;
+        let (mut units, mut loss) = read_entry(path, &name, value)?;
+        if is_disabled {
+            disabled.insert(name);
+            continue;
+        }
+        if let Some(token) = token {
+            let conversion;
+            (units, conversion) = rates.convert(path, &name, &token, units)?;
+            conversions.extend(conversion);
+            loss = None;
+        }
+        losses.extend(loss);
+        if status != Status::Approved {
+            statuses.insert(name.clone(), status);
+        }
+        *balance.entry(name).or_default() += units;
+    }
+
+    Ok(AllocationFile {
+        amounts: balance,
+        statuses,
+        disabled,
+        effective,
+        losses,
+        conversions,
+    })
+}
+
+/// Read the amount of a single entry of an allocation file, in base units,
+/// along with how earlier versions read it if that differs.
+pub fn read_entry(
+    path: &Path,
+    name: &str,
+    value: Value,
+) -> Result<(i128, Option<PrecisionLoss>), ReadError> {
+    if let Some(minor) = minor_units(&value) {
+        return match minor {
+            Some(units) if units <= MAX_ENTRY => Ok((units, None)),
+            Some(_) => Err(ReadError::AmountTooLarge {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                value,
+            }),
+            None => Err(ReadError::InvalidAmount {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                value,
+            }),
+        };
+    }
+
+    let text = match &value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.replace(',', ""),
+        _ => {
+            return Err(ReadError::InvalidType {
+                path: path.to_path_buf(),
+                key: name.to_string(),
+                value,
+            })
+        }
+    };
+    let Some(tokens) = parse_tokens(&text) else {
+        return Err(ReadError::InvalidAmount {
+            path: path.to_path_buf(),
+            key: name.to_string(),
+            value,
+        });
+    };
+    // A small sanity check. This means that a period was missed or
+    // something.
+    if tokens > MAX_ENTRY {
+        return Err(ReadError::AmountTooLarge {
+            path: path.to_path_buf(),
+            key: name.to_string(),
+            value,
+        });
+    }
+
+    let float = text
+        .parse::<f64>()
+        .map(|t| (t * DENOMINATOR as f64) as i128);
+    let loss = float
+        .ok()
+        .filter(|f| *f != tokens)
+        .map(|parsed| PrecisionLoss {
+            id: name.to_string(),
+            value,
+            parsed,
+            exact: tokens,
+        });
+    Ok((tokens, loss))
+}
+
+/// How to read the balances of a directory.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadOptions {
+    /// Print a timing report of reading and aggregating the files.
+    pub profile: bool,
+    /// Warn about amounts that earlier versions, which parsed amounts through
+    /// floats, read differently by more than this many base units. `None`
+    /// disables the warnings.
+    pub precision_tolerance: Option<u64>,
+    /// Only count the entries of allocation files with this status. Mint
+    /// files and interest only count towards approved balances.
+    pub status: Status,
+    /// Read entries whose key isn't a valid MANY identity, instead of failing.
+    pub allow_unknown_ids: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            profile: false,
+            precision_tolerance: Some(1),
+            status: Status::Approved,
+            allow_unknown_ids: false,
+        }
+    }
+}
+
+/// Check that the key of an entry is an identity tokens can be minted to.
+fn check_id(path: &Path, name: &str) -> Result<(), ReadError> {
+    let reason = match name.parse::<identity::Identity>() {
+        Ok(id) if id.is_addressable() => return Ok(()),
+        Ok(_) => "anonymous identity cannot hold tokens".to_string(),
+        Err(identity::IdentityError::MissingPrefix) => {
+            format!("not an identity, nor a name of {}", aliases::ALIASES_FILE)
+        }
+        Err(e) => e.to_string(),
+    };
+    Err(ReadError::InvalidId {
+        path: path.to_path_buf(),
+        key: name.to_string(),
+        reason,
+    })
+}
+
+/// Read and add up all the input files, returning the net balance of each
+/// identity, and the entries counted in it that were converted at the current
+/// rate.
+fn read_all_jsons(
+    storage: &dyn Storage,
+    options: &ReadOptions,
+    progress: &Progress,
+) -> Result<(BTreeMap<String, i128>, Vec<convert::Conversion>), anyhow::Error> {
+    let ReadOptions {
+        profile,
+        precision_tolerance: tolerance,
+        status,
+        allow_unknown_ids,
+    } = *options;
+    let start = Instant::now();
+    // Read all the JSON files.
+    let mut balance = BTreeMap::<String, i128>::new();
+
+    let files = input_files(storage)?;
+    let listed = start.elapsed();
+    progress.event("files_listed", serde_json::json!({ "total": files.len() }));
+    progress.start("Aggregating", files.len());
+    let mut entries = 0;
+    let mut slowest = Vec::new();
+    let mut losses = Vec::new();
+    let mut conversions = Vec::new();
+    let now = Local::now();
+    for (index, path) in files.iter().enumerate() {
+        let file_start = Instant::now();
+        let mut file = read_allocation(storage, path)?;
+        progress.event(
+            "file_parsed",
+            serde_json::json!({
+                "file": path.display().to_string(),
+                "index": index + 1,
+                "total": files.len(),
+                "entries": file.amounts.len(),
+            }),
+        );
+        progress.tick(index + 1);
+        if !file.is_effective(now) {
+            eprintln!(
+                "Skipping {}, it takes effect on {}.",
+                path.display(),
+                file.effective.unwrap_or_default()
+            );
+            continue;
+        }
+        if !allow_unknown_ids {
+            for name in file.amounts.keys() {
+                check_id(path, name)?;
+            }
+        }
+        if let Some(tolerance) = tolerance {
+            losses.extend(
+                std::mem::take(&mut file.losses)
+                    .into_iter()
+                    .filter(|l| l.parsed.abs_diff(l.exact) > tolerance as u128)
+                    .map(|l| (path, l)),
+            );
+        }
+        conversions.extend(
+            std::mem::take(&mut file.conversions)
+                .into_iter()
+                .filter(|c| file.status(&c.id) == status),
+        );
+        for (name, tokens) in file.with_status(status) {
+            let curr = balance.entry(name).or_default();
+            entries += 1;
+
+            // Make sure we don't end up with a negative or too small balance.
+            let new = *curr + tokens;
+            *curr = new;
+        }
+        if profile {
+            slowest.push((file_start.elapsed(), path));
+        }
+    }
+    let aggregated = start.elapsed();
+    progress.finish();
+    progress.event(
+        "aggregated",
+        serde_json::json!({ "identities": balance.len(), "entries": entries }),
+    );
+
+    if !losses.is_empty() {
+        eprintln!(
+            "warning: earlier versions read {} amount(s) differently, by more than {} base unit(s):",
+            losses.len(),
+            tolerance.unwrap_or_default()
+        );
+        for (path, loss) in &losses {
+            eprintln!(
+                "  {}: {}: {} was read as {} base units, now {}",
+                path.display(),
+                loss.id,
+                loss.value,
+                loss.parsed,
+                loss.exact
+            );
+        }
+        eprintln!();
+    }
+
+    if let Some(rule) = interest::load(storage)?.filter(|_| status == Status::Approved) {
+        for (name, tokens) in interest::accrued(storage, &rule, now)? {
+            *balance.entry(name).or_default() += tokens;
+        }
+    }
+
+    if profile {
+        let total = start.elapsed();
+        eprintln!("Aggregation profile:");
+        eprintln!("  files:       {}", files.len());
+        eprintln!("  entries:     {}", entries);
+        eprintln!("  identities:  {}", balance.len());
+        eprintln!("  listing:     {:?}", listed);
+        eprintln!("  parsing:     {:?}", aggregated - listed);
+        eprintln!("  interest:    {:?}", total - aggregated);
+        eprintln!("  total:       {:?}", total);
+        slowest.sort_by_key(|(elapsed, _)| std::cmp::Reverse(*elapsed));
+        for (elapsed, path) in slowest.iter().take(5) {
+            eprintln!("  slowest:     {:?}\t{}", elapsed, path.display());
+        }
+        eprintln!();
+    }
+
+    Ok((balance, conversions))
+}
+
+/// The remaining balance of each identity, in base units. Only identities
+/// with something left to mint are included.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BalanceSet {
+    balances: BTreeMap<String, u64>,
+    overminted: BTreeMap<String, u64>,
+    /// The version of the directory the balances were read at.
+    state_version: Option<u64>,
+    /// The entries in other tokens counted at the current rate.
+    conversions: Vec<convert::Conversion>,
+}
+
+impl BalanceSet {
+    /// Read and add up the allocation and mint files of a directory, including
+    /// interest.
+    pub fn read(
+        storage: &dyn Storage,
+        options: &ReadOptions,
+        progress: &Progress,
+    ) -> Result<Self, anyhow::Error> {
+        let mut set = Self {
+            state_version: Some(state::current(storage)?),
+            ..Self::default()
+        };
+        let (balances, conversions) = read_all_jsons(storage, options, progress)?;
+        set.conversions = conversions;
+        for (key, balance) in balances {
+            if balance.unsigned_abs() >= u64::MAX as u128 {
+                return Err(ReadError::BalanceTooLarge { key, balance }.into());
+            }
+            if balance > 0 {
+                set.balances.insert(key, balance as u64);
+            } else if balance < 0 {
+                set.overminted.insert(key, balance.unsigned_abs() as u64);
+            }
+        }
+        Ok(set)
+    }
+
+    pub fn get(&self, id: &str) -> Option<u64> {
+        self.balances.get(id).copied()
+    }
+
+    /// The balances, sorted by identity.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.balances.iter().map(|(id, b)| (id.as_str(), *b))
+    }
+
+    pub fn len(&self) -> usize {
+        self.balances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.balances.is_empty()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.balances.values().sum()
+    }
+
+    /// The identities that were minted more than they were allocated, e.g.
+    /// after an allocation was reduced, with the excess in base units.
+    pub fn overminted(&self) -> &BTreeMap<String, u64> {
+        &self.overminted
+    }
+
+    /// The version of the directory the balances were read at, if they were
+    /// read from one.
+    pub fn state_version(&self) -> Option<u64> {
+        self.state_version
+    }
+
+    /// Only keep the identities of `selection`.
+    pub fn selected(mut self, selection: &select::Selection) -> Self {
+        self.balances.retain(|id, _| selection.includes(id));
+        self.overminted.retain(|id, _| selection.includes(id));
+        self
+    }
+}
+
+impl From<BTreeMap<String, u64>> for BalanceSet {
+    fn from(balances: BTreeMap<String, u64>) -> Self {
+        Self {
+            balances,
+            overminted: BTreeMap::new(),
+            state_version: None,
+            conversions: Vec::new(),
+        }
+    }
+}
+
+impl From<BalanceSet> for BTreeMap<String, u64> {
+    fn from(set: BalanceSet) -> Self {
+        set.balances
+    }
+}
+
+/// Fails if the tool runs in read-only mode. Every code path that writes to
+/// disk or the network must call this first.
+pub fn ensure_writable(read_only: bool, what: &str) -> Result<(), anyhow::Error> {
+    if read_only {
+        anyhow::bail!("Refusing to {what} in read-only mode.");
+    }
+    Ok(())
+}
+
+/// The order of the entries in the generated payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Order {
+    /// Sorted by id.
+    Id,
+    /// Largest amounts first.
+    Amount,
+    /// Random order.
+    Shuffle,
+}
+
+/// How to plan a mint run.
+#[derive(Debug, Clone)]
+pub struct MintOptions {
+    /// The maximum amount to mint to each identity, in base units.
+    pub max: u64,
+    /// Randomize each identity's maximum, by a factor drawn with `jitter`.
+    pub randomize: bool,
+    /// How to draw the factors when randomizing, within 20% by default.
+    pub jitter: jitter::Jitter,
+    /// How to draw the factor of some identities instead, from `jitter.json`.
+    pub jitter_overrides: BTreeMap<String, jitter::Jitter>,
+    /// When randomizing, rescale the amounts so the total of the run matches
+    /// the total without randomization exactly.
+    pub preserve_total: bool,
+    /// Add bounded Laplace noise with this privacy budget (epsilon) instead.
+    pub noise: Option<f64>,
+    /// The order of the entries in the payload.
+    pub order: Order,
+}
+
+impl Default for MintOptions {
+    fn default() -> Self {
+        Self {
+            max: 100 * DENOMINATOR,
+            randomize: false,
+            jitter: jitter::Jitter::default(),
+            jitter_overrides: BTreeMap::new(),
+            preserve_total: false,
+            noise: None,
+            order: Order::Id,
+        }
+    }
+}
+
+impl MintOptions {
+    /// Whether the plan depends on random draws, and so on the seed.
+    pub fn is_random(&self) -> bool {
+        self.randomize || self.noise.is_some() || self.order == Order::Shuffle
+    }
+
+    /// How to draw the factor of `id` when randomizing.
+    pub fn jitter_of(&self, id: &str) -> &jitter::Jitter {
+        self.jitter_overrides.get(id).unwrap_or(&self.jitter)
+    }
+}
+
+/// Sample Laplace noise of the given scale, truncated to `[-bound, bound]`.
+fn bounded_laplace(rand: &mut (impl Rng + ?Sized), scale: f64, bound: f64) -> f64 {
+    let u: f64 = rand.gen_range(-0.5..0.5);
+    let noise = -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln();
+    noise.clamp(-bound, bound)
+}
+
+/// Scale the randomized caps by a common factor so that the amounts, each
+/// limited by its balance, add up to exactly `total`. `total` must not be more
+/// than the sum of the balances.
+fn rescale_to_total(
+    balances: &BTreeMap<String, u64>,
+    caps: &BTreeMap<String, f64>,
+    total: u64,
+) -> BTreeMap<String, u64> {
+    let amounts = |scale: f64| {
+        balances
+            .iter()
+            .map(|(id, b)| (id.clone(), (*b).min((caps[id] * scale) as u64)))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let sum = |amounts: &BTreeMap<String, u64>| amounts.values().sum::<u64>();
+
+    // Find the largest scale that doesn't go over the total. The sum is
+    // monotonic in the scale, so a bisection works.
+    let (mut low, mut high) = (0.0, 1.0);
+    while sum(&amounts(high)) < total && high < 1e18 {
+        high *= 2.0;
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if sum(&amounts(mid)) <= total {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    // Hand out what's left from rounding, one base unit at a time.
+    let mut result = amounts(low);
+    let mut remainder = total - sum(&result).min(total);
+    while remainder > 0 {
+        let before = remainder;
+        for (id, amount) in result.iter_mut() {
+            if remainder > 0 && *amount < balances[id] {
+                *amount += 1;
+                remainder -= 1;
+            }
+        }
+        if before == remainder {
+            break;
+        }
+    }
+    result
+}
+
+/// The amounts to mint in one run.
+#[derive(Debug, Clone)]
+pub struct MintPlan {
+    amounts: BTreeMap<String, u64>,
+    /// The same amounts, in payload order.
+    entries: Vec<(String, u64)>,
+    /// The version of the directory the plan was computed at. Writing the
+    /// plan fails if the directory changed since.
+    state_version: Option<u64>,
+    /// The seed of the random draws the plan was computed with, if it depends
+    /// on them. It is recorded in `runs.log`.
+    seed: Option<u64>,
+    /// The entries in other tokens the plan was computed with, converted at
+    /// the current rate. Their rates are pinned when the plan is written.
+    conversions: Vec<convert::Conversion>,
+}
+
+impl MintPlan {
+    /// Plan a run, with the random draws of `rand` (any generator, or a
+    /// `rng::Source`).
+    pub fn new(balances: &BalanceSet, options: &MintOptions, rand: &mut impl rng::Source) -> Self {
+        let MintOptions {
+            max,
+            randomize,
+            preserve_total,
+            noise,
+            order,
+            ..
+        } = *options;
+        let balances_state = balances.state_version;
+        let conversions = balances.conversions.clone();
+        let balances = &balances.balances;
+
+        let amounts = if preserve_total {
+            let total = balances.values().map(|b| (*b).min(max)).sum();
+            let caps = balances
+                .keys()
+                .map(|id| {
+                    (
+                        id.clone(),
+                        (max as f64) * options.jitter_of(id).sample(rand.of(id)),
+                    )
+                })
+                .collect();
+            rescale_to_total(balances, &caps, total)
+        } else if let Some(epsilon) = noise {
+            let scale = max as f64 / epsilon;
+            balances
+                .iter()
+                .map(|(id, balance)| {
+                    let noise = bounded_laplace(rand.of(id), scale, max as f64 / 2.0);
+                    let amount = ((*balance).min(max) as f64 + noise).clamp(0.0, *balance as f64);
+                    (id.clone(), amount as u64)
+                })
+                .filter(|(_, amount)| *amount > 0)
+                .collect::<BTreeMap<_, _>>()
+        } else {
+            balances
+                .iter()
+                .map(|(id, balance)| {
+                    let max = if randomize {
+                        ((max as f64) * options.jitter_of(id).sample(rand.of(id))) as u64
+                    } else {
+                        max
+                    };
+                    (id.clone(), (*balance).min(max))
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let mut entries = amounts
+            .iter()
+            .map(|(id, amount)| (id.clone(), *amount))
+            .collect::<Vec<_>>();
+        match order {
+            Order::Id => {}
+            Order::Amount => entries.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then(a_id.cmp(b_id))),
+            Order::Shuffle => entries.shuffle(rand.run()),
+        }
+        Self {
+            amounts,
+            entries,
+            state_version: balances_state,
+            seed: None,
+            conversions,
+        }
+    }
+
+    /// A plan of the given amounts as they are, in identity order.
+    pub fn from_amounts(amounts: BTreeMap<String, u64>) -> Self {
+        let entries = amounts.iter().map(|(id, a)| (id.clone(), *a)).collect();
+        Self {
+            amounts,
+            entries,
+            state_version: None,
+            seed: None,
+            conversions: Vec::new(),
+        }
+    }
+
+    /// Tie the plan to the version of the directory `balances` were read at,
+    /// so writing it fails if the directory changed since.
+    pub fn based_on(mut self, balances: &BalanceSet) -> Self {
+        self.state_version = balances.state_version;
+        self
+    }
+
+    /// Record that the plan was computed with random draws seeded with `seed`.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Bump the version of the directory, failing if it changed since the
+    /// plan was computed.
+    fn commit_state(&self, storage: &dyn Storage) -> Result<(), anyhow::Error> {
+        let loaded = match self.state_version {
+            Some(loaded) => loaded,
+            None => state::current(storage)?,
+        };
+        state::commit(storage, loaded)?;
+        Ok(())
+    }
+
+    /// Change the amount of `id`, keeping its place in the payload. An
+    /// amount of zero leaves it out.
+    pub fn set_amount(&mut self, id: &str, amount: u64) {
+        if amount == 0 {
+            self.amounts.remove(id);
+            self.entries.retain(|(i, _)| i != id);
+            return;
+        }
+        self.amounts.insert(id.to_string(), amount);
+        match self.entries.iter_mut().find(|(i, _)| i == id) {
+            Some(entry) => entry.1 = amount,
+            None => self.entries.push((id.to_string(), amount)),
+        }
+    }
+
+    /// Leave out the entries of less than `min`, returning them.
+    pub fn without_dust(mut self, min: u64) -> (Self, BTreeMap<String, u64>) {
+        let (dust, amounts) = std::mem::take(&mut self.amounts)
+            .into_iter()
+            .partition(|(_, amount)| *amount < min);
+        self.amounts = amounts;
+        self.entries.retain(|(_, amount)| *amount >= min);
+        (self, dust)
+    }
+
+    /// Scale the amounts down so they add up to at most `total`, keeping
+    /// their order. Entries that end up with nothing are left out.
+    pub fn capped(self, total: u64) -> Self {
+        self.capped_by(total, &priority::Priorities::new())
+    }
+
+    /// Like `capped`, but serving the identities in order of priority: each
+    /// priority is paid in full before the next one gets anything, and the
+    /// identities of the priority that reaches `total` are scaled down
+    /// proportionally.
+    pub fn capped_by(mut self, total: u64, priorities: &priority::Priorities) -> Self {
+        if self.total() <= total {
+            return self;
+        }
+        // Identities without a priority come last.
+        let mut groups = BTreeMap::<(bool, Option<u32>), BTreeMap<String, u64>>::new();
+        for (id, amount) in &self.amounts {
+            let priority = priorities.get(id).copied();
+            groups
+                .entry((priority.is_none(), priority))
+                .or_default()
+                .insert(id.clone(), *amount);
+        }
+        let mut remaining = total;
+        let mut amounts = BTreeMap::new();
+        for group in groups.into_values() {
+            let sum = group.values().sum::<u64>();
+            if sum <= remaining {
+                remaining -= sum;
+                amounts.extend(group);
+            } else {
+                let caps = group
+                    .iter()
+                    .map(|(id, amount)| (id.clone(), *amount as f64))
+                    .collect();
+                amounts.extend(rescale_to_total(&group, &caps, remaining));
+                remaining = 0;
+            }
+        }
+        self.amounts = amounts;
+        self.amounts.retain(|_, amount| *amount > 0);
+        let amounts = &self.amounts;
+        self.entries = self
+            .entries
+            .iter()
+            .filter_map(|(id, _)| amounts.get(id).map(|a| (id.clone(), *a)))
+            .collect();
+        self
+    }
+
+    /// The amount to mint to each identity, sorted by identity.
+    pub fn amounts(&self) -> &BTreeMap<String, u64> {
+        &self.amounts
+    }
+
+    /// The amount to mint to each identity, in payload order.
+    pub fn entries(&self) -> &[(String, u64)] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.amounts.values().sum()
+    }
+
+    /// Format the entries as a JSON object, keeping their order.
+    pub fn payload(&self, indent: &str) -> String {
+        if self.entries.is_empty() {
+            return "{}".to_string();
+        }
+        let lines = self
+            .entries
+            .iter()
+            .map(|(id, amount)| format!("{}{}: {}", indent, Value::from(id.as_str()), amount))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        format!("{{\n{}\n}}", lines)
+    }
+
+    /// Format the entries as canonical JSON: sorted by id, without
+    /// insignificant whitespace.
+    pub fn canonical_payload(&self) -> String {
+        let entries = self
+            .amounts
+            .iter()
+            .map(|(id, amount)| format!("{}:{}", Value::from(id.as_str()), amount))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{{{}}}", entries)
+    }
+
+    /// The CBOR-encoded arguments of the `tokens.mint` request.
+    pub fn cbor(&self, ledger: &Ledger, memo: Option<&str>) -> Result<Vec<u8>, anyhow::Error> {
+        let (token, distribution) = self.identities(ledger)?;
+        Ok(cbor::mint_args(&token, &distribution, memo))
+    }
+
+    /// The CBOR-encoded arguments of the `tokens.burn` request that burns the
+    /// amounts of the plan.
+    pub fn burn_cbor(&self, ledger: &Ledger, memo: Option<&str>) -> Result<Vec<u8>, anyhow::Error> {
+        let (token, distribution) = self.identities(ledger)?;
+        Ok(cbor::burn_args(&token, &distribution, memo))
+    }
+
+    /// The token of `ledger` and the entries of the plan, as identities.
+    fn identities(
+        &self,
+        ledger: &Ledger,
+    ) -> Result<(identity::Identity, Vec<(identity::Identity, u64)>), anyhow::Error> {
+        let distribution = self
+            .entries
+            .iter()
+            .map(|(id, amount)| {
+                id.parse::<identity::Identity>()
+                    .map(|id| (id, *amount))
+                    .map_err(|e| anyhow::anyhow!("Invalid identity '{id}': {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let token = ledger
+            .token
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid token address '{}': {e}", ledger.token))?;
+        Ok((token, distribution))
+    }
+
+    /// The `ledger` command line that submits the plan, quoted for `shell`.
+    pub fn command(
+        &self,
+        ledger: &Ledger,
+        pem: &Path,
+        memo: Option<&str>,
+        shell: Shell,
+        canonical: bool,
+    ) -> String {
+        self.ledger_command("mint", ledger, pem, memo, shell, canonical)
+    }
+
+    /// The `ledger` command line that burns the amounts of the plan, quoted
+    /// for `shell`.
+    pub fn burn_command(
+        &self,
+        ledger: &Ledger,
+        pem: &Path,
+        memo: Option<&str>,
+        shell: Shell,
+        canonical: bool,
+    ) -> String {
+        self.ledger_command("burn", ledger, pem, memo, shell, canonical)
+    }
+
+    fn ledger_command(
+        &self,
+        action: &str,
+        ledger: &Ledger,
+        pem: &Path,
+        memo: Option<&str>,
+        shell: Shell,
+        canonical: bool,
+    ) -> String {
+        // cmd can't take multi-line arguments, keep the payload compact there.
+        let indent = if shell == Shell::Cmd { "" } else { "    " };
+        let payload = if canonical {
+            self.canonical_payload()
+        } else {
+            self.payload(indent)
+        };
+        format!(
+            "ledger --pem {} {} token {} {} {} {}",
+            shell.quote_if_needed(&pem.display().to_string()),
+            shell.quote_if_needed(&ledger.url),
+            action,
+            shell.quote_if_needed(&ledger.token),
+            shell.quote(&payload),
+            if let Some(m) = memo {
+                format!("--memo {}", shell.quote(m))
+            } else {
+                "".to_string()
+            }
+        )
+    }
+
+    /// The arguments to `ledger` that submit the plan, unquoted, to run it
+    /// directly rather than through a shell.
+    pub fn ledger_args(
+        &self,
+        ledger: &Ledger,
+        pem: &Path,
+        memo: Option<&str>,
+        canonical: bool,
+    ) -> Vec<String> {
+        let payload = if canonical {
+            self.canonical_payload()
+        } else {
+            self.payload("")
+        };
+        let mut args = vec![
+            "--pem".to_string(),
+            pem.display().to_string(),
+            ledger.url.clone(),
+            "token".to_string(),
+            "mint".to_string(),
+            ledger.token.clone(),
+            payload,
+        ];
+        if let Some(memo) = memo {
+            args.extend(["--memo".to_string(), memo.to_string()]);
+        }
+        args
+    }
+
+    /// Record the run in a new mint file, holding the negatives of the amounts,
+    /// update the lifetime totals and log the run's summary. Returns the path
+    /// of the mint file.
+    pub fn write(
+        &self,
+        storage: &dyn Storage,
+        date: DateTime<Local>,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let output = PathBuf::from(format!("mint-{}.json", date.format("%Y%m%d-%H%M%S")));
+        self.commit_state(storage)?;
+        write_verified(storage, &output, &mint_file(&self.amounts)?)?;
+        totals::update(storage)?;
+        self.record(storage, &output, &self.amounts)?;
+        Ok(output)
+    }
+
+    /// Record the mint file `path`, for `amounts`: its checksum and those of
+    /// the run's inputs, the rates of the conversions it minted, and the run
+    /// in the log.
+    fn record(
+        &self,
+        storage: &dyn Storage,
+        path: &Path,
+        amounts: &BTreeMap<String, u64>,
+    ) -> Result<(), anyhow::Error> {
+        let conversions = self
+            .conversions
+            .iter()
+            .filter(|c| amounts.contains_key(&c.id))
+            .collect::<Vec<_>>();
+        integrity::record(storage, path)?;
+        convert::pin(storage, path, &conversions)?;
+        history::record(storage, path, amounts, self.seed, &conversions)
+    }
+
+    /// Record a burn of the plan's amounts in a new `burn-<date>.json` file,
+    /// holding the amounts themselves to compensate for what was minted in
+    /// excess. Returns the path of the file.
+    pub fn write_burn(
+        &self,
+        storage: &dyn Storage,
+        date: DateTime<Local>,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let output = PathBuf::from(format!("burn-{}.json", date.format("%Y%m%d-%H%M%S")));
+        self.commit_state(storage)?;
+        write_verified(storage, &output, &self.allocation_file()?)?;
+        Ok(output)
+    }
+
+    /// The content of an allocation file crediting the plan's amounts.
+    pub fn allocation_file(&self) -> Result<String, anyhow::Error> {
+        amounts_file(self.amounts.iter().map(|(id, a)| (id, *a as i128)))
+    }
+
+    /// Like `write`, but if the mint file would be larger than `max_bytes`,
+    /// split it into numbered parts (`mint-<date>-part<n>.json`) of at most
+    /// about that size, and list them in a `mint-<date>.manifest`. Returns
+    /// the paths of the mint files.
+    pub fn write_split(
+        &self,
+        storage: &dyn Storage,
+        date: DateTime<Local>,
+        max_bytes: usize,
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        // The size of the entries in a pretty-printed mint file, with their
+        // indentation and separators.
+        let entry_size = |id: &String, amount: &u64| {
+            Value::from(id.as_str()).to_string().len()
+                + Value::from(format_tokens_short(-(*amount as i128)))
+                    .to_string()
+                    .len()
+                + 6
+        };
+        // The braces and final newline.
+        const ENVELOPE: usize = 4;
+
+        let mut parts = vec![BTreeMap::new()];
+        let mut size = ENVELOPE;
+        for (id, amount) in &self.amounts {
+            let entry = entry_size(id, amount);
+            let part = parts.last_mut().unwrap();
+            if !part.is_empty() && size + entry > max_bytes {
+                parts.push(BTreeMap::new());
+                size = ENVELOPE;
+            }
+            parts.last_mut().unwrap().insert(id.clone(), *amount);
+            size += entry;
+        }
+        if parts.len() == 1 {
+            return Ok(vec![self.write(storage, date)?]);
+        }
+        self.write_parts(storage, date, &parts)
+    }
+
+    /// Split the plan into plans of at most `size` entries each, in payload
+    /// order, for runs submitted in several transactions (`--chunk-size`).
+    pub fn chunks(&self, size: usize) -> Vec<MintPlan> {
+        self.entries
+            .chunks(size.max(1))
+            .map(|entries| Self {
+                amounts: entries.iter().cloned().collect(),
+                entries: entries.to_vec(),
+                state_version: self.state_version,
+                seed: self.seed,
+                conversions: self.conversions.clone(),
+            })
+            .collect()
+    }
+
+    /// Write the mint files of the plan split into `chunks`, as numbered
+    /// parts listed in a manifest like `write_split`. Returns their paths.
+    pub fn write_chunks(
+        &self,
+        storage: &dyn Storage,
+        date: DateTime<Local>,
+        chunks: &[MintPlan],
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        if let [chunk] = chunks {
+            return Ok(vec![chunk.write(storage, date)?]);
+        }
+        let parts = chunks.iter().map(|c| c.amounts.clone()).collect::<Vec<_>>();
+        self.write_parts(storage, date, &parts)
+    }
+
+    fn write_parts(
+        &self,
+        storage: &dyn Storage,
+        date: DateTime<Local>,
+        parts: &[BTreeMap<String, u64>],
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.commit_state(storage)?;
+        let mut paths = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            let path = part_path(date, i);
+            write_verified(storage, &path, &mint_file(part)?)?;
+            paths.push(path);
+        }
+        write_manifest(storage, date, parts)?;
+
+        totals::update(storage)?;
+        for (part, path) in parts.iter().zip(&paths) {
+            self.record(storage, path, part)?;
+        }
+        Ok(paths)
+    }
+
+    /// Record the latest of the parts of a run submitted so far, for runs
+    /// submitted a part at a time (`--trickle`): write its mint file, and
+    /// list it in the run's manifest with the parts before it. The directory
+    /// is committed to with the first part. Returns the path of the part's
+    /// mint file.
+    pub fn write_part(
+        &self,
+        storage: &dyn Storage,
+        date: DateTime<Local>,
+        submitted: &[BTreeMap<String, u64>],
+    ) -> Result<PathBuf, anyhow::Error> {
+        let Some(part) = submitted.last() else {
+            anyhow::bail!("No part to record.");
+        };
+        if submitted.len() == 1 {
+            self.commit_state(storage)?;
+        }
+        let path = part_path(date, submitted.len() - 1);
+        write_verified(storage, &path, &mint_file(part)?)?;
+        write_manifest(storage, date, submitted)?;
+        totals::update(storage)?;
+        self.record(storage, &path, part)?;
+        Ok(path)
+    }
+}
+
+/// The mint file of the part `index` (0-based) of a run.
+fn part_path(date: DateTime<Local>, index: usize) -> PathBuf {
+    PathBuf::from(format!(
+        "mint-{}-part{}.json",
+        date.format("%Y%m%d-%H%M%S"),
+        index + 1
+    ))
+}
+
+/// Write the manifest listing the parts of a run.
+fn write_manifest(
+    storage: &dyn Storage,
+    date: DateTime<Local>,
+    parts: &[BTreeMap<String, u64>],
+) -> Result<(), anyhow::Error> {
+    let manifest = serde_json::json!({
+        "parts": parts.iter().enumerate().map(|(i, part)| serde_json::json!({
+            "file": part_path(date, i).display().to_string(),
+            "recipients": part.len(),
+            "total": part.values().sum::<u64>(),
+        })).collect::<Vec<_>>(),
+        "recipients": parts.iter().map(BTreeMap::len).sum::<usize>(),
+        "total": parts.iter().flat_map(BTreeMap::values).sum::<u64>(),
+    });
+    storage.write(
+        Path::new(&format!(
+            "mint-{}.{MANIFEST_EXTENSION}",
+            date.format("%Y%m%d-%H%M%S")
+        )),
+        format!("{}\n", serde_json::to_string_pretty(&manifest)?).as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// The extension of the manifests listing the parts of split mint files. It
+/// isn't `json`, so manifests aren't read as allocation files.
+pub const MANIFEST_EXTENSION: &str = "manifest";
+
+/// Write a mint or burn file, and check that it reads back as written before
+/// the run is considered committed. A file that doesn't is removed, as it
+/// would be read as an allocation file.
+fn write_verified(storage: &dyn Storage, path: &Path, content: &str) -> Result<(), anyhow::Error> {
+    storage.write(path, content.as_bytes())?;
+    let read = storage.read(path)?;
+    if read != content.as_bytes() || serde_json::from_slice::<Value>(&read).is_err() {
+        storage.remove(path)?;
+        anyhow::bail!("{:?} did not read back as written, and was removed.", path);
+    }
+    Ok(())
+}
+
+/// The content of a mint file, holding the negatives of `amounts`.
+fn mint_file(amounts: &BTreeMap<String, u64>) -> Result<String, anyhow::Error> {
+    amounts_file(amounts.iter().map(|(id, a)| (id, -(*a as i128))))
+}
+
+/// The content of an allocation file holding `amounts`, in tokens.
+fn amounts_file<'a>(
+    amounts: impl Iterator<Item = (&'a String, i128)>,
+) -> Result<String, anyhow::Error> {
+    Ok(format!(
+        "{}\n",
+        serde_json::to_string_pretty(
+            &amounts
+                .map(|(id, amount)| (id.clone(), format_tokens_short(amount)))
+                .collect::<BTreeMap<_, _>>(),
+        )?
+    ))
+}
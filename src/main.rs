@@ -1,230 +1,1364 @@
-use clap::Parser;
-use rand::{thread_rng, Rng};
-use serde_json::Value;
+use chrono::Local;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use many_after8::amounts::AmountFormat;
+use many_after8::scan::{Glob, Scan, Scanned};
+use many_after8::shell::Shell;
+use many_after8::storage::{self, Storage};
+use many_after8::{
+    addressbook, aliases, audit, bundle, calendar, config, deprecations, devnet, disable,
+    ensure_writable, format_tokens, history, input_files, inspect, integrity, interest,
+    is_mint_file, jitter, memo, parse_tokens, periods, plan, preview, priority, progress, prune,
+    receipts, recipients, report, restate, review, rng, rollback, run_report, search, select,
+    session, signatures, supply, totals, treasury, trickle, validate, verify, version, BalanceSet,
+    Ledger, MintOptions, MintPlan, Order, ReadOptions, Status,
+};
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
 use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 
-const DENOMINATOR: f64 = 1_000_000_000.0;
-
 #[derive(Debug, Parser)]
+#[command(next_help_heading = "Global options")]
 struct Opt {
-    /// The directory that contains the JSON files and the PEM file.
-    #[clap(long)]
-    dir: PathBuf,
+    /// The directory that contains the JSON files and the PEM file. Required.
+    #[arg(long, global = true)]
+    dir: Option<PathBuf>,
+
+    /// Only print results, warnings and errors: no run header and no progress
+    /// reports.
+    #[arg(long, short, global = true)]
+    quiet: bool,
+
+    /// Disable every code path that writes to disk or the network. Useful to
+    /// hand the tool to someone who should only inspect the directory.
+    #[arg(
+        long,
+        global = true,
+        env = "MANY_AFTER8_READ_ONLY",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    read_only: bool,
+
+    /// Print a timing report of reading and aggregating the input files.
+    #[arg(long, global = true, visible_alias = "profile")]
+    profile_aggregation: bool,
+
+    /// Warn about amounts that parsing through floats changes by more than
+    /// this many base units.
+    #[arg(long, global = true, default_value = "1")]
+    precision_tolerance: u64,
+
+    /// Don't warn about amounts that parsing through floats changes.
+    #[arg(long, global = true)]
+    no_precision_warnings: bool,
+
+    /// How to report progress on stderr.
+    #[arg(long, global = true, value_enum, default_value = "auto")]
+    progress: progress::ProgressMode,
+
+    /// Read entries whose key isn't a valid MANY identity, instead of
+    /// refusing to. Tokens minted to them can't be spent.
+    #[arg(long, global = true)]
+    allow_unknown_ids: bool,
+
+    /// Also read the input files of subdirectories, except hidden ones and
+    /// the ones this tool writes to.
+    #[arg(long, global = true)]
+    recursive: bool,
 
-    #[clap(subcommand)]
+    /// Only read the input files matching this glob pattern, as in
+    /// `2024/**/*.json`. Patterns without a `/` match file names. Can be
+    /// repeated.
+    #[arg(long, global = true, value_name = "GLOB")]
+    include: Vec<Glob>,
+
+    /// Don't read the input files matching this glob pattern, as in
+    /// `mint-2023*.json`. Can be repeated.
+    #[arg(long, global = true, value_name = "GLOB")]
+    exclude: Vec<Glob>,
+
+    /// Inject failures into storage accesses and submissions, with this
+    /// probability each (see the `chaos` module).
+    #[cfg(feature = "chaos")]
+    #[arg(long, global = true, hide = true, value_name = "RATE")]
+    chaos: Option<f64>,
+
+    /// Seed the failures of `--chaos`, to reproduce them.
+    #[cfg(feature = "chaos")]
+    #[arg(long, global = true, hide = true, requires = "chaos")]
+    chaos_seed: Option<u64>,
+
+    #[command(subcommand)]
     subcommand: Subcommand,
 }
 
 #[derive(Debug, Parser)]
 enum Subcommand {
     /// Output the minting command to run.
-    Mint(MintOpt),
+    Mint(Box<MintOpt>),
+
+    /// Output the command to burn what was minted in excess of allocations.
+    Burn(BurnOpt),
 
     /// Show remaining balances to mint.
     Balances(BalancesOpt),
+
+    /// Show the allocation files and what they contribute.
+    Inspect(inspect::InspectOpt),
+
+    /// Check the input files for errors, reporting every problem found.
+    Validate(validate::ValidateOpt),
+
+    /// Check that all recipients are valid, addressable MANY identities.
+    ValidateRecipients(recipients::ValidateRecipientsOpt),
+
+    /// Generate per-recipient receipts for past mint runs.
+    Receipts(receipts::ReceiptsOpt),
+
+    /// Package everything about a past mint run into one file, optionally
+    /// signed, for auditors.
+    Bundle(bundle::BundleOpt),
+
+    /// Close a budget period, freezing its report.
+    ClosePeriod(periods::ClosePeriodOpt),
+
+    /// Work with saved plans (`mint --format json` or `payload` outputs).
+    Plan(plan::PlanOpt),
+
+    /// Archive recipients that received everything they were allocated.
+    Prune(prune::PruneOpt),
+
+    /// Stop counting an allocation entry, keeping it in its file.
+    Disable(disable::DisableOpt),
+
+    /// Count a disabled allocation entry again.
+    Enable(disable::DisableOpt),
+
+    /// Reports over the mint history.
+    Report(report::ReportOpt),
+
+    /// Audit the mint history.
+    Audit(audit::AuditOpt),
+
+    /// List past mint runs.
+    History(history::HistoryOpt),
+
+    /// Credit back the balances of a mint run.
+    Rollback(rollback::RollbackOpt),
+
+    /// Correct the amounts a past run minted, linking the correction to it.
+    Restate(restate::RestateOpt),
+
+    /// Compare what was minted with the on-chain balances.
+    Verify(verify::VerifyOpt),
+
+    /// Check that the input and mint files recorded by past runs weren't
+    /// modified since.
+    VerifyIntegrity,
+
+    /// Check the signatures of the mint files written with `mint --sign`.
+    VerifySignatures(signatures::VerifySignaturesOpt),
+
+    /// Share recipient metadata between operators.
+    Addressbook(addressbook::AddressbookOpt),
+
+    /// Inspect the tool's configuration.
+    Config(ConfigOpt),
+}
+
+#[derive(Debug, Parser)]
+pub struct ConfigOpt {
+    #[command(subcommand)]
+    subcommand: ConfigSubcommand,
+}
+
+#[derive(Debug, Parser)]
+enum ConfigSubcommand {
+    /// List deprecated flags and their replacements.
+    Deprecations,
+}
+
+/// The output format of `mint` and `burn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// The full command line to run.
+    Command,
+    /// A preview of the run, as a JSON object with a stable schema (see the
+    /// `preview` module).
+    Json,
+    /// Only the JSON payload.
+    Payload,
+    /// The CBOR-encoded arguments of the `tokens.mint` (or `tokens.burn`)
+    /// request, as raw bytes (or hex when writing to a terminal).
+    Cbor,
 }
 
 #[derive(Debug, Parser)]
 pub struct MintOpt {
     /// The maximum amount to mint in one run.
-    #[clap(long, default_value = "100")]
-    max: f64,
+    #[arg(long, default_value = "100", value_parser = tokens_arg, help_heading = "Amounts")]
+    max: u64,
+
+    /// Skip ids that would get less than this amount, so the run isn't
+    /// cluttered with dust. They are minted once their balance adds up.
+    #[arg(long, value_parser = tokens_arg, help_heading = "Amounts")]
+    min: Option<u64>,
+
+    /// List the ids skipped by `--min`, instead of only counting them.
+    #[arg(long, requires = "min", help_heading = "Output")]
+    report_dust: bool,
+
+    /// The maximum amount to mint in one run, across all ids. Runs that would
+    /// mint more are scaled down, proportionally or in the order of
+    /// `priorities.json`.
+    #[arg(long, value_parser = tokens_arg, help_heading = "Amounts")]
+    total_max: Option<u64>,
+
+    /// Bound the run by the minter's balance of the token, queried from the
+    /// ledger with the `ledger` binary. Recipients are filled in the order
+    /// of `priorities.json`, and what they aren't minted carries to the next
+    /// run.
+    #[arg(long, help_heading = "Amounts")]
+    bounded_by_minter: bool,
 
     /// Whether to save a new JSON file containing the negatives of the balances
     /// we have minted.
-    #[clap(long)]
+    #[arg(long, help_heading = "Run")]
     dry_run: bool,
 
     /// Whether to randomize the amount, within 20% of the maximum. Each id
     /// will have a different randomized maximum.
-    #[clap(long)]
+    #[arg(long, help_heading = "Amounts")]
     randomize: bool,
 
-    /// A memo to pass to the minting command.
-    #[clap(long)]
+    /// When randomizing, the range of the factor applied to the maximum of
+    /// each id. Entries of `jitter.json` override it for their ids.
+    #[arg(
+        long,
+        requires = "randomize",
+        value_name = "LOW..HIGH",
+        default_value = "0.8..1.2",
+        help_heading = "Amounts"
+    )]
+    randomize_range: jitter::Range,
+
+    /// When randomizing, how the factors are drawn within the range. `normal`
+    /// is centered on its middle, the range being 4 standard deviations wide.
+    #[arg(long, requires = "randomize", value_enum, default_value_t = jitter::Distribution::Uniform, help_heading = "Amounts")]
+    randomize_dist: jitter::Distribution,
+
+    /// When randomizing, rescale the amounts so the total of the run matches
+    /// the total without randomization exactly.
+    #[arg(long, requires = "randomize", help_heading = "Amounts")]
+    preserve_total: bool,
+
+    /// Add bounded Laplace noise to each amount, with this privacy budget
+    /// (epsilon) per run. Smaller values hide amounts better. The noise is
+    /// scaled to `--max` and bounded to half of it. Mint files record what was
+    /// actually minted, so later runs make up for the noise and the lifetime
+    /// totals stay exact.
+    #[arg(long, conflicts_with = "randomize", value_parser = positive_f64, help_heading = "Amounts")]
+    noise: Option<f64>,
+
+    /// Seed the random draws of `--randomize`, `--noise` and `--order
+    /// shuffle`, so a dry run and the real run give the same plan. A random
+    /// seed is picked and printed if none is given. The seed is recorded in
+    /// `runs.log`.
+    #[arg(long, help_heading = "Amounts")]
+    seed: Option<u64>,
+
+    /// Where the random draws come from: the thread's generator, a generator
+    /// seeded with `--seed`, or, with `hmac`, seeded for each id from `--run-id`
+    /// and the id, so the same run id gives every id the same draws,
+    /// whichever machine computes the plan.
+    #[arg(long, value_enum, default_value_t = rng::RngKind::Seeded, help_heading = "Amounts")]
+    rng: rng::RngKind,
+
+    /// The run id the draws of `--rng hmac` are derived from, as in `2024-03`.
+    #[arg(long, required_if_eq("rng", "hmac"), help_heading = "Amounts")]
+    run_id: Option<String>,
+
+    /// Query the supply of the token with `--ledger`. Runs that would mint
+    /// past the maximum supply are refused.
+    #[arg(long, group = "supply_source", help_heading = "Amounts")]
+    check_supply: bool,
+
+    /// Read the supply of the token from a file instead, as JSON with its
+    /// `maximum` and `circulating` supply in base units, e.g. offline. It is
+    /// refused if it was saved before the last run.
+    #[arg(long, group = "supply_source", help_heading = "Amounts")]
+    supply: Option<PathBuf>,
+
+    /// With `--check-supply` or `--supply`, scale the run down to what is
+    /// left of the maximum supply, instead of refusing it.
+    #[arg(long, requires = "supply_source", help_heading = "Amounts")]
+    truncate_to_supply: bool,
+
+    /// The order of the entries in the generated payload. Alphabetical order
+    /// leaks information about our internal recipient list.
+    #[arg(long, value_enum, default_value = "id", help_heading = "Output")]
+    order: Order,
+
+    /// A memo to pass to the minting command. It can refer to the run with
+    /// {total}, {recipients}, {date} and {amount:<ID>}.
+    #[arg(long, help_heading = "Ledger")]
     memo: Option<String>,
 
-    /// Only output JSON, not the full command line.
-    #[clap(long)]
+    #[command(flatten, next_help_heading = "Ledger")]
+    memo_format: memo::MemoFormat,
+
+    /// The output format.
+    #[arg(long, value_enum, default_value = "command", help_heading = "Output")]
+    format: Format,
+
+    /// Deprecated, use `--format json`.
+    #[arg(long, hide = true, help_heading = "Output")]
     json: bool,
 
+    /// Emit the JSON payload in canonical form: keys sorted, no whitespace.
+    /// Overrides `--order`.
+    #[arg(long, conflicts_with = "order", help_heading = "Output")]
+    canonical: bool,
+
+    /// Write a machine-readable report of the run to this file once it
+    /// succeeded: its date, seed, amounts, input file checksums and
+    /// commands. The file must be outside the data directory.
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    report: Option<PathBuf>,
+
+    /// The format of the report. Without --report, the report is printed
+    /// instead of the usual output.
+    #[arg(long, value_enum, help_heading = "Output")]
+    report_format: Option<run_report::ReportFormat>,
+
+    /// Only warn, instead of refusing to mint, during a blackout window of
+    /// `calendar.yaml`.
+    #[arg(long, help_heading = "Run")]
+    override_blackout: bool,
+
+    /// The shell to quote the command line for. Defaults to PowerShell on
+    /// Windows and POSIX shells elsewhere.
+    #[arg(long, value_enum, help_heading = "Output")]
+    shell: Option<Shell>,
+
     /// The pem file to use for the command line.
-    #[clap(long)]
+    #[arg(long, help_heading = "Ledger")]
     pem: PathBuf,
+
+    /// Submit the mint request by running `ledger` directly, instead of
+    /// printing the command line. The mint file is only written once it
+    /// succeeds.
+    #[arg(long, conflicts_with_all = ["dry_run", "format", "json", "shell"], help_heading = "Ledger")]
+    execute: bool,
+
+    #[command(flatten, next_help_heading = "Ledger")]
+    target: Ledger,
+
+    #[command(flatten, next_help_heading = "Recipients")]
+    select: select::SelectOpt,
+
+    /// Split the mint file into numbered parts, listed in a manifest, when it
+    /// would be larger than this many bytes.
+    #[arg(long, default_value = "1048576", help_heading = "Run")]
+    max_file_size: usize,
+
+    /// Split the run into commands of at most this many ids, each recorded in
+    /// its own numbered mint file, so large runs stay within the ledger's
+    /// request size limits.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "trickle", help_heading = "Run")]
+    chunk_size: Option<u32>,
+
+    /// Acknowledge that this is the first run in the directory, which is
+    /// otherwise refused. A first run also prints an extended preview.
+    #[arg(long, help_heading = "Run")]
+    bootstrap: bool,
+
+    /// Review the plan before anything is written or printed: skip
+    /// recipients, or change their amounts, then confirm the run.
+    #[arg(long, help_heading = "Run")]
+    interactive: bool,
+
+    /// Sign each mint file the run writes with `--pem`, using `openssl`, in
+    /// a `<mint file>.sig` next to it that `verify-signatures` checks.
+    #[arg(long, help_heading = "Run")]
+    sign: bool,
+
+    /// The `openssl` binary to sign with.
+    #[arg(
+        long,
+        default_value = "openssl",
+        requires = "sign",
+        help_heading = "Run"
+    )]
+    openssl: PathBuf,
+
+    /// The `ledger` binary to run with `--execute`, to create the token with
+    /// `--devnet`, or to query the minter's balance with
+    /// `--bounded-by-minter`.
+    #[arg(long, default_value = "ledger", help_heading = "Ledger")]
+    ledger: PathBuf,
+
+    /// Mint a throwaway token on a ledger running locally, created on first
+    /// use, without stopping for blackout windows or the first run
+    /// confirmation. For scratch directories only.
+    #[arg(long, conflicts_with_all = ["url", "token"], help_heading = "Ledger")]
+    devnet: bool,
+
+    /// With `--execute`, mint to each recipient in its own transaction, at
+    /// most at this rate (e.g. `10/min`, `1/s` or `100/h`), instead of all at
+    /// once. Each transaction is recorded as soon as it succeeds.
+    #[arg(long, requires = "execute", help_heading = "Ledger")]
+    trickle: Option<trickle::Rate>,
 }
 
 #[derive(Debug, Parser)]
-pub struct BalancesOpt {}
-
-fn read_all_jsons(root: impl AsRef<Path>) -> Result<BTreeMap<String, u64>, anyhow::Error> {
-    // Read all the JSON files.
-    let mut balance = BTreeMap::<String, i128>::new();
-
-    for entry in std::fs::read_dir(root).unwrap() {
-        let entry = entry?;
-        let path = entry.path();
-        if path.extension().unwrap() == "json" {
-            let data = std::fs::read_to_string(&path).unwrap();
-            let data: BTreeMap<String, Value> = serde_json::from_str(&data).unwrap();
-            for (name, value) in data {
-                let tokens = match &value {
-                    Value::Number(n) => n.as_f64(),
-                    Value::String(s) => s.replace(',', "").parse::<f64>().ok(),
-                    x => {
-                        panic!("Invalid value type '{}' in file '{:?}'", x, path);
-                    }
-                };
-                if let Some(tokens) = tokens {
-                    // A small sanity check. This means that a period was missed or
-                    // something.
-                    if tokens > DENOMINATOR {
-                        panic!("Invalid token amount '{}' in file '{:?}'", value, path);
-                    }
-
-                    let tokens = (tokens * DENOMINATOR) as i128;
-                    let curr = balance.entry(name).or_default();
-
-                    // Make sure we don't end up with a negative or too small balance.
-                    let new = *curr + tokens;
-                    *curr = new;
-                } else {
-                    panic!("Invalid token amount '{}' in file '{:?}'", value, path);
-                }
-            }
-        }
-    }
+pub struct BurnOpt {
+    /// Do not write the compensating file.
+    #[arg(long, help_heading = "Run")]
+    dry_run: bool,
 
-    Ok(balance
-        .into_iter()
-        .filter_map(|(k, v)| {
-            if v >= u64::MAX as i128 {
-                panic!("Balance for '{}' is too large", k);
-            }
+    /// A memo to pass to the burn command. It can refer to the burn with
+    /// {total}, {recipients}, {date} and {amount:<ID>}.
+    #[arg(long, help_heading = "Ledger")]
+    memo: Option<String>,
 
-            if v > 0 {
-                Some((k, v as u64))
-            } else {
-                None
-            }
-        })
-        .collect())
+    #[command(flatten, next_help_heading = "Ledger")]
+    memo_format: memo::MemoFormat,
+
+    /// The output format.
+    #[arg(long, value_enum, default_value = "command", help_heading = "Output")]
+    format: Format,
+
+    /// Emit the JSON payload in canonical form: keys sorted, no whitespace.
+    #[arg(long, help_heading = "Output")]
+    canonical: bool,
+
+    /// The shell to quote the command line for. Defaults to PowerShell on
+    /// Windows and POSIX shells elsewhere.
+    #[arg(long, value_enum, help_heading = "Output")]
+    shell: Option<Shell>,
+
+    /// The pem file to use for the command line.
+    #[arg(long, help_heading = "Ledger")]
+    pem: PathBuf,
+
+    #[command(flatten, next_help_heading = "Ledger")]
+    target: Ledger,
+}
+
+#[derive(Debug, Parser)]
+pub struct BalancesOpt {
+    /// Also show the total minted so far to each identity.
+    #[arg(long)]
+    minted: bool,
+
+    /// Search the balances interactively, by identity or alias (the `alias`
+    /// field of `recipients.json`), instead of listing them all.
+    #[arg(long)]
+    interactive: bool,
+
+    /// Show the amounts of the allocation entries with this status instead,
+    /// e.g. `draft` to list what still needs sign-off.
+    #[arg(long, value_enum, default_value = "approved")]
+    status: Status,
+
+    #[command(flatten)]
+    select: select::SelectOpt,
+
+    #[command(flatten)]
+    amounts: AmountFormat,
+}
+
+/// Parse an amount of tokens into base units.
+fn tokens_arg(s: &str) -> Result<u64, String> {
+    parse_tokens(s)
+        .and_then(|t| u64::try_from(t).ok())
+        .filter(|t| *t > 0)
+        .ok_or_else(|| format!("expected a positive amount of tokens, got '{s}'"))
+}
+
+fn positive_f64(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+        _ => Err(format!("expected a positive number, got '{s}'")),
+    }
 }
 
 fn mint(
-    root: impl AsRef<Path>,
-    balances: BTreeMap<String, u64>,
+    storage: &dyn Storage,
+    balances: BalanceSet,
     opts: MintOpt,
+    read_only: bool,
+    quiet: bool,
+    progress: &progress::Progress,
 ) -> Result<(), anyhow::Error> {
+    if !opts.dry_run {
+        ensure_writable(read_only, "write a mint file (use --dry-run)")?;
+    }
+
     let now = chrono::Local::now();
 
-    let mut rand = thread_rng();
-    eprintln!("Minting tokens...");
-    eprintln!("Date: {}", now.to_rfc2822());
-    eprintln!("Flags: {opts:?}");
-    eprintln!();
+    if let Some(blackout) = calendar::active(storage, now.date_naive())? {
+        if opts.dry_run || opts.override_blackout || opts.devnet {
+            eprintln!("warning: minting during a {blackout}.");
+        } else {
+            anyhow::bail!(
+                "Refusing to mint during a {blackout}. Use --override-blackout to proceed."
+            );
+        }
+    }
+
+    if !quiet {
+        eprintln!("Minting tokens...");
+        eprintln!("Date: {}", now.to_rfc2822());
+        eprintln!("Flags: {opts:?}");
+        eprintln!();
+    }
 
     let MintOpt {
         dry_run,
         memo,
+        memo_format,
         randomize,
+        randomize_range,
+        randomize_dist,
+        preserve_total,
+        noise,
+        seed,
+        rng,
+        run_id,
+        check_supply,
+        supply,
+        truncate_to_supply,
+        order,
         max,
+        min,
+        report_dust,
+        total_max,
+        bounded_by_minter,
+        format,
         json,
+        canonical,
+        report: report_path,
+        report_format,
+        override_blackout: _,
+        shell,
         pem,
+        execute,
+        ledger,
+        bootstrap,
+        interactive,
+        sign,
+        openssl,
+        max_file_size,
+        chunk_size,
+        mut target,
+        select,
+        trickle,
+        devnet,
     } = opts;
-    let max = (max * DENOMINATOR) as u64;
+    let signer = sign.then(|| signatures::Signer {
+        openssl,
+        pem: pem.clone(),
+    });
+    if devnet {
+        target.url = devnet::DEVNET_URL.to_string();
+        target.token = devnet::token(storage, &ledger, &pem, read_only)?;
+    }
+    if json {
+        deprecations::warn("mint --json");
+    }
+    let format = if json { Format::Json } else { format };
+    let balances = balances.selected(&select.selection(storage)?);
+    if chunk_size.is_some() && format != Format::Command {
+        anyhow::bail!("--chunk-size only supports --format command.");
+    }
+    let jitter = jitter::Jitter {
+        range: randomize_range,
+        dist: randomize_dist,
+    };
+    let options = MintOptions {
+        max,
+        randomize,
+        jitter,
+        jitter_overrides: if randomize {
+            jitter::load(storage, jitter)?
+        } else {
+            BTreeMap::new()
+        },
+        preserve_total,
+        noise,
+        order,
+    };
 
-    let to_mint = balances
-        .into_iter()
-        .map(|(id, balance)| {
-            let max = if randomize {
-                ((max as f64) * rand.gen_range(0.8..1.2)) as u64
+    if seed.is_some() && rng != rng::RngKind::Seeded {
+        anyhow::bail!("--seed only applies to --rng seeded.");
+    }
+    let mut plan = match (rng, run_id) {
+        (rng::RngKind::Hmac, Some(run_id)) => {
+            MintPlan::new(&balances, &options, &mut rng::Hmac::new(&run_id))
+        }
+        (rng::RngKind::Thread, _) => MintPlan::new(&balances, &options, &mut thread_rng()),
+        _ => {
+            let seed = seed.unwrap_or_else(|| thread_rng().gen());
+            let plan = MintPlan::new(&balances, &options, &mut StdRng::seed_from_u64(seed));
+            if options.is_random() {
+                if !quiet {
+                    eprintln!("Seed: {seed} (use --seed {seed} to compute the same plan)");
+                }
+                plan.with_seed(seed)
             } else {
-                max
-            };
-            (id, balance.min(max))
-        })
-        .collect::<BTreeMap<_, _>>();
+                plan
+            }
+        }
+    };
+    if let Some(min) = min {
+        let dust;
+        (plan, dust) = plan.without_dust(min);
+        if !quiet && !dust.is_empty() {
+            eprintln!(
+                "Skipping {} id(s) with less than --min {} tokens to mint ({} tokens).",
+                dust.len(),
+                format_tokens(min as i128),
+                format_tokens(dust.values().sum::<u64>() as i128)
+            );
+        }
+        if report_dust {
+            for (id, amount) in &dust {
+                eprintln!("  {}\t{}", id, format_tokens(*amount as i128));
+            }
+        }
+    }
+    let priorities = priority::load(storage)?;
+    if let Some(total_max) = total_max.filter(|t| plan.total() > *t) {
+        if !quiet {
+            eprintln!(
+                "The run would mint {} tokens, scaling it down to --total-max {}.",
+                format_tokens(plan.total() as i128),
+                format_tokens(total_max as i128)
+            );
+        }
+        plan = plan.capped_by(total_max, &priorities);
+    }
+    if bounded_by_minter {
+        let funds = treasury::minter_balance(&ledger, &pem, &target)?;
+        if plan.total() > funds {
+            let total = plan.total();
+            plan = plan.capped_by(funds, &priorities);
+            eprintln!(
+                "warning: the run would mint {} tokens, but the minter only holds {}. {} tokens carry to the next run.",
+                format_tokens(total as i128),
+                format_tokens(funds as i128),
+                format_tokens((total - plan.total()) as i128)
+            );
+        }
+    }
+    let supply = match &supply {
+        Some(path) => Some(supply::read(storage, path)?),
+        None if check_supply => Some(supply::query(&ledger, &target)?),
+        None => None,
+    };
+    if let Some(supply) = supply {
+        if let Some(headroom) = supply.headroom().filter(|h| plan.total() > *h) {
+            let message = format!(
+                "The run would mint {} tokens, but only {} are left of the maximum supply",
+                format_tokens(plan.total() as i128),
+                format_tokens(headroom as i128)
+            );
+            if !truncate_to_supply {
+                anyhow::bail!("{message}. Use --truncate-to-supply to mint what is left.");
+            }
+            eprintln!("warning: {message}, scaling the run down.");
+            plan = plan.capped_by(headroom, &priorities);
+        }
+    }
 
-    let longest = to_mint
+    let longest = plan
+        .amounts()
         .values()
-        .map(|s| format!("{:.09}", *s as f64 / DENOMINATOR).len())
+        .map(|s| format_tokens(*s as i128).len())
         .max()
         .unwrap_or(0);
-    to_mint.iter().for_each(|(id, s)| {
-        eprintln!(
-            "{}\t{:>longest$}",
-            id,
-            format!("{:.09}", (*s as f64) / DENOMINATOR),
-        );
+    plan.amounts().iter().for_each(|(id, s)| {
+        eprintln!("{}\t{:>longest$}", id, format_tokens(*s as i128),);
     });
 
     eprintln!("--------------------------------------------------");
 
+    let aliases = aliases::load(storage)?;
+    if interactive {
+        let stdin = std::io::stdin();
+        match review::review(plan, &balances, &aliases, &mut stdin.lock())? {
+            Some(reviewed) => plan = reviewed,
+            None => {
+                eprintln!("Aborted, nothing was written.");
+                return Ok(());
+            }
+        }
+    }
+
+    let memo = match memo {
+        Some(template) => Some(memo::render(&template, &plan, now, &aliases, &memo_format)?),
+        None => None,
+    };
+
+    let first = !input_files(storage)?.iter().any(is_mint_file);
+    if first && !plan.is_empty() {
+        preview(&plan);
+        if !dry_run && !bootstrap && !devnet {
+            anyhow::bail!(
+                "This would be the first run in this directory. Review the preview above, and use --bootstrap to proceed."
+            );
+        }
+    }
+
+    let shell = shell.unwrap_or_else(Shell::detect);
+    let chunks = chunk_size.map(|size| plan.chunks(size as usize));
+    let report = if report_path.is_some() || report_format.is_some() {
+        let commands = match &chunks {
+            Some(chunks) => chunks
+                .iter()
+                .map(|chunk| chunk.command(&target, &pem, memo.as_deref(), shell, canonical))
+                .collect(),
+            None => vec![plan.command(&target, &pem, memo.as_deref(), shell, canonical)],
+        };
+        let run = run_report::Run {
+            date: now,
+            dry_run,
+            executed: execute,
+            commands,
+        };
+        Some(run_report::report(storage, &plan, &target, run)?)
+    } else {
+        None
+    };
+
+    if execute {
+        let submission = Submission {
+            binary: &ledger,
+            ledger: &target,
+            pem: &pem,
+            memo: memo.as_deref(),
+            canonical,
+            signer: signer.as_ref(),
+        };
+        match (trickle, chunk_size) {
+            (Some(rate), _) => submit_trickle(storage, &plan, &submission, rate, now, progress)?,
+            (None, Some(size)) if plan.entries().len() > size as usize => {
+                submit_chunks(storage, &plan, &submission, size as usize, now, progress)?
+            }
+            _ => submit(storage, &plan, &submission, now, max_file_size, progress)?,
+        };
+        if let Some(report) = &report {
+            emit_report(report, report_path.as_deref())?;
+        }
+        return Ok(());
+    }
+
     if !dry_run {
         // Commit a new file to disk.
-        let output = root
-            .as_ref()
-            .join(format!("mint-{}.json", now.format("%Y%m%d-%H%M%S")));
+        let paths = match &chunks {
+            Some(chunks) => plan.write_chunks(storage, now, chunks)?,
+            None => plan.write_split(storage, now, max_file_size)?,
+        };
+        if paths.len() > 1 {
+            eprintln!("Split the mint file into {} parts.", paths.len());
+        }
+        for path in &paths {
+            sign_run(storage, signer.as_ref(), path)?;
+        }
+    }
 
-        writeln!(
-            File::create(output).unwrap(),
+    if let Some(report) = &report {
+        emit_report(report, report_path.as_deref())?;
+        if report_path.is_none() {
+            return Ok(());
+        }
+    }
+
+    if format == Format::Json {
+        let run = preview::Run {
+            date: now,
+            dry_run,
+            memo: memo.as_deref(),
+        };
+        let preview = preview::preview(&plan, &target, &run, canonical);
+        if canonical {
+            println!("{preview}");
+        } else {
+            println!("{}", serde_json::to_string_pretty(&preview)?);
+        }
+    } else if format == Format::Payload {
+        if canonical {
+            println!("{}", plan.canonical_payload());
+        } else {
+            println!("{}", plan.payload("  "));
+        }
+    } else if format == Format::Cbor {
+        print_cbor(&plan.cbor(&target, memo.as_deref())?)?;
+    } else if let Some(chunks) = chunks.filter(|c| c.len() > 1) {
+        // Output a command line per chunk, each of which can be run alone.
+        for (i, chunk) in chunks.iter().enumerate() {
+            println!(
+                "# Part {} of {}, {} id(s), {} tokens",
+                i + 1,
+                chunks.len(),
+                chunk.entries().len(),
+                format_tokens(chunk.total() as i128)
+            );
+            println!(
+                "{}",
+                chunk.command(&target, &pem, memo.as_deref(), shell, canonical)
+            );
+        }
+    } else if !plan.is_empty() {
+        // Output the command line to run.
+        println!(
             "{}",
-            serde_json::to_string_pretty(
-                &to_mint
-                    .iter()
-                    .map(|(id, amount)| (
-                        id.clone(),
-                        (-((*amount as f64) / DENOMINATOR)).to_string()
-                    ))
-                    .collect::<BTreeMap<_, _>>(),
-            )?,
-        )?;
+            plan.command(&target, &pem, memo.as_deref(), shell, canonical)
+        );
     }
 
-    if json {
-        println!("{}", serde_json::to_string_pretty(&to_mint)?);
-    } else if !to_mint.is_empty() {
-        let to_mint = to_mint
-            .iter()
-            .map(|(id, amount)| format!(r#"    "{}": {}"#, id, amount))
-            .collect::<Vec<_>>()
-            .join(",\n");
-        let to_mint = format!("{{\n{}\n}}", to_mint);
+    Ok(())
+}
 
-        // Output the command line to run.
-        println!("ledger --pem {} https://alberto.app/api token mint mqbh742x4s356ddaryrxaowt4wxtlocekzpufodvowrirfrqaaaaa3l '{}' {}", pem.display(), to_mint, if let Some(m) = memo {
-            format!("--memo '{m}'")
+/// Write the report of a run to `path`, or print it if there is none.
+/// Refuse to write the report of a run in the data directory, where the next
+/// run would read it as an allocation file.
+fn check_report_path(dir: &Path, path: &Path) -> Result<(), anyhow::Error> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if let (Ok(dir), Ok(parent)) = (dir.canonicalize(), parent.canonicalize()) {
+        if parent.starts_with(dir) {
+            anyhow::bail!(
+                "Refusing to write the report {:?} in the data directory, where it would be read as an allocation file.",
+                path
+            );
+        }
+    }
+    Ok(())
+}
+
+fn emit_report(report: &serde_json::Value, path: Option<&Path>) -> Result<(), anyhow::Error> {
+    let text = format!("{}\n", serde_json::to_string_pretty(report)?);
+    match path {
+        Some(path) => std::fs::write(path, text)
+            .map_err(|e| anyhow::anyhow!("Could not write {:?}: {}", path, e)),
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+/// Output CBOR bytes, as hex on a terminal.
+fn print_cbor(bytes: &[u8]) -> Result<(), anyhow::Error> {
+    let mut stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        let hex = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        writeln!(stdout, "{hex}")?;
+    } else {
+        stdout.write_all(bytes)?;
+    }
+    Ok(())
+}
+
+/// Burn what was minted to identities in excess of their allocations, e.g.
+/// after an allocation was reduced. The compensating file holds the excess,
+/// bringing their balances back to zero.
+fn burn(
+    storage: &dyn Storage,
+    balances: BalanceSet,
+    opts: BurnOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    if !opts.dry_run {
+        ensure_writable(read_only, "write a burn file (use --dry-run)")?;
+    }
+
+    let plan = MintPlan::from_amounts(balances.overminted().clone()).based_on(&balances);
+    if plan.is_empty() {
+        eprintln!("Nothing to burn.");
+        return Ok(());
+    }
+
+    let now = chrono::Local::now();
+    eprintln!("Burning tokens...");
+    eprintln!("Date: {}", now.to_rfc2822());
+    eprintln!("Flags: {opts:?}");
+    eprintln!();
+    for (id, amount) in plan.amounts() {
+        eprintln!("{}\t{}", id, format_tokens(*amount as i128));
+    }
+    eprintln!("--------------------------------------------------");
+
+    let memo = match &opts.memo {
+        Some(template) => Some(memo::render(
+            template,
+            &plan,
+            now,
+            &aliases::load(storage)?,
+            &opts.memo_format,
+        )?),
+        None => None,
+    };
+    if !opts.dry_run {
+        let path = plan.write_burn(storage, now)?;
+        eprintln!("Recorded the burn in {}.", path.display());
+    }
+
+    if opts.format == Format::Json {
+        let run = preview::Run {
+            date: now,
+            dry_run: opts.dry_run,
+            memo: memo.as_deref(),
+        };
+        let preview = preview::burn_preview(&plan, &opts.target, &run, opts.canonical);
+        if opts.canonical {
+            println!("{preview}");
+        } else {
+            println!("{}", serde_json::to_string_pretty(&preview)?);
+        }
+    } else if opts.format == Format::Cbor {
+        print_cbor(&plan.burn_cbor(&opts.target, memo.as_deref())?)?;
+    } else if opts.format == Format::Payload {
+        if opts.canonical {
+            println!("{}", plan.canonical_payload());
         } else {
-            "".to_string()
-        });
+            println!("{}", plan.payload("  "));
+        }
+    } else {
+        let shell = opts.shell.unwrap_or_else(Shell::detect);
+        println!(
+            "{}",
+            plan.burn_command(
+                &opts.target,
+                &opts.pem,
+                memo.as_deref(),
+                shell,
+                opts.canonical
+            )
+        );
+    }
+    Ok(())
+}
+
+/// The number of largest recipients listed in the preview of a first run.
+const PREVIEW_LARGEST: usize = 5;
+
+/// An extended summary of the plan, for first runs.
+fn preview(plan: &MintPlan) {
+    eprintln!("First run in this directory:");
+    eprintln!("  recipients:  {}", plan.amounts().len());
+    eprintln!("  total:       {}", format_tokens(plan.total() as i128));
+    let mut largest = plan.amounts().iter().collect::<Vec<_>>();
+    largest.sort_by_key(|(id, amount)| (std::cmp::Reverse(**amount), *id));
+    for (id, amount) in largest.into_iter().take(PREVIEW_LARGEST) {
+        eprintln!("  largest:     {}\t{}", id, format_tokens(*amount as i128));
+    }
+    eprintln!("--------------------------------------------------");
+}
+
+/// How to run `ledger` with `--execute`.
+struct Submission<'a> {
+    binary: &'a Path,
+    ledger: &'a Ledger,
+    pem: &'a Path,
+    memo: Option<&'a str>,
+    canonical: bool,
+    signer: Option<&'a signatures::Signer>,
+}
+
+/// Sign a mint file of the run, with `--sign`.
+fn sign_run(
+    storage: &dyn Storage,
+    signer: Option<&signatures::Signer>,
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    if let Some(signer) = signer {
+        let signature = signer.sign_file(storage, path)?;
+        eprintln!("Signed {} in {}.", path.display(), signature.display());
+    }
+    Ok(())
+}
+
+impl Submission<'_> {
+    /// Run `ledger` to submit `plan`. The transaction result goes to our
+    /// stdout, errors to our stderr.
+    fn run(&self, plan: &MintPlan) -> Result<std::process::ExitStatus, anyhow::Error> {
+        #[cfg(feature = "chaos")]
+        many_after8::chaos::inject("network error, the request wasn't sent")?;
+        let status = std::process::Command::new(self.binary)
+            .args(plan.ledger_args(self.ledger, self.pem, self.memo, self.canonical))
+            .status()
+            .map_err(|e| anyhow::anyhow!("Could not run {:?}: {}", self.binary, e))?;
+        #[cfg(feature = "chaos")]
+        if status.success() {
+            many_after8::chaos::inject("network error, the response was lost")?;
+        }
+        Ok(status)
+    }
+}
+
+/// Run `ledger` to submit the plan, then record the run.
+fn submit(
+    storage: &dyn Storage,
+    plan: &MintPlan,
+    submission: &Submission,
+    now: chrono::DateTime<Local>,
+    max_file_size: usize,
+    progress: &progress::Progress,
+) -> Result<(), anyhow::Error> {
+    if plan.is_empty() {
+        eprintln!("Nothing to mint.");
+        return Ok(());
+    }
+
+    eprintln!("Submitting to {}...", submission.ledger.url);
+    let status = submission.run(plan)?;
+    if !status.success() {
+        anyhow::bail!(
+            "{:?} failed ({}), nothing was recorded.",
+            submission.binary,
+            status
+        );
     }
+    batch_submitted(progress, 0, 1, plan);
 
+    for path in plan.write_split(storage, now, max_file_size)? {
+        eprintln!("Recorded the run in {}.", path.display());
+        sign_run(storage, submission.signer, &path)?;
+    }
+    Ok(())
+}
+
+/// Run `ledger` once per recipient of the plan, in payload order and at most
+/// at `rate`, recording each transaction as a part of the run once it
+/// succeeds.
+fn submit_trickle(
+    storage: &dyn Storage,
+    plan: &MintPlan,
+    submission: &Submission,
+    rate: trickle::Rate,
+    now: chrono::DateTime<Local>,
+    progress: &progress::Progress,
+) -> Result<(), anyhow::Error> {
+    if plan.is_empty() {
+        eprintln!("Nothing to mint.");
+        return Ok(());
+    }
+
+    let count = plan.entries().len();
+    eprintln!(
+        "Submitting to {} in {count} transaction(s), at most {rate}...",
+        submission.ledger.url
+    );
+    progress.start("Submitting", count);
+    let mut submitted = Vec::new();
+    for (i, (id, amount)) in plan.entries().iter().enumerate() {
+        if i > 0 {
+            std::thread::sleep(rate.interval());
+        }
+        let part = BTreeMap::from([(id.clone(), *amount)]);
+        let part_plan = MintPlan::from_amounts(part.clone());
+        let status = submission.run(&part_plan)?;
+        if !status.success() {
+            progress.finish();
+            anyhow::bail!(
+                "{:?} failed ({}) minting to {id}, {i} of {count} transaction(s) were recorded.",
+                submission.binary,
+                status
+            );
+        }
+        batch_submitted(progress, i, count, &part_plan);
+        submitted.push(part);
+        let path = plan.write_part(storage, now, &submitted)?;
+        eprintln!("[{}/{count}] Recorded {id} in {}.", i + 1, path.display());
+        sign_run(storage, submission.signer, &path)?;
+    }
+    progress.finish();
+    Ok(())
+}
+
+/// Run `ledger` once per chunk of at most `size` ids, recording each chunk as
+/// a part of the run once it succeeds.
+fn submit_chunks(
+    storage: &dyn Storage,
+    plan: &MintPlan,
+    submission: &Submission,
+    size: usize,
+    now: chrono::DateTime<Local>,
+    progress: &progress::Progress,
+) -> Result<(), anyhow::Error> {
+    if plan.is_empty() {
+        eprintln!("Nothing to mint.");
+        return Ok(());
+    }
+
+    let chunks = plan.chunks(size);
+    let count = chunks.len();
+    eprintln!(
+        "Submitting to {} in {count} transaction(s)...",
+        submission.ledger.url
+    );
+    progress.start("Submitting", count);
+    let mut submitted = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let status = submission.run(chunk)?;
+        if !status.success() {
+            progress.finish();
+            anyhow::bail!(
+                "{:?} failed ({}) on part {}, {i} of {count} part(s) were recorded.",
+                submission.binary,
+                status,
+                i + 1
+            );
+        }
+        batch_submitted(progress, i, count, chunk);
+        submitted.push(chunk.amounts().clone());
+        let path = plan.write_part(storage, now, &submitted)?;
+        eprintln!("[{}/{count}] Recorded {}.", i + 1, path.display());
+        sign_run(storage, submission.signer, &path)?;
+    }
+    progress.finish();
     Ok(())
 }
 
+/// Report that the transaction `index` of `total`, minting `batch`, went
+/// through.
+fn batch_submitted(progress: &progress::Progress, index: usize, total: usize, batch: &MintPlan) {
+    progress.event(
+        "batch_submitted",
+        serde_json::json!({
+            "index": index + 1,
+            "total": total,
+            "entries": batch.entries().len(),
+            "amount": batch.total().to_string(),
+        }),
+    );
+    progress.tick(index + 1);
+}
+
 fn balances(
-    _root: impl AsRef<Path>,
-    balances: BTreeMap<String, u64>,
-    _opts: BalancesOpt,
+    storage: &dyn Storage,
+    balances: BalanceSet,
+    opts: BalancesOpt,
 ) -> Result<(), anyhow::Error> {
-    for (id, balance) in balances {
+    let balances = balances.selected(&opts.select.selection(storage)?);
+    let rule = interest::load(storage)?.filter(|_| opts.status == Status::Approved);
+    let accrued = match &rule {
+        Some(rule) => interest::accrued(storage, rule, Local::now())?,
+        None => BTreeMap::new(),
+    };
+    let minted = if opts.minted {
+        totals::lifetime(storage)?
+    } else {
+        BTreeMap::new()
+    };
+    let tokens = |amount| opts.amounts.format(amount);
+    let metadata = if opts.interactive {
+        recipients::load_metadata(storage)?
+    } else {
+        BTreeMap::new()
+    };
+
+    let mut entries = Vec::new();
+    for (id, balance) in balances.iter() {
         if balance > 0 {
-            println!("{}: {:0.9}", id, (balance as f64) / DENOMINATOR);
+            let mut notes = Vec::new();
+            if let Some(interest) = accrued.get(id) {
+                notes.push(format!("incl. {} interest", tokens(*interest)));
+            }
+            if opts.minted {
+                let minted = minted.get(id).copied().unwrap_or_default();
+                notes.push(format!("{} minted so far", tokens(minted as i128)));
+            }
+            let line = if notes.is_empty() {
+                format!("{}: {}", id, tokens(balance as i128))
+            } else {
+                format!("{}: {} ({})", id, tokens(balance as i128), notes.join(", "))
+            };
+            let alias = metadata
+                .get(id)
+                .and_then(|m| m.get("alias"))
+                .and_then(|a| a.as_str());
+            entries.push(search::Entry {
+                keys: std::iter::once(id.to_string())
+                    .chain(alias.map(str::to_string))
+                    .collect(),
+                line: match alias {
+                    Some(alias) => format!("{line} [{alias}]"),
+                    None => line,
+                },
+            });
+        }
+    }
+
+    if opts.interactive {
+        search::interactive(&entries)?;
+    } else {
+        for entry in &entries {
+            println!("{}", entry.line);
         }
     }
+
+    if let Some(rule) = rule {
+        eprintln!();
+        eprintln!(
+            "Interest: {}, {} accrued in total.",
+            interest::describe(&rule),
+            tokens(accrued.values().sum::<i128>())
+        );
+    }
     Ok(())
 }
 
+/// Parse the command line, with the defaults of the directory's configuration
+/// file. Returns the options and the directory.
+fn parse_with_config() -> Result<(Opt, PathBuf), anyhow::Error> {
+    let dir = Opt::command()
+        .ignore_errors(true)
+        .get_matches()
+        .get_one::<PathBuf>("dir")
+        .cloned();
+    let config = match dir {
+        Some(dir) => config::Config::load(&storage::FsStorage::new(&dir))?,
+        None => config::Config::default(),
+    };
+    let mut command = config.apply(Opt::command());
+    let matches = command.get_matches_mut();
+    let opts = Opt::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // `--dir` is global, so it can't be marked as required.
+    let Some(dir) = opts.dir.clone() else {
+        command
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the following required argument was not provided: --dir <DIR>",
+            )
+            .exit()
+    };
+    Ok((opts, dir))
+}
+
 fn main() -> Result<(), anyhow::Error> {
-    let opts = Opt::parse();
-    let root = &opts.dir;
-    let b = read_all_jsons(root)?;
+    let (opts, dir) = parse_with_config()?;
+    let read_only = opts.read_only;
+    let scan = Scan {
+        recursive: opts.recursive,
+        include: opts.include.clone(),
+        exclude: opts.exclude.clone(),
+    };
+    let storage: Box<dyn Storage> = if read_only {
+        Box::new(Scanned {
+            storage: storage::ReadOnly(storage::FsStorage::new(&dir)),
+            scan,
+        })
+    } else {
+        Box::new(Scanned {
+            storage: storage::FsStorage::new(&dir),
+            scan,
+        })
+    };
+    #[cfg(feature = "chaos")]
+    let storage: Box<dyn Storage> = match opts.chaos {
+        Some(rate) => {
+            many_after8::chaos::install(rate, opts.chaos_seed);
+            Box::new(many_after8::chaos::Chaotic(storage))
+        }
+        None => storage,
+    };
+    let storage = storage.as_ref();
+
+    if let Subcommand::Mint(mint_opts) = &opts.subcommand {
+        if let Some(path) = &mint_opts.report {
+            ensure_writable(read_only, "write a run report")?;
+            check_report_path(&dir, path)?;
+        }
+    }
+
+    version::check(storage, read_only)?;
+    if !read_only {
+        session::record(storage)?;
+    }
+    // Closing and reopening periods must work even if a closed period doesn't
+    // match its report anymore.
+    if !matches!(opts.subcommand, Subcommand::ClosePeriod(_)) {
+        periods::check(storage)?;
+    }
+
+    // Validation reports the problems that reading the balances would stop
+    // at, so it must run before.
+    if let Subcommand::Validate(opts) = opts.subcommand {
+        return validate::validate(storage, opts);
+    }
+    if let Subcommand::ValidateRecipients(recipients_opts) = opts.subcommand {
+        return recipients::validate_recipients(storage, recipients_opts, opts.allow_unknown_ids);
+    }
+
+    // Hold the lock from reading the balances to writing the mint file, or
+    // while pruning rewrites files.
+    let _lock = match &opts.subcommand {
+        Subcommand::Mint(mint_opts) if !mint_opts.dry_run => Some(storage::lock(storage)?),
+        Subcommand::Burn(BurnOpt { dry_run: false, .. }) => Some(storage::lock(storage)?),
+        Subcommand::Rollback(rollback::RollbackOpt { dry_run: false, .. }) => {
+            Some(storage::lock(storage)?)
+        }
+        Subcommand::Restate(restate::RestateOpt { dry_run: false, .. }) => {
+            Some(storage::lock(storage)?)
+        }
+        Subcommand::Prune(prune::PruneOpt { dry_run: false, .. }) => Some(storage::lock(storage)?),
+        Subcommand::Disable(_) | Subcommand::Enable(_) => Some(storage::lock(storage)?),
+        _ => None,
+    };
+    let options = ReadOptions {
+        profile: opts.profile_aggregation,
+        precision_tolerance: (!opts.no_precision_warnings).then_some(opts.precision_tolerance),
+        status: match &opts.subcommand {
+            Subcommand::Balances(balances) => balances.status,
+            _ => Status::Approved,
+        },
+        allow_unknown_ids: opts.allow_unknown_ids,
+    };
+    let progress = progress::Progress::new(if opts.quiet {
+        progress::ProgressMode::None
+    } else {
+        opts.progress
+    });
+    let b = BalanceSet::read(storage, &options, &progress)?;
 
     match opts.subcommand {
-        Subcommand::Mint(opts) => mint(root, b, opts),
-        Subcommand::Balances(opts) => balances(root, b, opts),
+        Subcommand::Mint(mint_opts) => {
+            mint(storage, b, *mint_opts, read_only, opts.quiet, &progress)
+        }
+        Subcommand::Burn(opts) => burn(storage, b, opts, read_only),
+        Subcommand::Balances(opts) => balances(storage, b, opts),
+        Subcommand::Inspect(opts) => inspect::inspect(storage, opts),
+        Subcommand::Validate(_) => unreachable!(),
+        Subcommand::ValidateRecipients(_) => unreachable!(),
+        Subcommand::Receipts(opts) => receipts::receipts(storage, opts, read_only),
+        Subcommand::Bundle(opts) => bundle::bundle(storage, opts, read_only),
+        Subcommand::ClosePeriod(opts) => periods::close_period(storage, opts, read_only),
+        Subcommand::Plan(opts) => plan::plan(opts, read_only),
+        Subcommand::Prune(opts) => prune::prune(storage, opts, read_only),
+        Subcommand::Disable(opts) => disable::disable(storage, opts, read_only),
+        Subcommand::Enable(opts) => disable::enable(storage, opts, read_only),
+        Subcommand::Report(opts) => report::report(storage, b.into(), opts),
+        Subcommand::Audit(opts) => audit::audit(storage, opts),
+        Subcommand::History(opts) => history::history(storage, opts),
+        Subcommand::Rollback(opts) => rollback::rollback(storage, opts, read_only),
+        Subcommand::Restate(opts) => restate::restate(storage, opts, read_only),
+        Subcommand::Verify(opts) => verify::verify(storage, opts),
+        Subcommand::VerifyIntegrity => integrity::verify_integrity(storage),
+        Subcommand::VerifySignatures(opts) => signatures::verify_signatures(storage, opts),
+        Subcommand::Addressbook(opts) => addressbook::addressbook(storage, opts, read_only),
+        Subcommand::Config(opts) => match opts.subcommand {
+            ConfigSubcommand::Deprecations => {
+                deprecations::list();
+                Ok(())
+            }
+        },
     }
 }
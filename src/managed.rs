@@ -0,0 +1,107 @@
+//! JSON allocation files as the tool rewrites them (`disable`, `enable` and
+//! `prune`). Their entries keep the order they were written in, so edits
+//! only change the entries they are about. Annotations go in a `_comments`
+//! section, which readers skip and edits keep:
+//!
+//! ```json
+//! {
+//!   "_comments": { "alice": "Q3 grant, see the board minutes of 2024-06-12" },
+//!   "alice": "1200"
+//! }
+//! ```
+use crate::storage::Storage;
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde_json::Value;
+use std::path::Path;
+
+/// The key of the section of an allocation file holding comments. Its value
+/// can be anything, and isn't read.
+pub const COMMENTS_KEY: &str = "_comments";
+
+/// The entries of a JSON file, in file order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entries(Vec<(String, Value)>);
+
+impl Entries {
+    pub fn read(storage: &dyn Storage, path: &Path) -> Result<Self, anyhow::Error> {
+        serde_json::from_str(&storage.read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid {:?}: {}", path, e))
+    }
+
+    /// Write the entries back, in their order.
+    pub fn write(&self, storage: &dyn Storage, path: &Path) -> Result<(), anyhow::Error> {
+        storage.write(
+            path,
+            format!("{}\n", serde_json::to_string_pretty(self)?).as_bytes(),
+        )
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(key, _)| key)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut Value)> {
+        self.0.iter_mut().map(|(key, value)| (&*key, value))
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let index = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.remove(index).1)
+    }
+
+    /// Whether only the comments and the effective date are left.
+    pub fn has_no_amounts(&self) -> bool {
+        self.keys()
+            .all(|k| k == COMMENTS_KEY || k == crate::EFFECTIVE_KEY)
+    }
+}
+
+impl FromIterator<(String, Value)> for Entries {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Entries {
+    type Item = (String, Value);
+    type IntoIter = std::vec::IntoIter<(String, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl serde::Serialize for Entries {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Entries {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor;
+
+        impl<'de> Visitor<'de> for EntriesVisitor {
+            type Value = Entries;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Entries, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Entries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
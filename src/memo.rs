@@ -0,0 +1,139 @@
+//! Memo templates. The `--memo` of `mint` can refer to the run:
+//!
+//! - `{total}`: the total minted by the run,
+//! - `{recipients}`: the number of recipients,
+//! - `{date}`: the date of the run, as in `2024-01-01`,
+//! - `{amount:<id>}`: the amount minted to an identity or alias.
+//!
+//! `{{` and `}}` stand for literal braces. Memos are read by humans on
+//! explorers, so amounts in them are rendered with `--memo-locale` and
+//! `--memo-precision` rather than with the 9 decimals of the ledger.
+use crate::aliases::{self, Aliases};
+use crate::{MintPlan, DECIMALS};
+use chrono::{DateTime, Local};
+
+/// How the digits of amounts are separated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Locale {
+    /// No grouping, with a decimal point, e.g. `1234567.5`.
+    #[default]
+    Plain,
+    /// English, e.g. `1,234,567.5`.
+    En,
+    /// German, e.g. `1.234.567,5`.
+    De,
+    /// French, with narrow no-break spaces, e.g. `1 234 567,5`.
+    Fr,
+    /// Swiss, e.g. `1'234'567.5`.
+    Ch,
+}
+
+impl Locale {
+    /// The separator of groups of thousands, and the decimal separator.
+    fn separators(self) -> (&'static str, char) {
+        match self {
+            Locale::Plain => ("", '.'),
+            Locale::En => (",", '.'),
+            Locale::De => (".", ','),
+            Locale::Fr => ("\u{202f}", ','),
+            Locale::Ch => ("'", '.'),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::Args)]
+pub struct MemoFormat {
+    /// How to separate the digits of amounts in the memo.
+    #[arg(long = "memo-locale", value_enum, default_value = "plain")]
+    pub locale: Locale,
+
+    /// The number of decimals of amounts in the memo, rounded half away
+    /// from zero. By default, amounts are shown without trailing zeros.
+    #[arg(long = "memo-precision", value_parser = clap::value_parser!(u32).range(0..=DECIMALS as i64))]
+    pub precision: Option<u32>,
+}
+
+impl MemoFormat {
+    /// Format an amount in base units.
+    pub fn format(&self, units: u64) -> String {
+        let units = units as u128;
+        let (precision, units) = match self.precision {
+            Some(precision) => {
+                let step = 10u128.pow(DECIMALS - precision);
+                (precision, (units + step / 2) / step)
+            }
+            None => {
+                let mut precision = DECIMALS;
+                let mut units = units;
+                while precision > 0 && units.is_multiple_of(10) {
+                    precision -= 1;
+                    units /= 10;
+                }
+                (precision, units)
+            }
+        };
+        let scale = 10u128.pow(precision);
+        let (group, point) = self.locale.separators();
+        let whole = (units / scale).to_string();
+        let mut out = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                out.push_str(group);
+            }
+            out.push(digit);
+        }
+        if precision > 0 {
+            out.push(point);
+            out.push_str(&format!(
+                "{:0width$}",
+                units % scale,
+                width = precision as usize
+            ));
+        }
+        out
+    }
+}
+
+/// Render a memo template for the run of `plan` on `date`.
+pub fn render(
+    template: &str,
+    plan: &MintPlan,
+    date: DateTime<Local>,
+    aliases: &Aliases,
+    format: &MemoFormat,
+) -> Result<String, anyhow::Error> {
+    let mut memo = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        memo.push_str(&rest[..i]);
+        let brace = &rest[i..i + 1];
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            memo.push_str(brace);
+            rest = after;
+            continue;
+        }
+        if brace == "}" {
+            anyhow::bail!("Unmatched '}}' in the memo. Use '}}}}' for a literal brace.");
+        }
+        let Some((placeholder, after)) = rest.split_once('}') else {
+            anyhow::bail!("Unmatched '{{' in the memo. Use '{{{{' for a literal brace.");
+        };
+        rest = after;
+        match placeholder.split_once(':') {
+            None if placeholder == "total" => memo.push_str(&format.format(plan.total())),
+            None if placeholder == "recipients" => memo.push_str(&plan.entries().len().to_string()),
+            None if placeholder == "date" => memo.push_str(&date.format("%Y-%m-%d").to_string()),
+            Some(("amount", name)) => {
+                let id = aliases::resolve(aliases, name);
+                let Some(amount) = plan.amounts().get(&id) else {
+                    anyhow::bail!("The memo refers to {name}, which is not part of this run.");
+                };
+                memo.push_str(&format.format(*amount));
+            }
+            _ => anyhow::bail!("Unknown placeholder {{{placeholder}}} in the memo."),
+        }
+    }
+    memo.push_str(rest);
+    Ok(memo)
+}
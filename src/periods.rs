@@ -0,0 +1,244 @@
+//! Budget periods. An optional `periods.json` in the data directory sets the
+//! period length (`{"length": "monthly"}` or `{"length": "quarterly"}`).
+//! Closing a period freezes a report of every file dated within it under
+//! `periods/`. From then on, files added to, removed from or modified within a
+//! closed period are refused until the period is reopened.
+//!
+//! Reports freeze the SHA-256 of each file. Reports closed by earlier
+//! versions hold a 64-bit FNV-1a hash instead, which is still accepted for
+//! them, but is no protection against deliberate edits: reopen and close
+//! those periods again to freeze them with SHA-256.
+use crate::session::fnv1a;
+use crate::storage::Storage;
+use crate::{
+    ensure_writable, format_tokens, input_files, is_mint_file, read_json, run_date, sha256,
+};
+use chrono::{DateTime, Datelike, Local};
+use clap::Parser;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const PERIODS_FILE: &str = "periods.json";
+pub const PERIODS_DIR: &str = "periods";
+
+#[derive(Debug, Parser)]
+pub struct ClosePeriodOpt {
+    /// The period to close, e.g. `2024-03` (monthly) or `2024-Q1` (quarterly).
+    period: String,
+
+    /// Reopen a closed period instead, deleting its frozen report.
+    #[arg(long)]
+    reopen: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Length {
+    Monthly,
+    Quarterly,
+}
+
+impl Length {
+    fn period_of(self, date: DateTime<Local>) -> String {
+        match self {
+            Length::Monthly => date.format("%Y-%m").to_string(),
+            Length::Quarterly => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+        }
+    }
+
+    fn is_valid(self, period: &str) -> bool {
+        let Some((year, rest)) = period.split_once('-') else {
+            return false;
+        };
+        let number = match self {
+            Length::Monthly => rest.parse::<u32>().ok().filter(|m| (1..=12).contains(m)),
+            Length::Quarterly => rest
+                .strip_prefix('Q')
+                .and_then(|q| q.parse::<u32>().ok())
+                .filter(|q| (1..=4).contains(q)),
+        };
+        year.len() == 4 && year.parse::<u32>().is_ok() && number.is_some() && rest.len() == 2
+    }
+}
+
+fn load(storage: &dyn Storage) -> Result<Option<Length>, anyhow::Error> {
+    let path = Path::new(PERIODS_FILE);
+    if !storage.exists(path) {
+        return Ok(None);
+    }
+    let value: Value = serde_json::from_str(&storage.read_to_string(path)?)?;
+    match value["length"].as_str() {
+        Some("monthly") => Ok(Some(Length::Monthly)),
+        Some("quarterly") => Ok(Some(Length::Quarterly)),
+        _ => anyhow::bail!(
+            "Invalid period length in {:?}, expected monthly or quarterly.",
+            path
+        ),
+    }
+}
+
+fn report_path(period: &str) -> PathBuf {
+    Path::new(PERIODS_DIR).join(format!("{period}.json"))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The input files dated within each period, by file name.
+fn files_by_period(
+    storage: &dyn Storage,
+    length: Length,
+) -> Result<BTreeMap<String, BTreeMap<String, PathBuf>>, anyhow::Error> {
+    let mut periods = BTreeMap::<String, BTreeMap<String, PathBuf>>::new();
+    for path in input_files(storage)? {
+        periods
+            .entry(length.period_of(run_date(storage, &path)?))
+            .or_default()
+            .insert(file_name(&path), path);
+    }
+    Ok(periods)
+}
+
+fn hash(storage: &dyn Storage, path: &Path) -> Result<String, anyhow::Error> {
+    Ok(sha256::hex_digest(&storage.read(path)?))
+}
+
+/// Whether the file is as `frozen`, its hash in a report.
+fn is_unchanged(storage: &dyn Storage, path: &Path, frozen: &str) -> Result<bool, anyhow::Error> {
+    // The FNV-1a hashes of reports closed by earlier versions.
+    if frozen.len() == 16 {
+        return Ok(frozen == format!("{:016x}", fnv1a(&storage.read(path)?)));
+    }
+    Ok(frozen == hash(storage, path)?)
+}
+
+/// The closed period whose report covers the file, if any.
+pub fn frozen_in(storage: &dyn Storage, path: &Path) -> Result<Option<String>, anyhow::Error> {
+    let name = file_name(path);
+    for report in storage.list(Path::new(PERIODS_DIR))? {
+        let report: Value = serde_json::from_str(&storage.read_to_string(&report)?)?;
+        if report["files"].get(&name).is_some() {
+            return Ok(report["period"].as_str().map(str::to_string));
+        }
+    }
+    Ok(None)
+}
+
+/// Refuse to operate if any closed period no longer matches its frozen report.
+pub fn check(storage: &dyn Storage) -> Result<(), anyhow::Error> {
+    let Some(length) = load(storage)? else {
+        return Ok(());
+    };
+
+    let mut current = files_by_period(storage, length)?;
+    for path in storage.list(Path::new(PERIODS_DIR))? {
+        let report: Value = serde_json::from_str(&storage.read_to_string(&path)?)?;
+        let period = report["period"].as_str().unwrap_or_default();
+        let frozen = report["files"].as_object().cloned().unwrap_or_default();
+        let files = current.remove(period).unwrap_or_default();
+
+        for (name, path) in &files {
+            match frozen.get(name) {
+                None => anyhow::bail!(
+                    "File '{name}' is dated within closed period {period}. Reopen the period with `close-period {period} --reopen` to accept it."
+                ),
+                Some(f) if !is_unchanged(storage, path, f["hash"].as_str().unwrap_or_default())? => anyhow::bail!(
+                    "File '{name}' was modified after period {period} was closed. Reopen the period with `close-period {period} --reopen` to accept it."
+                ),
+                _ => {}
+            }
+        }
+        if let Some(name) = frozen.keys().find(|name| !files.contains_key(*name)) {
+            anyhow::bail!(
+                "File '{name}' from closed period {period} is missing or was re-dated. Reopen the period with `close-period {period} --reopen` to accept it."
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn close_period(
+    storage: &dyn Storage,
+    opts: ClosePeriodOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    let length = load(storage)?.ok_or_else(|| {
+        anyhow::anyhow!("No budget periods configured. Create {PERIODS_FILE} first.")
+    })?;
+    let period = opts.period;
+    if !length.is_valid(&period) {
+        anyhow::bail!("Invalid period '{period}' for {length:?} periods.");
+    }
+    let path = report_path(&period);
+
+    if opts.reopen {
+        ensure_writable(read_only, "reopen a period")?;
+        if !storage.exists(&path) {
+            anyhow::bail!("Period {period} is not closed.");
+        }
+        storage.remove(&path)?;
+        eprintln!("Reopened period {period}.");
+        return Ok(());
+    }
+
+    ensure_writable(read_only, "close a period")?;
+    if storage.exists(&path) {
+        anyhow::bail!("Period {period} is already closed.");
+    }
+    if period >= length.period_of(Local::now()) {
+        anyhow::bail!("Period {period} has not ended yet.");
+    }
+
+    let files = files_by_period(storage, length)?
+        .remove(&period)
+        .unwrap_or_default();
+    let mut frozen = BTreeMap::new();
+    let mut allocated = BTreeMap::<String, i128>::new();
+    let mut minted = BTreeMap::<String, i128>::new();
+    for (name, path) in &files {
+        let target = if is_mint_file(path) {
+            &mut minted
+        } else {
+            &mut allocated
+        };
+        for (id, tokens) in read_json(storage, path)? {
+            *target.entry(id).or_default() += tokens;
+        }
+        frozen.insert(
+            name.clone(),
+            json!({ "date": run_date(storage, path)?.to_rfc3339(), "hash": hash(storage, path)? }),
+        );
+    }
+
+    let format = |amounts: &BTreeMap<String, i128>| {
+        amounts
+            .iter()
+            .map(|(id, t)| (id.clone(), format_tokens(*t)))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let report = json!({
+        "period": period,
+        "closed_at": Local::now().to_rfc3339(),
+        "files": frozen,
+        "allocated": format(&allocated),
+        "minted": format(&minted.iter().map(|(id, t)| (id.clone(), -t)).collect()),
+        "total_allocated": format_tokens(allocated.values().sum::<i128>()),
+        "total_minted": format_tokens(-minted.values().sum::<i128>()),
+    });
+
+    storage.write(
+        &path,
+        (serde_json::to_string_pretty(&report)? + "\n").as_bytes(),
+    )?;
+    eprintln!(
+        "Closed period {} ({} files). Report written to {}.",
+        period,
+        files.len(),
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,357 @@
+//! Plans: the previews `mint --format json` outputs, or the payloads of
+//! `mint --format payload`, i.e. a JSON object of amounts in base units per
+//! identity. They can be saved and compared to review how a change of policy
+//! or flags affects a run.
+//!
+//! `plan show --format toml-sorted` converts a plan to a line per identity,
+//! sorted, for reviews of plans in pull requests to diff cleanly:
+//!
+//! ```toml
+//! # 2 recipients, 15250000000 base units in total.
+//! "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f" = 3250000000
+//! "magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e" = 12000000000
+//! ```
+//!
+//! Plans in that format (`.toml`) can be read back by the other commands.
+//!
+//! `plan comment` adds a reviewer comment to a plan, or a reply to one, in
+//! `<plan>.comments` next to it, a JSON object per line. `plan show` lists
+//! them after the plan, threaded, and marks the comments made on an earlier
+//! version of the plan, so the discussion that led to changes stays with it.
+use crate::amounts::AmountFormat;
+use crate::{ensure_writable, flat, preview, sha256};
+use chrono::{DateTime, Local};
+use clap::Parser;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Parser)]
+pub struct PlanOpt {
+    #[command(subcommand)]
+    subcommand: PlanSubcommand,
+}
+
+#[derive(Debug, Parser)]
+enum PlanSubcommand {
+    /// Show the differences between two plans, per identity.
+    Diff {
+        /// The plan to compare from.
+        a: PathBuf,
+
+        /// The plan to compare to.
+        b: PathBuf,
+
+        #[command(flatten)]
+        amounts: AmountFormat,
+    },
+
+    /// Print a plan in another format.
+    Show {
+        /// The plan to print.
+        plan: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = PlanFormat::TomlSorted)]
+        format: PlanFormat,
+    },
+
+    /// Add a reviewer comment to a plan.
+    Comment {
+        /// The plan to comment on.
+        plan: PathBuf,
+
+        /// The comment.
+        #[arg(long, short)]
+        message: String,
+
+        /// The number of the comment to reply to.
+        #[arg(long)]
+        reply_to: Option<u64>,
+
+        /// The author of the comment. Defaults to $USER.
+        #[arg(long, env = "USER")]
+        author: String,
+    },
+}
+
+/// The formats `plan show` prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlanFormat {
+    /// A JSON object of amounts in base units, as `mint --format payload`.
+    Json,
+    /// A line per identity, sorted, in TOML.
+    TomlSorted,
+}
+
+pub fn plan(opts: PlanOpt, read_only: bool) -> Result<(), anyhow::Error> {
+    match opts.subcommand {
+        PlanSubcommand::Diff { a, b, amounts } => diff(&a, &b, &amounts),
+        PlanSubcommand::Show { plan, format } => {
+            let amounts = read_plan(&plan)?;
+            let comments = format_comments(&digest(&plan)?, &read_comments(&plan)?);
+            match format {
+                // The comments go to stderr, to keep the output a payload.
+                PlanFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&amounts)?);
+                    eprint!("{comments}");
+                }
+                // As TOML comments, the output still reads back as a plan.
+                PlanFormat::TomlSorted => print!("{}{comments}", toml_sorted(&amounts)),
+            }
+            Ok(())
+        }
+        PlanSubcommand::Comment {
+            plan,
+            message,
+            reply_to,
+            author,
+        } => {
+            ensure_writable(read_only, "comment on a plan")?;
+            let comment = add_comment(&plan, &author, &message, reply_to)?;
+            eprintln!("Added comment {} to {}.", comment.number, plan.display());
+            Ok(())
+        }
+    }
+}
+
+/// A reviewer comment on a plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// The number of the comment, from 1.
+    pub number: u64,
+    /// The number of the comment it replies to.
+    pub reply_to: Option<u64>,
+    pub author: String,
+    pub date: DateTime<Local>,
+    pub message: String,
+    /// The SHA-256 of the plan the comment was made on.
+    pub plan_sha256: String,
+}
+
+/// The file the comments on `plan` are kept in.
+pub fn comments_path(plan: &Path) -> PathBuf {
+    let mut name = plan.file_name().unwrap_or_default().to_os_string();
+    name.push(".comments");
+    plan.with_file_name(name)
+}
+
+fn digest(plan: &Path) -> Result<String, anyhow::Error> {
+    let content =
+        std::fs::read(plan).map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", plan, e))?;
+    Ok(sha256::hex_digest(&content))
+}
+
+/// The comments on `plan`, in the order they were made.
+pub fn read_comments(plan: &Path) -> Result<Vec<Comment>, anyhow::Error> {
+    let path = comments_path(plan);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", path, e))?;
+    let mut comments = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = || anyhow::anyhow!("{}:{}: invalid comment", path.display(), i + 1);
+        let value: Value = serde_json::from_str(line).map_err(|_| invalid())?;
+        let text = |key: &str| value[key].as_str().map(str::to_string).ok_or_else(invalid);
+        comments.push(Comment {
+            number: value["number"].as_u64().ok_or_else(invalid)?,
+            reply_to: value["reply_to"].as_u64(),
+            author: text("author")?,
+            date: DateTime::parse_from_rfc3339(&text("date")?)
+                .map_err(|_| invalid())?
+                .with_timezone(&Local),
+            message: text("message")?,
+            plan_sha256: text("plan_sha256")?,
+        });
+    }
+    Ok(comments)
+}
+
+/// Add a comment to `plan`, or a reply to the comment `reply_to`.
+pub fn add_comment(
+    plan: &Path,
+    author: &str,
+    message: &str,
+    reply_to: Option<u64>,
+) -> Result<Comment, anyhow::Error> {
+    read_plan(plan)?;
+    if message.trim().is_empty() {
+        anyhow::bail!("The comment is empty.");
+    }
+    let comments = read_comments(plan)?;
+    if let Some(number) = reply_to.filter(|n| !comments.iter().any(|c| c.number == *n)) {
+        anyhow::bail!("{} has no comment {number}.", plan.display());
+    }
+    let comment = Comment {
+        number: comments.iter().map(|c| c.number).max().unwrap_or(0) + 1,
+        reply_to,
+        author: author.to_string(),
+        date: Local::now(),
+        message: message.trim().to_string(),
+        plan_sha256: digest(plan)?,
+    };
+    let line = json!({
+        "number": comment.number,
+        "reply_to": comment.reply_to,
+        "author": comment.author,
+        "date": comment.date.to_rfc3339(),
+        "message": comment.message,
+        "plan_sha256": comment.plan_sha256,
+    });
+    let path = comments_path(plan);
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{line}"))
+        .map_err(|e| anyhow::anyhow!("Could not write {:?}: {}", path, e))?;
+    Ok(comment)
+}
+
+/// The comments as TOML comment lines, replies under what they reply to.
+/// Comments on another version of the plan than `plan_sha256` are marked.
+pub fn format_comments(plan_sha256: &str, comments: &[Comment]) -> String {
+    fn thread(
+        out: &mut String,
+        plan_sha256: &str,
+        comments: &[Comment],
+        parent: Option<u64>,
+        depth: usize,
+    ) {
+        for comment in comments.iter().filter(|c| c.reply_to == parent) {
+            let earlier = if comment.plan_sha256 == plan_sha256 {
+                ""
+            } else {
+                " (on an earlier version)"
+            };
+            out.push_str(&format!(
+                "# {:indent$}[{}] {}, {}{earlier}: {}\n",
+                "",
+                comment.number,
+                comment.author,
+                comment.date.format("%Y-%m-%d %H:%M"),
+                comment.message.replace('\n', " "),
+                indent = depth * 2
+            ));
+            thread(out, plan_sha256, comments, Some(comment.number), depth + 1);
+        }
+    }
+
+    if comments.is_empty() {
+        return String::new();
+    }
+    let mut out = "#\n# Comments:\n".to_string();
+    thread(&mut out, plan_sha256, comments, None, 0);
+    out
+}
+
+/// A plan in the `toml-sorted` format.
+pub fn toml_sorted(amounts: &BTreeMap<String, u64>) -> String {
+    let mut out = format!(
+        "# {} recipients, {} base units in total.\n",
+        amounts.len(),
+        amounts.values().sum::<u64>()
+    );
+    for (id, amount) in amounts {
+        out.push_str(&format!("{} = {amount}\n", Value::from(id.as_str())));
+    }
+    out
+}
+
+/// Read a plan, returning the amount in base units for each identity.
+pub fn read_plan(path: &Path) -> Result<BTreeMap<String, u64>, anyhow::Error> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", path, e))?;
+    if path
+        .extension()
+        .is_some_and(|ext| ext == crate::TOML_EXTENSION)
+    {
+        return flat::toml(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid plan {:?}: {}", path, e))?
+            .into_iter()
+            .map(
+                |(line, id, amount)| match amount.as_str().and_then(|a| a.parse().ok()) {
+                    Some(amount) => Ok((id, amount)),
+                    None => anyhow::bail!(
+                        "Invalid amount {} for '{}' in {:?} (line {}), expected base units.",
+                        amount,
+                        id,
+                        path,
+                        line
+                    ),
+                },
+            )
+            .collect();
+    }
+    let value: Value = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid plan {:?}: {}", path, e))?;
+    if let Some(amounts) = preview::amounts(&value) {
+        return amounts.map_err(|e| anyhow::anyhow!("Invalid plan {:?}: {}", path, e));
+    }
+    let data: BTreeMap<String, Value> = serde_json::from_value(value)
+        .map_err(|e| anyhow::anyhow!("Invalid plan {:?}: {}", path, e))?;
+    data.into_iter()
+        .map(|(id, amount)| match amount.as_u64() {
+            Some(amount) => Ok((id, amount)),
+            None => anyhow::bail!(
+                "Invalid amount {} for '{}' in {:?}, expected base units.",
+                amount,
+                id,
+                path
+            ),
+        })
+        .collect()
+}
+
+fn diff(a: &Path, b: &Path, amounts: &AmountFormat) -> Result<(), anyhow::Error> {
+    let (a, b) = (read_plan(a)?, read_plan(b)?);
+    let tokens = |amount: u64| amounts.format(amount as i128);
+    let delta = |from: u64, to: u64| {
+        if to >= from {
+            format!("+{}", tokens(to - from))
+        } else {
+            format!("-{}", tokens(from - to))
+        }
+    };
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    let ids = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+    for id in ids {
+        match (a.get(id), b.get(id)) {
+            (None, Some(to)) => {
+                println!("+ {}: {}", id, tokens(*to));
+                added += 1;
+            }
+            (Some(from), None) => {
+                println!("- {}: {}", id, tokens(*from));
+                removed += 1;
+            }
+            (Some(from), Some(to)) if from != to => {
+                println!(
+                    "~ {}: {} -> {} ({})",
+                    id,
+                    tokens(*from),
+                    tokens(*to),
+                    delta(*from, *to)
+                );
+                changed += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let (total_a, total_b) = (a.values().sum::<u64>(), b.values().sum::<u64>());
+    println!(
+        "Total: {} -> {} ({})",
+        tokens(total_a),
+        tokens(total_b),
+        delta(total_a, total_b)
+    );
+    eprintln!("{added} added, {removed} removed, {changed} changed.");
+    Ok(())
+}
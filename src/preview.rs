@@ -0,0 +1,107 @@
+//! The machine-readable preview of a mint run, output by `mint --format
+//! json`. Its schema is stable: fields may be added, but existing fields keep
+//! their meaning until `version` changes.
+//!
+//! ```json
+//! {
+//!   "amounts": [{ "amount": "3250000001", "id": "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f" }],
+//!   "recipients": 1,
+//!   "run": { "date": "2024-01-01T12:00:00+00:00", "dry_run": true, "memo": null, "tool_version": "0.1.0" },
+//!   "schema": "many-after8/mint-preview",
+//!   "token": "mqbh742x4s356ddaryrxaowt4wxtlocekzpufodvowrirfrqaaaaa3l",
+//!   "total": "3250000001",
+//!   "url": "https://alberto.app/api",
+//!   "version": 1
+//! }
+//! ```
+//!
+//! - `amounts` are in payload order, and in base units. They are strings, so
+//!   consumers that read JSON numbers as floats don't lose precision.
+//! - `total` is the sum of `amounts`, in base units, also as a string.
+//! - `run.memo` is `null` when there is no memo.
+//!
+//! `burn --format json` outputs the same preview for the amounts it burns,
+//! with the schema `many-after8/burn-preview`, so it is never read back as
+//! a mint plan.
+use crate::{Ledger, MintPlan};
+use chrono::{DateTime, Local};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// The value of the `schema` field.
+pub const SCHEMA: &str = "many-after8/mint-preview";
+
+/// The value of the `schema` field of burn previews.
+pub const BURN_SCHEMA: &str = "many-after8/burn-preview";
+
+/// The version of the schema.
+pub const VERSION: u64 = 1;
+
+/// The metadata of the run a preview is for.
+pub struct Run<'a> {
+    pub date: DateTime<Local>,
+    pub dry_run: bool,
+    pub memo: Option<&'a str>,
+}
+
+/// The preview of `plan`. `canonical` lists amounts sorted by identity.
+pub fn preview(plan: &MintPlan, ledger: &Ledger, run: &Run, canonical: bool) -> Value {
+    let entries = if canonical {
+        plan.amounts()
+            .iter()
+            .map(|(id, a)| (id.clone(), *a))
+            .collect()
+    } else {
+        plan.entries().to_vec()
+    };
+    json!({
+        "schema": SCHEMA,
+        "version": VERSION,
+        "token": ledger.token,
+        "url": ledger.url,
+        "amounts": entries
+            .iter()
+            .map(|(id, amount)| json!({ "id": id, "amount": amount.to_string() }))
+            .collect::<Vec<_>>(),
+        "recipients": entries.len(),
+        "total": plan.total().to_string(),
+        "run": {
+            "date": run.date.to_rfc3339(),
+            "dry_run": run.dry_run,
+            "memo": run.memo,
+            "tool_version": env!("CARGO_PKG_VERSION"),
+        },
+    })
+}
+
+/// The preview of a burn of the amounts of `plan`.
+pub fn burn_preview(plan: &MintPlan, ledger: &Ledger, run: &Run, canonical: bool) -> Value {
+    let mut preview = preview(plan, ledger, run, canonical);
+    preview["schema"] = json!(BURN_SCHEMA);
+    preview
+}
+
+/// The amounts of a preview, by identity. `None` if `value` isn't a preview
+/// of a supported version.
+pub fn amounts(value: &Value) -> Option<Result<BTreeMap<String, u64>, String>> {
+    if value["schema"] != SCHEMA {
+        return None;
+    }
+    if value["version"] != VERSION {
+        return Some(Err(format!(
+            "unsupported preview version {}, expected {VERSION}",
+            value["version"]
+        )));
+    }
+    let parse = |entry: &Value| {
+        let id = entry["id"].as_str()?;
+        let amount = entry["amount"].as_str()?.parse::<u64>().ok()?;
+        Some((id.to_string(), amount))
+    };
+    Some(
+        value["amounts"]
+            .as_array()
+            .and_then(|a| a.iter().map(parse).collect::<Option<_>>())
+            .ok_or_else(|| "invalid amounts, expected an array of ids and amounts".to_string()),
+    )
+}
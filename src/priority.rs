@@ -0,0 +1,53 @@
+//! The order recipients are served in when a run is capped, by `--total-max`
+//! or by the supply. Without priorities, every recipient is scaled down by the
+//! same proportion. An optional `priorities.json` gives recipients (by
+//! identity or alias) a priority, 1 being the highest:
+//!
+//! ```json
+//! { "alice": 1, "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f": 2 }
+//! ```
+//!
+//! Recipients are then served in order of priority, those without one last.
+//! Recipients of the same priority share what is left proportionally.
+use crate::aliases;
+use crate::storage::Storage;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const PRIORITY_FILE: &str = "priorities.json";
+
+/// The priority of each identity (in textual form) that has one.
+pub type Priorities = BTreeMap<String, u32>;
+
+pub fn load(storage: &dyn Storage) -> Result<Priorities, anyhow::Error> {
+    let path = Path::new(PRIORITY_FILE);
+    if !storage.exists(path) {
+        return Ok(Priorities::new());
+    }
+    let entries: BTreeMap<String, Value> = serde_json::from_str(&storage.read_to_string(path)?)
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid {:?}, expected an object of priorities: {}",
+                path,
+                e
+            )
+        })?;
+    let aliases = aliases::load(storage)?;
+
+    let mut priorities = Priorities::new();
+    for (key, value) in entries {
+        let priority = value
+            .as_u64()
+            .and_then(|p| u32::try_from(p).ok())
+            .filter(|p| *p > 0)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid {:?}: '{key}' is {value}, expected a priority of 1 or more.",
+                    path
+                )
+            })?;
+        priorities.insert(aliases::resolve(&aliases, &key), priority);
+    }
+    Ok(priorities)
+}
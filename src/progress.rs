@@ -0,0 +1,140 @@
+//! Progress reporting for long runs. On a terminal, a progress bar with an
+//! ETA is drawn on stderr. For wrapper UIs, `--progress json` writes events to
+//! stderr instead, one JSON object per line:
+//!
+//! ```json
+//! {"event":"file_parsed","entries":12,"file":"grants.json","index":1,"total":3}
+//! ```
+//!
+//! `mint --execute` reports a `batch_submitted` event after each transaction
+//! that goes through, and draws a bar when submitting in several.
+use serde_json::Value;
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+/// The width of the bar itself, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// How often the bar is redrawn at most.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// A progress bar if stderr is a terminal, nothing otherwise.
+    Auto,
+    /// No progress output.
+    None,
+    /// A progress bar on stderr.
+    Bar,
+    /// Newline-delimited JSON events on stderr.
+    Json,
+}
+
+struct Phase {
+    name: &'static str,
+    total: usize,
+    start: Instant,
+    drawn: Option<Instant>,
+}
+
+pub struct Progress {
+    mode: ProgressMode,
+    phase: RefCell<Option<Phase>>,
+}
+
+impl Progress {
+    pub fn new(mode: ProgressMode) -> Self {
+        let mode = match mode {
+            ProgressMode::Auto if std::io::stderr().is_terminal() => ProgressMode::Bar,
+            ProgressMode::Auto => ProgressMode::None,
+            mode => mode,
+        };
+        Self {
+            mode,
+            phase: RefCell::new(None),
+        }
+    }
+
+    /// Report an event. `fields` must be a JSON object.
+    pub fn event(&self, event: &str, fields: Value) {
+        if self.mode != ProgressMode::Json {
+            return;
+        }
+        // Keep the event name first, as it's what readers dispatch on.
+        let fields = match fields {
+            Value::Object(fields) if !fields.is_empty() => {
+                format!(",{}", &Value::Object(fields).to_string()[1..])
+            }
+            _ => "}".to_string(),
+        };
+        eprintln!("{{\"event\":{}{}", Value::from(event), fields);
+    }
+
+    /// Start a phase of `total` steps, e.g. files to parse.
+    pub fn start(&self, name: &'static str, total: usize) {
+        *self.phase.borrow_mut() = Some(Phase {
+            name,
+            total,
+            start: Instant::now(),
+            drawn: None,
+        });
+    }
+
+    /// Report that `done` steps of the current phase are done.
+    pub fn tick(&self, done: usize) {
+        if self.mode != ProgressMode::Bar {
+            return;
+        }
+        let mut phase = self.phase.borrow_mut();
+        let Some(phase) = phase.as_mut() else {
+            return;
+        };
+        if phase.drawn.is_some_and(|d| d.elapsed() < REDRAW_INTERVAL) && done < phase.total {
+            return;
+        }
+        phase.drawn = Some(Instant::now());
+
+        let fraction = if phase.total == 0 {
+            1.0
+        } else {
+            done as f64 / phase.total as f64
+        };
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let elapsed = phase.start.elapsed().as_secs_f64();
+        let eta = if done > 0 && done < phase.total {
+            let remaining = elapsed / done as f64 * (phase.total - done) as f64;
+            format!("  ETA {}", format_seconds(remaining))
+        } else {
+            String::new()
+        };
+        eprint!(
+            "\r\x1b[K{} [{}{}] {}/{}{}",
+            phase.name,
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            done,
+            phase.total,
+            eta
+        );
+    }
+
+    /// End the current phase, clearing its bar.
+    pub fn finish(&self) {
+        let phase = self.phase.borrow_mut().take();
+        if self.mode == ProgressMode::Bar && phase.is_some_and(|p| p.drawn.is_some()) {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let seconds = seconds.ceil() as u64;
+    if seconds >= 3600 {
+        format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else if seconds >= 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{seconds}s")
+    }
+}
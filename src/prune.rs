@@ -0,0 +1,186 @@
+//! Pruning of recipients that received everything they were allocated. Their
+//! entries are moved out of the allocation and mint files into an archive
+//! under `completed/<date>/`, and `completed/index.json` records when each one
+//! completed. Remaining balances don't change, as only identities whose
+//! entries add up to exactly zero are pruned.
+//!
+//! The files pruned are recorded in `manifest.json`, so `verify-integrity`
+//! and `verify-signatures` only report the changes made to them since. The
+//! signatures of the mint files removed are archived with their entries.
+use crate::managed::{Entries, COMMENTS_KEY};
+use crate::storage::Storage;
+use crate::{
+    aliases, ensure_writable, input_files, integrity, is_mint_file, periods, read_json, run_date,
+    signatures,
+};
+use chrono::Local;
+use clap::Parser;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+pub const COMPLETED_DIR: &str = "completed";
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Parser)]
+pub struct PruneOpt {
+    /// Prune identities with nothing left to mint.
+    #[arg(long)]
+    completed: bool,
+
+    /// Only show what would be pruned.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+fn write_json(storage: &dyn Storage, path: &Path, value: &Value) -> Result<(), anyhow::Error> {
+    storage.write(
+        path,
+        format!("{}\n", serde_json::to_string_pretty(value)?).as_bytes(),
+    )
+}
+
+pub fn prune(storage: &dyn Storage, opts: PruneOpt, read_only: bool) -> Result<(), anyhow::Error> {
+    if !opts.completed {
+        anyhow::bail!("Nothing to prune, use --completed.");
+    }
+    if !opts.dry_run {
+        ensure_writable(read_only, "prune (use --dry-run)")?;
+    }
+
+    let loaded = crate::state::current(storage)?;
+
+    // Net amount and last run of each identity, over every file.
+    let files = input_files(storage)?;
+    let mut net = BTreeMap::<String, i128>::new();
+    let mut completed_on = BTreeMap::<String, String>::new();
+    for path in &files {
+        let date = is_mint_file(path)
+            .then(|| run_date(storage, path))
+            .transpose()?;
+        for (id, tokens) in read_json(storage, path)? {
+            *net.entry(id.clone()).or_default() += tokens;
+            if let Some(date) = date {
+                let date = date.format("%Y-%m-%d").to_string();
+                let last = completed_on.entry(id).or_default();
+                if *last < date {
+                    *last = date;
+                }
+            }
+        }
+    }
+    let completed = net
+        .into_iter()
+        .filter(|(id, net)| *net == 0 && completed_on.contains_key(id))
+        .map(|(id, _)| id)
+        .collect::<BTreeSet<_>>();
+    if completed.is_empty() {
+        eprintln!("No completed recipients to prune.");
+        return Ok(());
+    }
+
+    let aliases = aliases::load(storage)?;
+    let now = Local::now();
+    let archive = Path::new(COMPLETED_DIR).join(now.format("%Y%m%d-%H%M%S").to_string());
+    let mut changes = Vec::<(PathBuf, Entries, Map<String, Value>)>::new();
+    for path in &files {
+        if path.extension().is_some_and(|ext| ext != "json") {
+            if let Some(id) = read_json(storage, path)?
+                .into_keys()
+                .find(|id| completed.contains(id))
+            {
+                anyhow::bail!(
+                    "Pruning would modify {:?} ('{}'), which isn't JSON and isn't rewritten. Remove its completed entries by hand first.",
+                    path,
+                    id
+                );
+            }
+            continue;
+        }
+        let mut kept = Entries::read(storage, path)?;
+        // Entries are moved as they are written, which may be as aliases, hex
+        // or DIDs.
+        let keys = kept
+            .keys()
+            .filter(|key| completed.contains(&aliases::resolve(&aliases, key)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let pruned = keys
+            .into_iter()
+            .filter_map(|key| kept.remove(&key).map(|value| (key, value)))
+            .collect::<Map<_, _>>();
+        if pruned.is_empty() {
+            continue;
+        }
+        if let Some(period) = periods::frozen_in(storage, path)? {
+            anyhow::bail!(
+                "Pruning would modify {:?}, which is frozen in closed period {}. Reopen it first.",
+                path,
+                period
+            );
+        }
+        changes.push((path.clone(), kept, pruned));
+    }
+
+    for id in &completed {
+        println!("{}: completed on {}", id, completed_on[id]);
+    }
+    for (path, kept, pruned) in &changes {
+        eprintln!(
+            "{}: {} entries moved to {}{}",
+            path.display(),
+            pruned.len(),
+            archive.display(),
+            if kept.has_no_amounts() {
+                ", file removed"
+            } else {
+                ""
+            }
+        );
+    }
+    if opts.dry_run {
+        return Ok(());
+    }
+
+    crate::state::commit(storage, loaded)?;
+    let paths = changes
+        .iter()
+        .map(|(path, _, _)| path.clone())
+        .collect::<Vec<_>>();
+    for (path, kept, mut pruned) in changes {
+        if kept.has_no_amounts() {
+            // The comments of a removed file are archived with its entries,
+            // as is its signature.
+            pruned.extend(kept.into_iter().filter(|(k, _)| k == COMMENTS_KEY));
+            write_json(storage, &archive.join(&path), &Value::Object(pruned))?;
+            storage.remove(&path)?;
+            let signature = signatures::signature_path(&path);
+            if storage.exists(&signature) {
+                storage.write(&archive.join(&signature), &storage.read(&signature)?)?;
+                storage.remove(&signature)?;
+            }
+        } else {
+            write_json(storage, &archive.join(&path), &Value::Object(pruned))?;
+            kept.write(storage, &path)?;
+        }
+    }
+
+    let index_path = Path::new(COMPLETED_DIR).join(INDEX_FILE);
+    let mut index: Map<String, Value> = if storage.exists(&index_path) {
+        serde_json::from_str(&storage.read_to_string(&index_path)?)?
+    } else {
+        Map::new()
+    };
+    for id in completed {
+        index.insert(
+            id.clone(),
+            json!({ "completed": completed_on[&id], "archive": archive.display().to_string() }),
+        );
+    }
+    write_json(storage, &index_path, &Value::Object(index))?;
+    integrity::record_prune(storage, now, &paths)?;
+
+    // The lifetime totals no longer match the files.
+    crate::totals::rebuild(storage)?;
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Receipts of past mint runs, one small JSON file per recipient per run,
+//! `receipts/<run>/<id>.json`. With `--pem`, each receipt is signed with
+//! `openssl pkeyutl`, and the signature written next to it as
+//! `<id>.json.sig`, which recipients can check with the operator's public
+//! key, as for bundles.
+use crate::restate;
+use crate::signatures;
+use crate::storage::Storage;
+use crate::{ensure_writable, format_tokens, input_files, is_mint_file, run_date};
+use clap::Parser;
+use serde_json::json;
+use std::path::PathBuf;
+
+/// The default directory receipts are written to.
+pub const RECEIPTS_DIR: &str = "receipts";
+
+#[derive(Debug, Parser)]
+pub struct ReceiptsOpt {
+    /// The directory to write receipts to, relative to the data directory.
+    /// One sub-directory is created per mint run, with one JSON file per
+    /// recipient.
+    #[arg(long, default_value = RECEIPTS_DIR)]
+    out: PathBuf,
+
+    /// Only generate receipts for this mint file.
+    #[arg(long)]
+    run: Option<PathBuf>,
+
+    /// The transaction hash of the run, to include in the receipts. Requires
+    /// `--run`.
+    #[arg(long, requires = "run")]
+    tx_hash: Option<String>,
+
+    /// The memo of the run, to include in the receipts. Requires `--run`.
+    #[arg(long, requires = "run")]
+    memo: Option<String>,
+
+    /// Sign each receipt with this key, using `openssl`.
+    #[arg(long)]
+    pem: Option<PathBuf>,
+
+    /// The `openssl` binary to sign with.
+    #[arg(long, default_value = "openssl", requires = "pem")]
+    openssl: PathBuf,
+}
+
+/// Generate one receipt per recipient per mint run.
+pub fn receipts(
+    storage: &dyn Storage,
+    opts: ReceiptsOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    ensure_writable(read_only, "write receipts")?;
+
+    let runs = match &opts.run {
+        Some(run) => {
+            let path = PathBuf::from(run.file_name().unwrap_or_default());
+            if !is_mint_file(&path) || !storage.exists(&path) {
+                anyhow::bail!("Not a mint file: {:?}", run);
+            }
+            vec![path]
+        }
+        None => input_files(storage)?
+            .into_iter()
+            .filter(|p| is_mint_file(p))
+            .collect(),
+    };
+
+    let restatements = restate::load(storage)?;
+    let mut count = 0;
+    for path in runs {
+        let date = run_date(storage, &path)?;
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let dir = opts.out.join(name.as_ref());
+
+        let restated_by = restatements
+            .get(&path.display().to_string())
+            .into_iter()
+            .flatten()
+            .map(|r| r.file.display().to_string())
+            .collect::<Vec<_>>();
+        for (id, amount) in restate::run_amounts(storage, &path, &restatements)? {
+            // Mint files hold the negative of what was minted.
+            let amount = -amount;
+            let mut receipt = json!({
+                "recipient": id,
+                "amount": format_tokens(amount),
+                "amount_base_units": amount.to_string(),
+                "date": date.to_rfc3339(),
+                "run": path.file_name().unwrap_or_default().to_string_lossy(),
+                "tx_hash": opts.tx_hash,
+                "memo": opts.memo,
+            });
+            if !restated_by.is_empty() {
+                receipt["restated_by"] = json!(restated_by);
+            }
+            let content = serde_json::to_string_pretty(&receipt)? + "\n";
+            let output = dir.join(format!("{id}.json"));
+            storage.write(&output, content.as_bytes())?;
+            if let Some(pem) = &opts.pem {
+                let signature = signatures::sign(&opts.openssl, pem, content.as_bytes())?;
+                storage.write(&signatures::signature_path(&output), &signature)?;
+            }
+            count += 1;
+        }
+    }
+
+    let signed = if opts.pem.is_some() { " signed" } else { "" };
+    eprintln!("Wrote {count}{signed} receipts to {}.", opts.out.display());
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Recipients: validation of the ids in allocation files, and the optional
+//! `recipients.json` metadata file, which holds free-form fields per identity:
+//!
+//! ```json
+//! { "m...": { "alias": "alice", "tags": ["grants", "q1"] } }
+//! ```
+//!
+//! `validate-recipients` checks the ids of the allocation files and of
+//! `recipients.json`, names of `aliases.json` standing for their identity.
+//! With `--live`, the balance of each identity is also queried with the
+//! `ledger` binary (see [`treasury`](crate::treasury)): identities the ledger
+//! refuses are invalid, and the ones that hold nothing are listed, as they
+//! may be mistyped.
+use crate::identity::Identity;
+use crate::storage::Storage;
+use crate::{aliases, input_files, read_json, treasury, Ledger};
+use clap::Parser;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const RECIPIENTS_FILE: &str = "recipients.json";
+
+/// The metadata fields of each recipient, by identity.
+pub type Metadata = BTreeMap<String, Map<String, Value>>;
+
+pub fn load_metadata(storage: &dyn Storage) -> Result<Metadata, anyhow::Error> {
+    let path = Path::new(RECIPIENTS_FILE);
+    if !storage.exists(path) {
+        return Ok(Metadata::new());
+    }
+    serde_json::from_str(&storage.read_to_string(path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid {:?}, expected an object per identity: {}", path, e))
+}
+
+pub fn save_metadata(storage: &dyn Storage, metadata: &Metadata) -> Result<(), anyhow::Error> {
+    storage.write(
+        Path::new(RECIPIENTS_FILE),
+        format!("{}\n", serde_json::to_string_pretty(metadata)?).as_bytes(),
+    )
+}
+
+#[derive(Debug, Parser)]
+pub struct ValidateRecipientsOpt {
+    /// Also check each identity against the network, by querying its balance
+    /// with `--ledger`.
+    #[arg(long)]
+    live: bool,
+
+    /// The `ledger` binary to query balances with.
+    #[arg(long, default_value = "ledger", requires = "live")]
+    ledger: PathBuf,
+
+    #[command(flatten)]
+    target: Ledger,
+}
+
+/// Check that every recipient id is a well-formed MANY identity that can
+/// actually hold tokens. Fails if any id is invalid, unless
+/// `allow_unknown_ids`, which only lists them.
+pub fn validate_recipients(
+    storage: &dyn Storage,
+    opts: ValidateRecipientsOpt,
+    allow_unknown_ids: bool,
+) -> Result<(), anyhow::Error> {
+    let mut recipients = BTreeMap::<String, Vec<String>>::new();
+    for path in input_files(storage)? {
+        for id in read_json(storage, &path)?.into_keys() {
+            recipients
+                .entry(id)
+                .or_default()
+                .push(path.display().to_string());
+        }
+    }
+    let aliases = aliases::load(storage)?;
+    for key in load_metadata(storage)?.into_keys() {
+        recipients
+            .entry(aliases::resolve(&aliases, &key))
+            .or_default()
+            .push(RECIPIENTS_FILE.to_string());
+    }
+
+    let (mut invalid, mut empty) = (0, 0);
+    for (id, files) in &recipients {
+        let problem = match id.parse::<Identity>() {
+            Ok(identity) if !identity.is_addressable() => {
+                Some("anonymous identity cannot hold tokens".to_string())
+            }
+            Ok(_) if opts.live => match treasury::balance_of(&opts.ledger, id, &opts.target) {
+                Ok(0) => {
+                    println!(
+                        "{}: holds nothing on {}, check it isn't mistyped (in {})",
+                        id,
+                        opts.target.url,
+                        files.join(", ")
+                    );
+                    empty += 1;
+                    None
+                }
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            },
+            Ok(_) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(problem) = problem {
+            invalid += 1;
+            println!("{}: {} (in {})", id, problem, files.join(", "));
+        }
+    }
+
+    let empty = if opts.live {
+        format!(", {empty} holding nothing")
+    } else {
+        String::new()
+    };
+    eprintln!(
+        "{} recipients checked, {} invalid{}.",
+        recipients.len(),
+        invalid,
+        empty
+    );
+    if invalid > 0 && !allow_unknown_ids {
+        anyhow::bail!("Found {invalid} invalid recipient(s).");
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! Reports over the mint history. The heatmap shows, for each group of
+//! recipients and each ISO week, how much was minted, followed by what is
+//! left to mint. A recipient's group is the `group` field of its entry in
+//! `recipients.json`.
+use crate::amounts::AmountFormat;
+use crate::recipients::load_metadata;
+use crate::restate;
+use crate::storage::Storage;
+use crate::{input_files, is_mint_file, run_date};
+use clap::Parser;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::IsTerminal;
+
+/// The group of recipients without a `group` field.
+const UNGROUPED: &str = "(ungrouped)";
+
+/// Background colors of the terminal heatmap, from the lowest to the highest
+/// amounts (xterm 256-color greens).
+const COLORS: &[u8] = &[22, 28, 34, 40, 46];
+
+#[derive(Debug, Parser)]
+pub struct ReportOpt {
+    /// A grid of the amounts minted per group and per week.
+    #[arg(long)]
+    heatmap: bool,
+
+    /// Output CSV instead of a table.
+    #[arg(long)]
+    csv: bool,
+
+    #[command(flatten)]
+    amounts: AmountFormat,
+}
+
+pub fn report(
+    storage: &dyn Storage,
+    balances: BTreeMap<String, u64>,
+    opts: ReportOpt,
+) -> Result<(), anyhow::Error> {
+    if !opts.heatmap {
+        anyhow::bail!("Nothing to report, use --heatmap.");
+    }
+
+    let metadata = load_metadata(storage)?;
+    let group_of = |id: &str| {
+        metadata
+            .get(id)
+            .and_then(|m| m.get("group"))
+            .and_then(|g| g.as_str())
+            .unwrap_or(UNGROUPED)
+            .to_string()
+    };
+
+    // Minted amounts by group and by week.
+    let mut grid = BTreeMap::<String, BTreeMap<String, i128>>::new();
+    let mut weeks = BTreeSet::new();
+    let restatements = restate::load(storage)?;
+    for path in input_files(storage)?
+        .into_iter()
+        .filter(|p| is_mint_file(p))
+    {
+        let week = run_date(storage, &path)?.format("%G-W%V").to_string();
+        for (id, amount) in restate::run_amounts(storage, &path, &restatements)? {
+            *grid
+                .entry(group_of(&id))
+                .or_default()
+                .entry(week.clone())
+                .or_default() -= amount;
+        }
+        weeks.insert(week);
+    }
+    let mut remaining = BTreeMap::<String, u64>::new();
+    for (id, balance) in &balances {
+        *remaining.entry(group_of(id)).or_default() += balance;
+        grid.entry(group_of(id)).or_default();
+    }
+
+    let tokens = |amount| opts.amounts.format(amount);
+
+    if opts.csv {
+        let header = std::iter::once("group")
+            .chain(weeks.iter().map(String::as_str))
+            .chain(std::iter::once("remaining"))
+            .collect::<Vec<_>>();
+        println!("{}", header.join(","));
+        for (group, by_week) in &grid {
+            let mut row = vec![csv_field(group)];
+            for week in &weeks {
+                row.push(tokens(by_week.get(week).copied().unwrap_or_default()));
+            }
+            row.push(tokens(
+                remaining.get(group).copied().unwrap_or_default() as i128
+            ));
+            println!("{}", row.join(","));
+        }
+        return Ok(());
+    }
+
+    let color = std::io::stdout().is_terminal();
+    let highest = grid
+        .values()
+        .flat_map(|w| w.values())
+        .copied()
+        .max()
+        .unwrap_or_default();
+    let group_width = grid.keys().map(|g| g.len()).max().unwrap_or(0).max(5);
+    let width = grid
+        .values()
+        .flat_map(|w| w.values().copied())
+        .chain(remaining.values().map(|r| *r as i128))
+        .map(|a| tokens(a).len())
+        .max()
+        .unwrap_or(0)
+        .max(9);
+
+    print!("{:<group_width$}", "group");
+    for week in &weeks {
+        print!("  {:>width$}", week);
+    }
+    println!("  {:>width$}", "remaining");
+    for (group, by_week) in &grid {
+        print!("{:<group_width$}", group);
+        for week in &weeks {
+            let amount = by_week.get(week).copied().unwrap_or_default();
+            let cell = format!("{:>width$}", tokens(amount));
+            if color && amount > 0 && highest > 0 {
+                let level = ((amount * COLORS.len() as i128 - 1) / highest) as usize;
+                print!("  \x1b[30;48;5;{}m{}\x1b[0m", COLORS[level], cell);
+            } else {
+                print!("  {cell}");
+            }
+        }
+        println!(
+            "  {:>width$}",
+            tokens(remaining.get(group).copied().unwrap_or_default() as i128)
+        );
+    }
+    Ok(())
+}
+
+/// Quote a CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
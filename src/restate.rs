@@ -0,0 +1,217 @@
+//! Restating past runs. When a run is found to be wrong, e.g. a recipient
+//! was minted more or less than its mint file says, `restate <run> --set
+//! <id>=<tokens>` records what the run minted in a correction file, instead
+//! of an ad-hoc adjustment file:
+//!
+//! ```json
+//! {
+//!   "_restates": {
+//!     "date": "2024-03-01T09:00:00+00:00",
+//!     "reason": "Bob was minted 90, not 100",
+//!     "run": "mint-20240101-120000.json"
+//!   },
+//!   "magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e": "10"
+//! }
+//! ```
+//!
+//! Corrections are written as `restate-<n>-<mint file>`, and hold the
+//! negative of what they add to the run, like mint files, so the balances
+//! count them as any other input. The reports over the mint history
+//! (`history`, `report`, `audit anomalies`, `receipts` and the lifetime
+//! totals `verify` compares) count runs as restated, and `history` lists the
+//! corrections of each run. A run can be restated again: each correction is
+//! from the run as last restated.
+use crate::history::{self, RUNS_LOG};
+use crate::storage::Storage;
+use crate::{
+    aliases, ensure_writable, format_tokens, format_tokens_short, input_files, is_mint_file,
+    parse_tokens, periods, read_json, state, totals,
+};
+use clap::Parser;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The key of the section of a correction file that links it to its run.
+pub const RESTATES_KEY: &str = "_restates";
+
+#[derive(Debug, Parser)]
+pub struct RestateOpt {
+    /// The mint file of the run, e.g. `mint-20240101-120000.json`. The
+    /// extension can be left out.
+    run: PathBuf,
+
+    /// What the run minted to an identity (or alias), as in `alice=90`. Can
+    /// be repeated.
+    #[arg(long = "set", value_name = "ID=TOKENS", required = true, value_parser = amount_arg)]
+    amounts: Vec<(String, i128)>,
+
+    /// Why the run is restated, recorded with the correction.
+    #[arg(long)]
+    reason: Option<String>,
+
+    /// Do not write the correction file.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+fn amount_arg(s: &str) -> Result<(String, i128), String> {
+    let (id, tokens) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected ID=TOKENS, got '{s}'"))?;
+    let units = parse_tokens(tokens)
+        .filter(|units| *units >= 0)
+        .ok_or_else(|| format!("invalid amount '{tokens}'"))?;
+    Ok((id.to_string(), units))
+}
+
+/// Whether the path is a correction file written by `restate`.
+pub fn is_restatement(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with("restate-") && n.ends_with(".json"))
+}
+
+/// A correction of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Restatement {
+    pub file: PathBuf,
+    pub reason: Option<String>,
+    /// The negative of what the correction adds to the amounts of the run.
+    pub amounts: BTreeMap<String, i128>,
+}
+
+/// The corrections of each run, by mint file, oldest first.
+pub type Restatements = BTreeMap<String, Vec<Restatement>>;
+
+pub fn load(storage: &dyn Storage) -> Result<Restatements, anyhow::Error> {
+    let mut restatements = Restatements::new();
+    for path in input_files(storage)?
+        .into_iter()
+        .filter(|p| is_restatement(p))
+    {
+        let mut file: Value = serde_json::from_str(&storage.read_to_string(&path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid {:?}: {}", path, e))?;
+        let metadata = file[RESTATES_KEY].take();
+        let Some(run) = metadata["run"].as_str() else {
+            anyhow::bail!("{:?} doesn't say which run it restates.", path);
+        };
+        let restatement = Restatement {
+            reason: metadata["reason"].as_str().map(str::to_string),
+            amounts: read_json(storage, &path)?,
+            file: path,
+        };
+        restatements
+            .entry(run.to_string())
+            .or_default()
+            .push(restatement);
+    }
+    Ok(restatements)
+}
+
+/// The amounts of the mint file `path` with its corrections: like in the
+/// mint file, the negative of what the run minted.
+pub fn run_amounts(
+    storage: &dyn Storage,
+    path: &Path,
+    restatements: &Restatements,
+) -> Result<BTreeMap<String, i128>, anyhow::Error> {
+    let mut amounts = read_json(storage, path)?;
+    let corrections = restatements.get(&path.display().to_string());
+    for restatement in corrections.into_iter().flatten() {
+        for (id, amount) in &restatement.amounts {
+            *amounts.entry(id.clone()).or_default() += amount;
+        }
+    }
+    amounts.retain(|_, amount| *amount != 0);
+    Ok(amounts)
+}
+
+pub fn restate(
+    storage: &dyn Storage,
+    opts: RestateOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    if !opts.dry_run {
+        ensure_writable(read_only, "write a restatement (use --dry-run)")?;
+    }
+
+    let loaded = state::current(storage)?;
+    let mut run = PathBuf::from(opts.run.file_name().unwrap_or_default());
+    if run.extension().is_none() {
+        run.set_extension("json");
+    }
+    if !is_mint_file(&run) || !storage.exists(&run) {
+        anyhow::bail!("Not a mint file: {:?}", opts.run);
+    }
+    if let Some(period) = periods::frozen_in(storage, &run)? {
+        eprintln!(
+            "warning: {} is part of the closed period {period}, its report won't reflect the restatement.",
+            run.display()
+        );
+    }
+
+    let aliases = aliases::load(storage)?;
+    let restatements = load(storage)?;
+    let current = run_amounts(storage, &run, &restatements)?;
+    let mut corrections = BTreeMap::new();
+    for (name, units) in opts.amounts {
+        let id = aliases::resolve(&aliases, &name);
+        let minted = -current.get(&id).copied().unwrap_or_default();
+        if units != minted {
+            eprintln!(
+                "{}\t{} -> {}",
+                id,
+                format_tokens(minted),
+                format_tokens(units)
+            );
+            corrections.insert(id, minted - units);
+        }
+    }
+    if corrections.is_empty() {
+        eprintln!("Nothing to restate, the run minted these amounts.");
+        return Ok(());
+    }
+    if opts.dry_run {
+        return Ok(());
+    }
+
+    let now = chrono::Local::now();
+    let number = restatements
+        .get(&run.display().to_string())
+        .map_or(0, Vec::len)
+        + 1;
+    let output = PathBuf::from(format!("restate-{number}-{}", run.display()));
+    if storage.exists(&output) {
+        anyhow::bail!("{} already exists.", output.display());
+    }
+    let mut metadata = json!({ "run": run.display().to_string(), "date": now.to_rfc3339() });
+    if let Some(reason) = &opts.reason {
+        metadata["reason"] = json!(reason);
+    }
+    let mut file = Map::new();
+    file.insert(RESTATES_KEY.to_string(), metadata.clone());
+    for (id, amount) in &corrections {
+        file.insert(id.clone(), json!(format_tokens_short(*amount)));
+    }
+
+    state::commit(storage, loaded)?;
+    storage.write(
+        &output,
+        format!("{}\n", serde_json::to_string_pretty(&file)?).as_bytes(),
+    )?;
+    let entry = json!({
+        "run": output.display().to_string(),
+        "restates": metadata,
+        "corrections": corrections
+            .iter()
+            .map(|(id, amount)| (id.clone(), json!(format_tokens(-amount))))
+            .collect::<Map<_, _>>(),
+        "context": history::context(storage),
+    });
+    storage.append(Path::new(RUNS_LOG), format!("{entry}\n").as_bytes())?;
+    totals::update(storage)?;
+    eprintln!("Restated {} in {}.", run.display(), output.display());
+    Ok(())
+}
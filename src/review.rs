@@ -0,0 +1,98 @@
+//! `mint --interactive`: review the plan before anything is written or
+//! printed. The prompt, on stderr, takes:
+//!
+//! - `yes` to go on with the plan as it is, `no` (or the end of input) to
+//!   abort the run,
+//! - `skip <id>` to leave a recipient out of the run,
+//! - `set <id> <tokens>` to change the amount of a recipient, up to its
+//!   balance,
+//! - `list` to show the plan again.
+//!
+//! Recipients are given by identity, alias or a unique prefix of their
+//! identity. What they aren't minted stays in their balance.
+use crate::aliases::{self, Aliases};
+use crate::{format_tokens, parse_tokens, BalanceSet, MintPlan};
+use std::io::{BufRead, Write};
+
+fn show(plan: &MintPlan) {
+    for (id, amount) in plan.entries() {
+        eprintln!("  {}\t{}", id, format_tokens(*amount as i128));
+    }
+    eprintln!(
+        "  {} recipient(s), {} tokens in total.",
+        plan.entries().len(),
+        format_tokens(plan.total() as i128)
+    );
+}
+
+/// The recipient `name` stands for.
+fn find(balances: &BalanceSet, aliases: &Aliases, name: &str) -> Result<String, String> {
+    let id = aliases::resolve(aliases, name);
+    if balances.get(&id).is_some() {
+        return Ok(id);
+    }
+    let matching = balances
+        .iter()
+        .map(|(id, _)| id)
+        .filter(|id| id.starts_with(name))
+        .collect::<Vec<_>>();
+    match matching[..] {
+        [id] => Ok(id.to_string()),
+        [] => Err(format!("no recipient '{name}'")),
+        _ => Err(format!("'{name}' matches {} recipients", matching.len())),
+    }
+}
+
+/// Review `plan`, reading answers from `input`. Returns the plan to go on
+/// with, or `None` if the run is aborted.
+pub fn review(
+    mut plan: MintPlan,
+    balances: &BalanceSet,
+    aliases: &Aliases,
+    input: &mut impl BufRead,
+) -> Result<Option<MintPlan>, anyhow::Error> {
+    eprintln!("Review the run: yes, no, skip <id>, set <id> <tokens> or list.");
+    let mut line = String::new();
+    loop {
+        eprint!("review> ");
+        std::io::stderr().flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            eprintln!();
+            return Ok(None);
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let result = match words[..] {
+            [] => continue,
+            ["y" | "yes"] => return Ok(Some(plan)),
+            ["n" | "no" | "q" | "quit"] => return Ok(None),
+            ["l" | "list"] => {
+                show(&plan);
+                continue;
+            }
+            ["skip", name] => find(balances, aliases, name).map(|id| {
+                plan.set_amount(&id, 0);
+                format!("Skipped {id}.")
+            }),
+            ["set", name, tokens] => find(balances, aliases, name).and_then(|id| {
+                let balance = balances.get(&id).unwrap_or_default();
+                match parse_tokens(tokens).and_then(|t| u64::try_from(t).ok()) {
+                    Some(amount) if amount <= balance => {
+                        plan.set_amount(&id, amount);
+                        Ok(format!("{id}: {}", format_tokens(amount as i128)))
+                    }
+                    Some(_) => Err(format!(
+                        "{id} only has {} tokens left",
+                        format_tokens(balance as i128)
+                    )),
+                    None => Err(format!("invalid amount '{tokens}'")),
+                }
+            }),
+            _ => Err(format!("unknown command '{}'", line.trim())),
+        };
+        match result {
+            Ok(message) => eprintln!("{message}"),
+            Err(error) => eprintln!("error: {error}."),
+        }
+    }
+}
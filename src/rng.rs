@@ -0,0 +1,72 @@
+//! Where the random draws of a plan come from, for `mint --rng`:
+//!
+//! - `thread`: the thread's generator, seeded by the system. Plans can't be
+//!   computed again.
+//! - `seeded`: a ChaCha generator (`StdRng`) seeded from `--seed`, or from a
+//!   random seed that is printed and recorded. This is the default.
+//! - `hmac`: each identity's draws are seeded from the HMAC-SHA-256 of the
+//!   identity, keyed with `--run-id`. The jitter of an identity only depends
+//!   on the run id and the identity, so any machine computes the same amount
+//!   from the same run id, whatever the other identities of the run.
+use crate::sha256;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RngKind {
+    Thread,
+    #[default]
+    Seeded,
+    Hmac,
+}
+
+/// A source of random draws for planning a run.
+pub trait Source {
+    /// The draws for the amount of `id`.
+    fn of(&mut self, id: &str) -> &mut dyn RngCore;
+
+    /// The draws for the run as a whole, as the order of its entries.
+    fn run(&mut self) -> &mut dyn RngCore;
+}
+
+/// Any generator draws the same way for every identity.
+impl<R: RngCore> Source for R {
+    fn of(&mut self, _id: &str) -> &mut dyn RngCore {
+        self
+    }
+
+    fn run(&mut self) -> &mut dyn RngCore {
+        self
+    }
+}
+
+/// Draws derived from a run id, see the module documentation.
+pub struct Hmac {
+    run_id: String,
+    current: StdRng,
+}
+
+impl Hmac {
+    pub fn new(run_id: &str) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            current: StdRng::from_seed([0; 32]),
+        }
+    }
+
+    fn reseed(&mut self, message: &str) -> &mut dyn RngCore {
+        self.current = StdRng::from_seed(sha256::hmac(self.run_id.as_bytes(), message.as_bytes()));
+        &mut self.current
+    }
+}
+
+impl Source for Hmac {
+    fn of(&mut self, id: &str) -> &mut dyn RngCore {
+        self.reseed(id)
+    }
+
+    /// Keyed with the empty message, which is no identity.
+    fn run(&mut self) -> &mut dyn RngCore {
+        self.reseed("")
+    }
+}
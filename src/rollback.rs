@@ -0,0 +1,104 @@
+//! Rolling back a mint run. The inverse of a mint file, i.e. the amounts it
+//! minted, is written as `rollback-<mint file>`, which credits the balances
+//! again. If the run was submitted, the tokens can be burnt with the command
+//! printed by `--burn`.
+use crate::shell::Shell;
+use crate::storage::Storage;
+use crate::{
+    ensure_writable, format_tokens, is_mint_file, periods, read_json, state, Ledger, MintPlan,
+};
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub struct RollbackOpt {
+    /// The mint file to roll back.
+    run: PathBuf,
+
+    /// Do not write the rollback file.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Also print the `ledger` command that burns what the run minted, for
+    /// runs that were submitted. Requires `--pem`.
+    #[arg(long, requires = "pem")]
+    burn: bool,
+
+    /// The pem file to use for the burn command.
+    #[arg(long)]
+    pem: Option<PathBuf>,
+
+    /// A memo to pass to the burn command.
+    #[arg(long)]
+    memo: Option<String>,
+
+    /// The shell to quote the burn command for. Defaults to PowerShell on
+    /// Windows and POSIX shells elsewhere.
+    #[arg(long, value_enum)]
+    shell: Option<Shell>,
+
+    #[command(flatten)]
+    target: Ledger,
+}
+
+pub fn rollback(
+    storage: &dyn Storage,
+    opts: RollbackOpt,
+    read_only: bool,
+) -> Result<(), anyhow::Error> {
+    if !opts.dry_run {
+        ensure_writable(read_only, "write a rollback file (use --dry-run)")?;
+    }
+
+    let loaded = state::current(storage)?;
+    let run = PathBuf::from(opts.run.file_name().unwrap_or_default());
+    if !is_mint_file(&run) || !storage.exists(&run) {
+        anyhow::bail!("Not a mint file: {:?}", opts.run);
+    }
+    let output = PathBuf::from(format!("rollback-{}", run.display()));
+    if storage.exists(&output) {
+        anyhow::bail!(
+            "{} was already rolled back in {}.",
+            run.display(),
+            output.display()
+        );
+    }
+    if let Some(period) = periods::frozen_in(storage, &run)? {
+        eprintln!(
+            "warning: {} is part of the closed period {period}, its report won't reflect the rollback.",
+            run.display()
+        );
+    }
+
+    // Mint files hold the negative of what was minted.
+    let minted = read_json(storage, &run)?
+        .into_iter()
+        .filter(|(_, amount)| *amount < 0)
+        .map(|(id, amount)| (id, amount.unsigned_abs() as u64))
+        .collect::<BTreeMap<_, _>>();
+    let plan = MintPlan::from_amounts(minted);
+    if plan.is_empty() {
+        eprintln!("Nothing to roll back.");
+        return Ok(());
+    }
+    for (id, amount) in plan.amounts() {
+        eprintln!("{}\t{}", id, format_tokens(*amount as i128));
+    }
+
+    if !opts.dry_run {
+        state::commit(storage, loaded)?;
+        storage.write(&output, plan.allocation_file()?.as_bytes())?;
+        eprintln!("Credited the balances back in {}.", output.display());
+    }
+
+    if opts.burn {
+        let shell = opts.shell.unwrap_or_else(Shell::detect);
+        let pem = opts.pem.unwrap_or_default();
+        println!(
+            "{}",
+            plan.burn_command(&opts.target, &pem, opts.memo.as_deref(), shell, false)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+//! The report of a mint run, for automation to archive and audit runs. It is
+//! written by `mint --report <PATH>`, or printed instead of the usual output
+//! with `--report-format json` alone, once the run succeeded.
+//!
+//! ```json
+//! {
+//!   "amounts": [{ "amount": "3250000001", "id": "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f" }],
+//!   "commands": ["ledger --pem id.pem https://alberto.app/api token mint ..."],
+//!   "date": "2024-01-01T12:00:00+00:00",
+//!   "dry_run": false,
+//!   "executed": false,
+//!   "inputs": [{ "file": "grants.json", "sha256": "..." }],
+//!   "recipients": 1,
+//!   "schema": "many-after8/run-report",
+//!   "seed": null,
+//!   "token": "mqbh742x4s356ddaryrxaowt4wxtlocekzpufodvowrirfrqaaaaa3l",
+//!   "tool_version": "0.1.0",
+//!   "total": "3250000001",
+//!   "url": "https://alberto.app/api",
+//!   "version": 1
+//! }
+//! ```
+//!
+//! - `amounts` are in payload order and `total` is their sum, in base units,
+//!   as strings like in the preview.
+//! - `inputs` are the files the plan was computed from, as they were before
+//!   the run wrote its mint file.
+//! - `commands` are the `ledger` commands of the run: one per chunk with
+//!   `--chunk-size`, and what `--execute` ran.
+use crate::storage::Storage;
+use crate::{input_files, sha256, Ledger, MintPlan};
+use chrono::{DateTime, Local};
+use serde_json::{json, Value};
+
+/// The value of the `schema` field.
+pub const SCHEMA: &str = "many-after8/run-report";
+
+/// The version of the schema.
+pub const VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Json,
+}
+
+/// The metadata of the run a report is for.
+pub struct Run {
+    pub date: DateTime<Local>,
+    pub dry_run: bool,
+    pub executed: bool,
+    pub commands: Vec<String>,
+}
+
+/// The report of the run of `plan`. The checksums are of the input files as
+/// they are now, so it is built before the run writes anything.
+pub fn report(
+    storage: &dyn Storage,
+    plan: &MintPlan,
+    ledger: &Ledger,
+    run: Run,
+) -> Result<Value, anyhow::Error> {
+    let inputs = input_files(storage)?
+        .iter()
+        .map(|path| {
+            Ok(json!({
+                "file": path.display().to_string(),
+                "sha256": sha256::hex_digest(&storage.read(path)?),
+            }))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    Ok(json!({
+        "schema": SCHEMA,
+        "version": VERSION,
+        "date": run.date.to_rfc3339(),
+        "dry_run": run.dry_run,
+        "executed": run.executed,
+        "seed": plan.seed(),
+        "token": ledger.token,
+        "url": ledger.url,
+        "amounts": plan
+            .entries()
+            .iter()
+            .map(|(id, amount)| json!({ "id": id, "amount": amount.to_string() }))
+            .collect::<Vec<_>>(),
+        "recipients": plan.entries().len(),
+        "total": plan.total().to_string(),
+        "inputs": inputs,
+        "commands": run.commands,
+        "tool_version": env!("CARGO_PKG_VERSION"),
+    }))
+}
@@ -0,0 +1,181 @@
+//! Which files of the directory are input files. By default, the files
+//! directly in the directory; with `--recursive`, the files of its
+//! subdirectories too, so allocations can be organized as in
+//! `2024/q1/grants.json`. `--include` and `--exclude` select files by glob:
+//!
+//! - `*` matches any part of a file or directory name, `?` a single
+//!   character, and `**` any number of directories, as in `2024/**/*.json`.
+//! - Patterns without a `/` match file names in any directory, as in
+//!   `mint-*.json`. Others match the path from the root of the directory.
+//!
+//! Hidden directories and the directories this tool writes to (bundles,
+//! receipts, archives and period reports) are never scanned.
+use crate::storage::Storage;
+use crate::{bundle, periods, prune, receipts};
+use chrono::{DateTime, Local};
+use std::path::{Path, PathBuf};
+
+/// A glob pattern, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob(String);
+
+impl std::str::FromStr for Glob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = s.trim_start_matches("./");
+        if pattern.is_empty() || pattern.starts_with('/') {
+            return Err(format!(
+                "expected a pattern relative to the directory, got '{s}'"
+            ));
+        }
+        Ok(Self(pattern.to_string()))
+    }
+}
+
+impl std::fmt::Display for Glob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            matches(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, c)| *c == b'/' && matches(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let name = text.iter().position(|c| *c == b'/').unwrap_or(text.len());
+            (0..=name).any(|i| matches(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => matches!(text, [c, tail @ ..] if *c != b'/' && matches(rest, tail)),
+        [p, rest @ ..] => matches!(text, [c, tail @ ..] if c == p && matches(rest, tail)),
+    }
+}
+
+impl Glob {
+    /// Whether the pattern matches `path`, relative to the directory.
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('\\', "/");
+        let text = if self.0.contains('/') {
+            path.as_str()
+        } else {
+            path.rsplit('/').next().unwrap_or_default()
+        };
+        matches(self.0.as_bytes(), text.as_bytes())
+    }
+}
+
+/// The rules selecting input files.
+#[derive(Debug, Clone, Default)]
+pub struct Scan {
+    /// Also read the files of subdirectories.
+    pub recursive: bool,
+    /// Only read files matching one of these patterns, if any.
+    pub include: Vec<Glob>,
+    /// Don't read files matching any of these patterns.
+    pub exclude: Vec<Glob>,
+}
+
+/// The directories this tool writes to at the root of the directory.
+const OUTPUT_DIRS: &[&str] = &[
+    bundle::BUNDLES_DIR,
+    periods::PERIODS_DIR,
+    prune::COMPLETED_DIR,
+    receipts::RECEIPTS_DIR,
+];
+
+impl Scan {
+    fn selects(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|g| g.matches(path)))
+            && !self.exclude.iter().any(|g| g.matches(path))
+    }
+
+    fn scanned(dir: &Path) -> bool {
+        let hidden = dir
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+        let output =
+            dir.parent() == Some(Path::new("")) && OUTPUT_DIRS.iter().any(|d| dir == Path::new(d));
+        !hidden && !output
+    }
+
+    /// The files of `storage` the rules select, sorted.
+    pub fn files(&self, storage: &dyn Storage) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut files = Vec::new();
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            files.extend(storage.list(&dir)?);
+            if self.recursive {
+                dirs.extend(
+                    storage
+                        .list_dirs(&dir)?
+                        .into_iter()
+                        .filter(|d| Self::scanned(d)),
+                );
+            }
+        }
+        files.retain(|f| self.selects(f));
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Wraps a storage so listing its root gives the files selected by a `Scan`,
+/// for every command to read the same input files.
+pub struct Scanned<S> {
+    pub storage: S,
+    pub scan: Scan,
+}
+
+impl<S: Storage> Storage for Scanned<S> {
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        if dir == Path::new("") {
+            self.scan.files(&self.storage)
+        } else {
+            self.storage.list(dir)
+        }
+    }
+
+    fn list_dirs(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.storage.list_dirs(dir)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.storage.exists(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        self.storage.read(path)
+    }
+
+    fn modified(&self, path: &Path) -> Result<DateTime<Local>, anyhow::Error> {
+        self.storage.modified(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        self.storage.write(path, data)
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        self.storage.append(path, data)
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), anyhow::Error> {
+        self.storage.remove(path)
+    }
+
+    fn lock(&self) -> Result<(), anyhow::Error> {
+        self.storage.lock()
+    }
+
+    fn unlock(&self) -> Result<(), anyhow::Error> {
+        self.storage.unlock()
+    }
+}
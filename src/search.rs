@@ -0,0 +1,87 @@
+//! Fuzzy search over lines of output, for `balances --interactive`. Each
+//! query keeps the lines whose identity or alias contains its characters in
+//! order, best matches first, one page at a time.
+use std::io::{BufRead, Write};
+
+/// The number of matches shown per query when the terminal height is unknown.
+const DEFAULT_PAGE: usize = 20;
+
+/// A line of output, with the keys it can be found by.
+pub struct Entry {
+    pub keys: Vec<String>,
+    pub line: String,
+}
+
+/// How well `query` matches `candidate`, lower is better. `None` if the
+/// characters of the query don't all appear in order in the candidate.
+/// Case-insensitive. A match scores the gaps between matched characters and
+/// where the first one is, so contiguous matches at the start rank first.
+pub fn score(query: &str, candidate: &str) -> Option<usize> {
+    let candidate = candidate.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = next + candidate[next..].iter().position(|c| *c == q)?;
+        score += match last {
+            None => found,
+            Some(last) => found - last - 1,
+        };
+        last = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+/// The entries matching `query`, best first. An empty query matches all of
+/// them, in their original order.
+pub fn matches<'a>(entries: &'a [Entry], query: &str) -> Vec<&'a Entry> {
+    let mut matches = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let best = entry.keys.iter().filter_map(|k| score(query, k)).min()?;
+            Some((best, i, entry))
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|(score, i, _)| (*score, *i));
+    matches.into_iter().map(|(_, _, entry)| entry).collect()
+}
+
+/// Prompt for queries on stderr and print the matches on stdout, until an
+/// empty query after a search or the end of input.
+pub fn interactive(entries: &[Entry]) -> Result<(), anyhow::Error> {
+    let page = std::env::var("LINES")
+        .ok()
+        .and_then(|l| l.parse::<usize>().ok())
+        .map(|l| l.saturating_sub(3).max(1))
+        .unwrap_or(DEFAULT_PAGE);
+    eprintln!(
+        "{} entries. Type to search identities and aliases, an empty line to quit.",
+        entries.len()
+    );
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        eprint!("search> ");
+        std::io::stderr().flush()?;
+        let Some(query) = lines.next().transpose()? else {
+            eprintln!();
+            return Ok(());
+        };
+        if query.trim().is_empty() {
+            return Ok(());
+        }
+
+        let found = matches(entries, &query);
+        for entry in found.iter().take(page) {
+            println!("{}", entry.line);
+        }
+        if found.len() > page {
+            eprintln!("... and {} more, refine the search.", found.len() - page);
+        } else if found.is_empty() {
+            eprintln!("No matches.");
+        }
+    }
+}
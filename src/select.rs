@@ -0,0 +1,72 @@
+//! `--only` and `--skip`, to target or exclude some recipients of `mint` and
+//! `balances` without editing the allocation files. Each takes an identity,
+//! an alias, or `@file` for a file listing them one per line (blank lines and
+//! `#` comments are ignored), and can be repeated.
+use crate::aliases::{self, Aliases};
+use crate::identity::Identity;
+use crate::storage::Storage;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct SelectOpt {
+    /// Only include these recipients.
+    #[arg(long, value_name = "ID|@FILE")]
+    pub only: Vec<String>,
+
+    /// Leave these recipients out.
+    #[arg(long, value_name = "ID|@FILE")]
+    pub skip: Vec<String>,
+}
+
+/// The recipients selected by `--only` and `--skip`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    only: Option<BTreeSet<String>>,
+    skip: BTreeSet<String>,
+}
+
+impl Selection {
+    pub fn includes(&self, id: &str) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(id)) && !self.skip.contains(id)
+    }
+}
+
+/// The identities (in textual form) of the arguments of an option.
+fn identities(aliases: &Aliases, args: &[String]) -> Result<BTreeSet<String>, anyhow::Error> {
+    let mut ids = BTreeSet::new();
+    for arg in args {
+        let names = match arg.strip_prefix('@') {
+            Some(file) => std::fs::read_to_string(Path::new(file))
+                .map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", file, e))?
+                .lines()
+                .map(|line| line.split_once('#').map_or(line, |(name, _)| name).trim())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => vec![arg.clone()],
+        };
+        for name in names {
+            let id = aliases::resolve(aliases, &name);
+            if id.parse::<Identity>().is_err() {
+                anyhow::bail!("'{name}' is neither an identity nor an alias.");
+            }
+            ids.insert(id);
+        }
+    }
+    Ok(ids)
+}
+
+impl SelectOpt {
+    pub fn selection(&self, storage: &dyn Storage) -> Result<Selection, anyhow::Error> {
+        let aliases = aliases::load(storage)?;
+        Ok(Selection {
+            only: if self.only.is_empty() {
+                None
+            } else {
+                Some(identities(&aliases, &self.only)?)
+            },
+            skip: identities(&aliases, &self.skip)?,
+        })
+    }
+}
@@ -0,0 +1,145 @@
+//! Operator session log. Every invocation appends one JSON line to
+//! `sessions.log` in the data directory, so changes to the directory can be
+//! tied back to the exact command that produced them. `history --verbose`
+//! lists the session of each run.
+//!
+//! The `config_hash` of an entry identifies the configuration of the
+//! directory, its `after8.toml` or `after8.json` and the defaults they set,
+//! so runs made with different configurations can be told apart. Options
+//! given on the command line are in `argv`. Entries written by earlier
+//! versions hashed the parsed options instead.
+use crate::config::{Config, CONFIG_JSON, CONFIG_TOML};
+use crate::storage::Storage;
+use chrono::{DateTime, FixedOffset};
+use serde_json::{json, Value};
+use std::path::Path;
+
+pub const SESSIONS_LOG: &str = "sessions.log";
+
+/// The name of the host, as best as we can tell without extra dependencies.
+pub fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|s| s.trim().to_string())
+        })
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// 64-bit FNV-1a. Not cryptographic, but stable across builds and platforms,
+/// which is all we need to tell configurations apart in the log.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The hash of the configuration of the directory: the bytes of its
+/// configuration file, and the defaults it sets. Directories without one all
+/// hash the same.
+pub fn config_hash(storage: &dyn Storage) -> Result<String, anyhow::Error> {
+    let mut bytes = Vec::new();
+    for name in [CONFIG_TOML, CONFIG_JSON] {
+        let path = Path::new(name);
+        if storage.exists(path) {
+            bytes.extend(format!("{name}\0").as_bytes());
+            bytes.extend(storage.read(path)?);
+            bytes.push(0);
+        }
+    }
+    for (key, value) in Config::load(storage)?.iter() {
+        bytes.extend(format!("{key}={value}\n").as_bytes());
+    }
+    Ok(format!("{:016x}", fnv1a(&bytes)))
+}
+
+/// Append a session entry to the log.
+pub fn record(storage: &dyn Storage) -> Result<(), anyhow::Error> {
+    let entry = json!({
+        "date": chrono::Local::now().to_rfc3339(),
+        "argv": std::env::args().collect::<Vec<_>>(),
+        "config_hash": config_hash(storage)?,
+        "version": env!("CARGO_PKG_VERSION"),
+        "host": hostname(),
+    });
+
+    storage.append(Path::new(SESSIONS_LOG), format!("{entry}\n").as_bytes())
+}
+
+/// An entry of the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub date: DateTime<FixedOffset>,
+    pub argv: Vec<String>,
+    pub config_hash: String,
+    pub version: String,
+    pub host: String,
+}
+
+impl std::fmt::Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on {}, version {}, config {}: {}",
+            self.date.format("%Y-%m-%d %H:%M:%S"),
+            self.host,
+            self.version,
+            self.config_hash,
+            self.argv.join(" ")
+        )
+    }
+}
+
+/// The sessions in the log, oldest first.
+pub fn load(storage: &dyn Storage) -> Result<Vec<Session>, anyhow::Error> {
+    let path = Path::new(SESSIONS_LOG);
+    if !storage.exists(path) {
+        return Ok(Vec::new());
+    }
+    let mut sessions = Vec::new();
+    for (i, line) in storage.read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = match serde_json::from_str::<Value>(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("warning: {SESSIONS_LOG}:{}: invalid entry: {e}", i + 1);
+                continue;
+            }
+        };
+        let Some(date) = entry["date"]
+            .as_str()
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        else {
+            eprintln!("warning: {SESSIONS_LOG}:{}: entry without a date", i + 1);
+            continue;
+        };
+        let field = |name: &str| entry[name].as_str().unwrap_or("unknown").to_string();
+        sessions.push(Session {
+            date,
+            argv: entry["argv"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|arg| arg.as_str().map(str::to_string))
+                .collect(),
+            config_hash: field("config_hash"),
+            version: field("version"),
+            host: field("host"),
+        });
+    }
+    sessions.sort_by_key(|session| session.date);
+    Ok(sessions)
+}
+
+/// The session a change made at `date` belongs to: the last one started by
+/// then, as each invocation is recorded before it changes anything.
+pub fn at(sessions: &[Session], date: DateTime<FixedOffset>) -> Option<&Session> {
+    let started = sessions.partition_point(|session| session.date.timestamp() <= date.timestamp());
+    started.checked_sub(1).map(|i| &sessions[i])
+}
@@ -0,0 +1,100 @@
+//! Quoting of arguments in the generated command lines, for the shell the
+//! operator will paste them into.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    /// sh, bash, zsh and friends.
+    Posix,
+    /// Windows PowerShell and pwsh.
+    Powershell,
+    /// The Windows command prompt.
+    Cmd,
+}
+
+impl Shell {
+    /// PowerShell on Windows, POSIX everywhere else.
+    pub fn detect() -> Self {
+        if cfg!(windows) {
+            if std::env::var_os("PSModulePath").is_some() {
+                Shell::Powershell
+            } else {
+                Shell::Cmd
+            }
+        } else {
+            Shell::Posix
+        }
+    }
+
+    /// Quote an argument so the shell passes it through verbatim.
+    pub fn quote(self, arg: &str) -> String {
+        match self {
+            Shell::Posix => format!("'{}'", arg.replace('\'', r"'\''")),
+            Shell::Powershell => format!("'{}'", arg.replace('\'', "''")),
+            Shell::Cmd => quote_cmd(arg),
+        }
+    }
+
+    /// Quote an argument only if it contains characters the shell would
+    /// interpret, e.g. a path with spaces or backslashes.
+    pub fn quote_if_needed(self, arg: &str) -> String {
+        // cmd expands variables even outside quotes.
+        let safe_chars = if self == Shell::Cmd {
+            "-_./=:,+@"
+        } else {
+            "-_./=:,+@%"
+        };
+        let safe = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || safe_chars.contains(c));
+        if safe {
+            arg.to_string()
+        } else {
+            self.quote(arg)
+        }
+    }
+}
+
+/// Quote an argument for cmd. Its quotes are escaped as `\"` for the
+/// program, but cmd itself takes each one as the start or end of a quoted
+/// part, so what follows is escaped for where cmd thinks it is:
+///
+/// - `%` would expand variables even in quotes. It is escaped as `^%`,
+///   outside quotes, so cmd looks up a name with a `^` in it, which it leaves
+///   as is, then drops the `^`.
+/// - `&`, `|`, `<`, `>`, `(`, `)` and `^` are escaped with `^` outside
+///   quotes.
+///
+/// Backslashes before a quote are doubled, as programs read `\"` as a
+/// quote. cmd has no way to continue a quoted argument on the next line, so
+/// line breaks are dropped.
+fn quote_cmd(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut in_quotes = true;
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        // Double the backslashes just written, before a quote.
+        let before_quote = |quoted: &mut String| quoted.push_str(&"\\".repeat(backslashes));
+        match c {
+            '\r' | '\n' => continue,
+            '"' => {
+                before_quote(&mut quoted);
+                quoted.push_str("\\\"");
+                in_quotes = !in_quotes;
+            }
+            '%' if in_quotes => {
+                before_quote(&mut quoted);
+                quoted.push_str("\"^%\"");
+            }
+            '%' | '&' | '|' | '<' | '>' | '(' | ')' | '^' if !in_quotes => {
+                quoted.push('^');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+        backslashes = if c == '\\' { backslashes + 1 } else { 0 };
+    }
+    quoted.push_str(&"\\".repeat(backslashes));
+    quoted.push('"');
+    quoted
+}
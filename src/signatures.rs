@@ -0,0 +1,216 @@
+//! Signatures of mint files, so tampering with committed runs is detectable.
+//! With `mint --sign`, each mint file a run writes is signed with its
+//! `--pem` using `openssl pkeyutl`, and the signature written next to it as
+//! `<mint file>.sig`. `verify-signatures` checks them with the operator's
+//! public key, as does `openssl pkeyutl -verify -pubin -inkey <public key>
+//! -rawin -in <mint file> -sigfile <mint file>.sig`.
+//!
+//! Mint files from before signing was turned on have no signature. They are
+//! listed, but only refused with `--require`. A signature left without its
+//! mint file is always reported, as the run was removed.
+//!
+//! `prune` can't sign the mint files it rewrites. Their signatures are of
+//! the files as they were run, which are listed as pruned as long as they
+//! are as `manifest.json` recorded them after pruning (see [`integrity`]).
+//! The signatures of the files it removes are archived with their entries.
+//!
+//! [`integrity`]: crate::integrity
+use crate::storage::Storage;
+use crate::{input_files, integrity, is_mint_file};
+use clap::Parser;
+use rand::Rng;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The extension of signature files.
+pub const EXTENSION: &str = "sig";
+
+#[derive(Debug, Parser)]
+pub struct VerifySignaturesOpt {
+    /// The public key of the operator, in PEM format.
+    #[arg(long)]
+    public_key: Option<PathBuf>,
+
+    /// The key the mint files were signed with, if there is no `--public-key`.
+    #[arg(long)]
+    pem: Option<PathBuf>,
+
+    /// Also refuse mint files that aren't signed.
+    #[arg(long)]
+    require: bool,
+
+    /// The `openssl` binary to verify with.
+    #[arg(long, default_value = "openssl")]
+    openssl: PathBuf,
+}
+
+/// The signature file of `path`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.{EXTENSION}", path.display()))
+}
+
+/// A temporary file holding `data`, for `openssl` to read. Ed25519 keys sign
+/// in one shot, which `openssl` only does from a file. It is created with a
+/// random name, only if it doesn't exist yet, and only readable by us, so
+/// another user can't swap what is signed. It is removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, data: &[u8]) -> Result<Self, anyhow::Error> {
+        let suffix: u64 = rand::thread_rng().gen();
+        let path = std::env::temp_dir().join(format!("many-after8-{name}-{suffix:016x}"));
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("Could not create {:?}: {}", path, e))?;
+        let temp = TempFile(path);
+        file.write_all(data)
+            .map_err(|e| anyhow::anyhow!("Could not write {:?}: {}", temp.0, e))?;
+        Ok(temp)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Sign `data` with `pem`, returning the signature.
+pub fn sign(openssl: &Path, pem: &Path, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let input = TempFile::new("sign", data)?;
+    let output = Command::new(openssl)
+        .args(["pkeyutl", "-sign", "-rawin", "-inkey"])
+        .arg(pem)
+        .arg("-in")
+        .arg(&input.0)
+        .output();
+    let output = output.map_err(|e| anyhow::anyhow!("Could not run {:?}: {}", openssl, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{:?} failed ({}), nothing was signed: {}",
+            openssl,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Whether `signature` is a signature of `data` by `key`, a public key if
+/// `public`, else a private one.
+fn verify(
+    openssl: &Path,
+    key: &Path,
+    public: bool,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, anyhow::Error> {
+    let input = TempFile::new("verify", data)?;
+    let sigfile = TempFile::new("verify-sig", signature)?;
+    let output = Command::new(openssl)
+        .args(["pkeyutl", "-verify"])
+        .args(public.then_some("-pubin"))
+        .arg("-inkey")
+        .arg(key)
+        .arg("-rawin")
+        .arg("-in")
+        .arg(&input.0)
+        .arg("-sigfile")
+        .arg(&sigfile.0)
+        .output();
+    let output = output.map_err(|e| anyhow::anyhow!("Could not run {:?}: {}", openssl, e))?;
+    if output.status.success() {
+        return Ok(true);
+    }
+    // `openssl` fails the same way when the signature is wrong, and when it
+    // can't check it at all, which only the former prints.
+    if String::from_utf8_lossy(&output.stdout).contains("Verification Failure") {
+        return Ok(false);
+    }
+    anyhow::bail!(
+        "{:?} failed ({}), the signatures weren't checked: {}",
+        openssl,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
+
+/// How `mint --sign` signs the mint files it writes.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    pub openssl: PathBuf,
+    pub pem: PathBuf,
+}
+
+impl Signer {
+    /// Sign the mint file `path`. Returns the path of its signature.
+    pub fn sign_file(&self, storage: &dyn Storage, path: &Path) -> Result<PathBuf, anyhow::Error> {
+        let signature = sign(&self.openssl, &self.pem, &storage.read(path)?)?;
+        let output = signature_path(path);
+        storage.write(&output, &signature)?;
+        Ok(output)
+    }
+}
+
+pub fn verify_signatures(
+    storage: &dyn Storage,
+    opts: VerifySignaturesOpt,
+) -> Result<(), anyhow::Error> {
+    let (key, public) = match (&opts.public_key, &opts.pem) {
+        (Some(key), _) => (key, true),
+        (None, Some(pem)) => (pem, false),
+        (None, None) => anyhow::bail!("Give the --public-key to check the signatures with."),
+    };
+
+    let (mut signed, mut unsigned, mut pruned, mut failures) = (0, 0, 0, 0);
+    for path in input_files(storage)?
+        .into_iter()
+        .filter(|p| is_mint_file(p))
+    {
+        let signature = signature_path(&path);
+        if !storage.exists(&signature) {
+            println!("{}: not signed", path.display());
+            unsigned += 1;
+            continue;
+        }
+        let (data, signature) = (storage.read(&path)?, storage.read(&signature)?);
+        if verify(&opts.openssl, key, public, &data, &signature)? {
+            signed += 1;
+        } else if integrity::is_pruned(storage, &path)? {
+            println!("{}: pruned after it was signed", path.display());
+            pruned += 1;
+        } else {
+            println!("{}: invalid signature", path.display());
+            failures += 1;
+        }
+    }
+    for signature in storage.list(Path::new(""))? {
+        let Some(path) = signature
+            .to_str()
+            .and_then(|s| s.strip_suffix(&format!(".{EXTENSION}")))
+            .map(Path::new)
+        else {
+            continue;
+        };
+        if is_mint_file(path) && !storage.exists(path) {
+            println!("{}: the mint file is gone", signature.display());
+            failures += 1;
+        }
+    }
+
+    if opts.require {
+        failures += unsigned;
+    }
+    if failures > 0 {
+        anyhow::bail!("{failures} mint file(s) failed the signature check.");
+    }
+    eprintln!(
+        "{signed} signature(s) verified, {pruned} mint file(s) pruned since, {unsigned} not signed."
+    );
+    Ok(())
+}
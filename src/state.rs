@@ -0,0 +1,42 @@
+//! Optimistic versioning of the data directory. `state.version` holds a
+//! counter that every command changing the balances bumps when it commits.
+//! A command remembers the version it loaded the directory at, and refuses
+//! to commit if it changed since: another operator committed in between.
+//! This holds even where the lock isn't honored, e.g. on some network
+//! filesystems.
+use crate::storage::Storage;
+use std::path::Path;
+
+pub const STATE_VERSION_FILE: &str = "state.version";
+
+/// The current version of the directory. Directories without a
+/// `state.version` are at version 0.
+pub fn current(storage: &dyn Storage) -> Result<u64, anyhow::Error> {
+    let path = Path::new(STATE_VERSION_FILE);
+    if !storage.exists(path) {
+        return Ok(0);
+    }
+    let content = storage.read_to_string(path)?;
+    content
+        .trim()
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid version '{}' in {:?}", content.trim(), path))
+}
+
+/// Bump the version, if it is still `loaded`. Call before writing the files
+/// of a commit. Returns the new version.
+pub fn commit(storage: &dyn Storage, loaded: u64) -> Result<u64, anyhow::Error> {
+    let current = current(storage)?;
+    if current != loaded {
+        anyhow::bail!(
+            "The directory changed since it was loaded (state version {current}, loaded at {loaded}). \
+             Another operator may have committed a run; run the command again."
+        );
+    }
+    let next = current + 1;
+    storage.write(
+        Path::new(STATE_VERSION_FILE),
+        format!("{next}\n").as_bytes(),
+    )?;
+    Ok(next)
+}
@@ -0,0 +1,471 @@
+//! Access to the data directory. All paths are relative to the root of the
+//! storage. Subcommands only go through this trait, so they can run against
+//! an in-memory directory as well as the filesystem.
+use chrono::{DateTime, Local};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub trait Storage {
+    /// List the files directly under `dir`, sorted. Returns an empty list if
+    /// `dir` doesn't exist.
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error>;
+
+    /// List the directories directly under `dir`, sorted. Returns an empty
+    /// list if `dir` doesn't exist.
+    fn list_dirs(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error>;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, anyhow::Error>;
+
+    /// The last time the file was written.
+    fn modified(&self, path: &Path) -> Result<DateTime<Local>, anyhow::Error>;
+
+    /// Write a file, creating its parent directories if needed.
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error>;
+
+    /// Append to a file, creating it if needed.
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error>;
+
+    fn remove(&self, path: &Path) -> Result<(), anyhow::Error>;
+
+    /// Take an exclusive lock on the whole storage. Fails if it is already
+    /// locked.
+    fn lock(&self) -> Result<(), anyhow::Error>;
+
+    fn unlock(&self) -> Result<(), anyhow::Error>;
+
+    fn read_to_string(&self, path: &Path) -> Result<String, anyhow::Error> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|_| anyhow::anyhow!("File {:?} is not valid UTF-8", path))
+    }
+}
+
+/// Holds the lock on a storage until dropped.
+pub struct LockGuard<'a>(&'a dyn Storage);
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.unlock() {
+            eprintln!("warning: failed to release lock: {e}");
+        }
+    }
+}
+
+pub fn lock(storage: &dyn Storage) -> Result<LockGuard<'_>, anyhow::Error> {
+    storage.lock()?;
+    Ok(LockGuard(storage))
+}
+
+/// A directory on the filesystem.
+pub struct FsStorage {
+    root: PathBuf,
+}
+
+const LOCK_FILE: &str = ".after8.lock";
+
+/// The lock held while taking over a stale lock.
+const TAKEOVER_FILE: &str = ".after8.lock.takeover";
+
+impl FsStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Storage for FsStorage {
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let full = self.root.join(dir);
+        if !full.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(full)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(dir.join(entry.file_name()));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn list_dirs(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let full = self.root.join(dir);
+        if !full.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut dirs = Vec::new();
+        for entry in std::fs::read_dir(full)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(dir.join(entry.file_name()));
+            }
+        }
+        dirs.sort();
+        Ok(dirs)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.root.join(path).exists()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        std::fs::read(self.root.join(path))
+            .map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", path, e))
+    }
+
+    fn modified(&self, path: &Path) -> Result<DateTime<Local>, anyhow::Error> {
+        Ok(std::fs::metadata(self.root.join(path))?.modified()?.into())
+    }
+
+    /// Files are written to a temporary file next to them, synced, then
+    /// renamed over the file, so a crash leaves either the old or the new
+    /// content, never part of it.
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        let full = self.root.join(path);
+        let (Some(parent), Some(name)) = (full.parent(), full.file_name()) else {
+            anyhow::bail!("Could not write {:?}: not a file name", path);
+        };
+        std::fs::create_dir_all(parent)?;
+        let temporary = parent.join(format!(
+            ".{}.{}.tmp",
+            name.to_string_lossy(),
+            std::process::id()
+        ));
+        let written = File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(data)?;
+                file.sync_all()
+            })
+            .and_then(|()| std::fs::rename(&temporary, &full));
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&temporary);
+            anyhow::bail!("Could not write {:?}: {}", path, e);
+        }
+        // Make the rename itself durable.
+        #[cfg(unix)]
+        File::open(parent)?.sync_all()?;
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.root.join(path))?
+            .write_all(data)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), anyhow::Error> {
+        std::fs::remove_file(self.root.join(path))?;
+        Ok(())
+    }
+
+    /// The lock file records the process holding it. A lock left behind by
+    /// a process of this host that is no longer running is stale, and taken
+    /// over.
+    fn lock(&self) -> Result<(), anyhow::Error> {
+        let path = self.root.join(LOCK_FILE);
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", LockHolder::current().to_json())?;
+                    return Ok(());
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let holder = std::fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|s| LockHolder::parse(&s));
+                    match holder {
+                        Some(holder) if holder.is_stale() => {
+                            if self.take_over(&path, &holder)? {
+                                return Ok(());
+                            }
+                        }
+                        Some(holder) => anyhow::bail!(
+                            "Directory is locked by {holder}. Remove {:?} if that run is gone.",
+                            path
+                        ),
+                        None => anyhow::bail!(
+                            "Directory is locked by another process. Remove {:?} if it is stale.",
+                            path
+                        ),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn unlock(&self) -> Result<(), anyhow::Error> {
+        std::fs::remove_file(self.root.join(LOCK_FILE))?;
+        Ok(())
+    }
+}
+
+impl FsStorage {
+    /// Replace the stale lock of `stale` at `path` with ours. Takeovers hold
+    /// a second lock, so of two processes that found the same stale lock,
+    /// the second finds the lock of the first instead. The lock is replaced
+    /// with a rename, so it never goes missing for other processes to take.
+    /// Returns whether the lock is ours, or `false` if it changed since it
+    /// was read.
+    fn take_over(&self, path: &Path, stale: &LockHolder) -> Result<bool, anyhow::Error> {
+        let guard = self.root.join(TAKEOVER_FILE);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&guard) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => anyhow::bail!(
+                "Another process is taking over the stale lock of the directory. Remove {:?} if it is gone.",
+                guard
+            ),
+            Err(e) => return Err(e.into()),
+        };
+        let read_holder = || {
+            std::fs::read_to_string(path)
+                .ok()
+                .and_then(|s| LockHolder::parse(&s))
+        };
+        let result = (|| {
+            writeln!(file, "{}", LockHolder::current().to_json())?;
+            if read_holder().as_ref() != Some(stale) {
+                return Ok(false);
+            }
+            eprintln!("warning: removing the stale lock of {stale}, which is no longer running.");
+            let temp = self
+                .root
+                .join(format!("{LOCK_FILE}.{}.tmp", std::process::id()));
+            std::fs::write(&temp, format!("{}\n", LockHolder::current().to_json()))?;
+            std::fs::rename(&temp, path)?;
+            Ok(read_holder().is_some_and(|holder| holder.is_current()))
+        })();
+        std::fs::remove_file(&guard)?;
+        result
+    }
+}
+
+/// The process holding the lock of a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LockHolder {
+    pid: u32,
+    /// `None` for locks taken by versions that only recorded the process id.
+    host: Option<String>,
+    since: Option<String>,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            host: Some(crate::session::hostname()),
+            since: Some(Local::now().to_rfc3339()),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "pid": self.pid, "host": self.host, "since": self.since })
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        if let Ok(pid) = s.trim().parse() {
+            return Some(Self {
+                pid,
+                host: None,
+                since: None,
+            });
+        }
+        let value = serde_json::from_str::<serde_json::Value>(s).ok()?;
+        let text = |key: &str| value[key].as_str().map(str::to_string);
+        Some(Self {
+            pid: value["pid"].as_u64()?.try_into().ok()?,
+            host: text("host"),
+            since: text("since"),
+        })
+    }
+
+    /// Whether the holder is this process.
+    fn is_current(&self) -> bool {
+        self.pid == std::process::id()
+            && self.host.as_deref() == Some(crate::session::hostname().as_str())
+    }
+
+    /// Whether the holder is a process of this host that is gone. Only
+    /// known where processes are listed in `/proc`.
+    fn is_stale(&self) -> bool {
+        let proc = Path::new("/proc");
+        self.host.as_deref() == Some(crate::session::hostname().as_str())
+            && proc.join("self").exists()
+            && !proc.join(self.pid.to_string()).exists()
+    }
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "process {}", self.pid)?;
+        if let Some(host) = &self.host {
+            write!(f, " on {host}")?;
+        }
+        if let Some(since) = &self.since {
+            write!(f, " since {since}")?;
+        }
+        Ok(())
+    }
+}
+
+/// File contents and modification times, by path.
+type MemoryFiles = BTreeMap<PathBuf, (Vec<u8>, DateTime<Local>)>;
+
+/// A storage that only lives in memory, to exercise full flows without
+/// touching the filesystem.
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: RefCell<MemoryFiles>,
+    locked: Cell<bool>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All files and their content, e.g. to check what a flow wrote.
+    pub fn files(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.files
+            .borrow()
+            .iter()
+            .map(|(path, (data, _))| (path.clone(), data.clone()))
+            .collect()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        Ok(self
+            .files
+            .borrow()
+            .keys()
+            .filter(|p| p.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn list_dirs(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        // Directories only exist through the files under them.
+        let files = self.files.borrow();
+        let dirs = files
+            .keys()
+            .filter_map(|p| p.strip_prefix(dir).ok())
+            .filter_map(|rest| rest.components().next().map(|c| (c, rest)))
+            .filter(|(_, rest)| rest.components().count() > 1)
+            .map(|(first, _)| dir.join(first))
+            .collect::<std::collections::BTreeSet<_>>();
+        Ok(dirs.into_iter().collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.borrow().contains_key(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        self.files
+            .borrow()
+            .get(path)
+            .map(|(data, _)| data.clone())
+            .ok_or_else(|| anyhow::anyhow!("Could not read {:?}: not found", path))
+    }
+
+    fn modified(&self, path: &Path) -> Result<DateTime<Local>, anyhow::Error> {
+        self.files
+            .borrow()
+            .get(path)
+            .map(|(_, date)| *date)
+            .ok_or_else(|| anyhow::anyhow!("File {:?} not found", path))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        self.files
+            .borrow_mut()
+            .insert(path.to_path_buf(), (data.to_vec(), Local::now()));
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
+        let mut files = self.files.borrow_mut();
+        let entry = files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| (Vec::new(), Local::now()));
+        entry.0.extend_from_slice(data);
+        entry.1 = Local::now();
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), anyhow::Error> {
+        self.files
+            .borrow_mut()
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| anyhow::anyhow!("File {:?} not found", path))
+    }
+
+    fn lock(&self) -> Result<(), anyhow::Error> {
+        if self.locked.replace(true) {
+            anyhow::bail!("Storage is already locked.");
+        }
+        Ok(())
+    }
+
+    fn unlock(&self) -> Result<(), anyhow::Error> {
+        self.locked.set(false);
+        Ok(())
+    }
+}
+
+/// Wraps a storage and refuses every write. Locking is a no-op, as nothing
+/// can be written anyway.
+pub struct ReadOnly<S>(pub S);
+
+impl<S: Storage> Storage for ReadOnly<S> {
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.0.list(dir)
+    }
+
+    fn list_dirs(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.0.list_dirs(dir)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.0.exists(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        self.0.read(path)
+    }
+
+    fn modified(&self, path: &Path) -> Result<DateTime<Local>, anyhow::Error> {
+        self.0.modified(path)
+    }
+
+    fn write(&self, path: &Path, _data: &[u8]) -> Result<(), anyhow::Error> {
+        anyhow::bail!("Refusing to write {:?} in read-only mode.", path)
+    }
+
+    fn append(&self, path: &Path, _data: &[u8]) -> Result<(), anyhow::Error> {
+        anyhow::bail!("Refusing to write {:?} in read-only mode.", path)
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), anyhow::Error> {
+        anyhow::bail!("Refusing to remove {:?} in read-only mode.", path)
+    }
+
+    fn lock(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn unlock(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! The supply of the token, to keep runs within its maximum supply. With
+//! `mint --check-supply`, it is queried from the token info, as JSON, with
+//! `ledger <url> token info <token>`. Offline, `--supply` reads the same JSON
+//! from a file, which is refused if it was saved before the last run. In base
+//! units:
+//!
+//! ```json
+//! { "maximum": 1000000000000000, "circulating": 250000000000000 }
+//! ```
+//!
+//! The object may also be under a `supply` key. A `maximum` of `null`, or
+//! none, means the supply is unlimited.
+use crate::storage::Storage;
+use crate::{input_files, is_mint_file, run_date, Ledger};
+use chrono::{DateTime, Local};
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Supply {
+    pub maximum: Option<u64>,
+    pub circulating: u64,
+}
+
+impl Supply {
+    /// How much more can be minted, if the supply is limited.
+    pub fn headroom(&self) -> Option<u64> {
+        self.maximum.map(|m| m.saturating_sub(self.circulating))
+    }
+}
+
+/// An amount of base units, as a number or a string.
+fn units(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Read the supply saved in `path`, refusing it if it was saved before the
+/// last run in `storage`, which it doesn't account for.
+pub fn read(storage: &dyn Storage, path: &Path) -> Result<Supply, anyhow::Error> {
+    let saved: DateTime<Local> = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", path, e))?
+        .into();
+    let mut last_run = None;
+    for run in input_files(storage)?
+        .into_iter()
+        .filter(|p| is_mint_file(p))
+    {
+        let date = run_date(storage, &run)?;
+        if last_run.as_ref().is_none_or(|(last, _)| date > *last) {
+            last_run = Some((date, run));
+        }
+    }
+    if let Some((date, run)) = last_run.filter(|(date, _)| saved < *date) {
+        anyhow::bail!(
+            "{:?} was saved on {}, before the run {} of {}. Save the supply again, or use --check-supply.",
+            path,
+            saved.format("%Y-%m-%d %H:%M:%S"),
+            run.display(),
+            date.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read {:?}: {}", path, e))?;
+    parse(&content, &format!("{path:?}"))
+}
+
+/// Query the supply of the token of `target` from its token info, with
+/// `binary`.
+pub fn query(binary: &Path, target: &Ledger) -> Result<Supply, anyhow::Error> {
+    let output = Command::new(binary)
+        .args([&target.url, "token", "info", &target.token])
+        .output()
+        .map_err(|e| anyhow::anyhow!("Could not run {:?}: {}", binary, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{:?} failed ({}), could not get the supply of {}: {}",
+            binary,
+            output.status,
+            target.token,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse(
+        &String::from_utf8_lossy(&output.stdout),
+        &format!("the token info of {}", target.token),
+    )
+}
+
+/// Parse the supply from `content`, read from `source`.
+fn parse(content: &str, source: &str) -> Result<Supply, anyhow::Error> {
+    let value: Value = serde_json::from_str(content)
+        .map_err(|e| anyhow::anyhow!("Invalid supply in {}: {}", source, e))?;
+    let supply = match value.get("supply") {
+        Some(supply) => supply,
+        None => &value,
+    };
+    let field = |name: &str| -> Result<Option<u64>, anyhow::Error> {
+        match &supply[name] {
+            Value::Null => Ok(None),
+            v => units(v).map(Some).ok_or_else(|| {
+                anyhow::anyhow!("Invalid '{name}' {v} in {}, expected base units.", source)
+            }),
+        }
+    };
+    Ok(Supply {
+        maximum: field("maximum")?,
+        circulating: field("circulating")?
+            .ok_or_else(|| anyhow::anyhow!("Missing 'circulating' in {}.", source))?,
+    })
+}
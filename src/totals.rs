@@ -0,0 +1,131 @@
+//! Lifetime totals minted per identity, kept in a derived `totals.json` so
+//! they don't need a scan of every mint file. The file records which runs it
+//! covers. It is updated after each run, and ignored and rebuilt whenever it
+//! doesn't match the mint files in the directory. Corrections of `restate`
+//! count as runs, which they amend.
+use crate::restate::is_restatement;
+use crate::storage::Storage;
+use crate::{input_files, is_mint_file, read_json};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+pub const TOTALS_FILE: &str = "totals.json";
+
+struct Totals {
+    runs: BTreeSet<String>,
+    minted: BTreeMap<String, u64>,
+}
+
+fn run_names(storage: &dyn Storage) -> Result<BTreeSet<String>, anyhow::Error> {
+    Ok(input_files(storage)?
+        .into_iter()
+        .filter(|p| is_mint_file(p) || is_restatement(p))
+        .map(|p| p.display().to_string())
+        .collect())
+}
+
+fn load(storage: &dyn Storage) -> Option<Totals> {
+    let path = Path::new(TOTALS_FILE);
+    if !storage.exists(path) {
+        return None;
+    }
+    let value = storage
+        .read_to_string(path)
+        .ok()
+        .and_then(|c| serde_json::from_str::<Value>(&c).ok());
+    let runs = value.as_ref().and_then(|v| {
+        v["runs"]
+            .as_array()?
+            .iter()
+            .map(|r| r.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+    });
+    let minted = value.as_ref().and_then(|v| {
+        v["minted"]
+            .as_object()?
+            .iter()
+            .map(|(id, m)| Some((id.clone(), m.as_u64()?)))
+            .collect::<Option<_>>()
+    });
+    match (runs, minted) {
+        (Some(runs), Some(minted)) => Some(Totals { runs, minted }),
+        _ => {
+            eprintln!("warning: ignoring invalid {TOTALS_FILE}, it will be rebuilt.");
+            None
+        }
+    }
+}
+
+/// Add the amounts of the given runs to `minted`.
+fn add_runs<'a>(
+    storage: &dyn Storage,
+    minted: &mut BTreeMap<String, u64>,
+    runs: impl IntoIterator<Item = &'a String>,
+) -> Result<(), anyhow::Error> {
+    for run in runs {
+        let path = Path::new(run);
+        for (id, amount) in read_json(storage, path)? {
+            // Mint files hold the negative of what was minted, and so do
+            // corrections, which can also take back from it.
+            let minted = minted.entry(id).or_default();
+            if is_restatement(path) {
+                *minted = (*minted as i128 - amount).max(0) as u64;
+            } else {
+                *minted += (-amount).max(0) as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The total minted so far to each identity.
+pub fn lifetime(storage: &dyn Storage) -> Result<BTreeMap<String, u64>, anyhow::Error> {
+    let runs = run_names(storage)?;
+    match load(storage) {
+        Some(totals) if totals.runs == runs => Ok(totals.minted),
+        _ => {
+            let mut minted = BTreeMap::new();
+            add_runs(storage, &mut minted, &runs)?;
+            Ok(minted)
+        }
+    }
+}
+
+/// Bring `totals.json` up to date after a run. Only reads the runs it doesn't
+/// cover yet, unless it covers runs that don't exist anymore.
+pub fn update(storage: &dyn Storage) -> Result<(), anyhow::Error> {
+    let runs = run_names(storage)?;
+    let (mut minted, missing) = match load(storage) {
+        Some(totals) if totals.runs.is_subset(&runs) => {
+            let missing = runs.difference(&totals.runs).cloned().collect::<Vec<_>>();
+            (totals.minted, missing)
+        }
+        _ => (BTreeMap::new(), runs.iter().cloned().collect()),
+    };
+    add_runs(storage, &mut minted, &missing)?;
+    save(storage, &runs, &minted)
+}
+
+/// Recompute `totals.json` from scratch, e.g. after mint files were edited.
+pub fn rebuild(storage: &dyn Storage) -> Result<(), anyhow::Error> {
+    let runs = run_names(storage)?;
+    let mut minted = BTreeMap::new();
+    add_runs(storage, &mut minted, &runs)?;
+    save(storage, &runs, &minted)
+}
+
+fn save(
+    storage: &dyn Storage,
+    runs: &BTreeSet<String>,
+    minted: &BTreeMap<String, u64>,
+) -> Result<(), anyhow::Error> {
+    storage.write(
+        Path::new(TOTALS_FILE),
+        format!(
+            "{}\n",
+            serde_json::to_string_pretty(&json!({ "runs": runs, "minted": minted }))?
+        )
+        .as_bytes(),
+    )
+}
@@ -0,0 +1,75 @@
+//! Balances on the ledger, queried live with the `ledger` binary.
+//!
+//! `mint --bounded-by-minter` is for tokens distributed from a funded account:
+//! the run is bounded by what the minter holds on-chain, queried with
+//! `ledger --pem <pem> <url> balance <token>`.
+//!
+//! When the balance doesn't cover the run, recipients are filled in the
+//! order of `priorities.json`, as with `--total-max`. What they aren't
+//! minted stays in their balance, so the shortfall carries to the next run
+//! without anything to record.
+//!
+//! `verify` compares what each recipient holds, queried with `ledger <url>
+//! balance <id> <token>`, with what was minted to it.
+use crate::Ledger;
+use std::path::Path;
+use std::process::Command;
+
+/// The balance of the token of `target` held by the identity of `pem`, in
+/// base units, queried with `binary`.
+pub fn minter_balance(binary: &Path, pem: &Path, target: &Ledger) -> Result<u64, anyhow::Error> {
+    let mut command = Command::new(binary);
+    command
+        .arg("--pem")
+        .arg(pem)
+        .args([&target.url, "balance", &target.token]);
+    query(binary, command, target, "the minter")
+}
+
+/// The balance of the token of `target` held by `id`, in base units,
+/// queried with `binary`.
+pub fn balance_of(binary: &Path, id: &str, target: &Ledger) -> Result<u64, anyhow::Error> {
+    let mut command = Command::new(binary);
+    command.args([&target.url, "balance", id, &target.token]);
+    query(binary, command, target, id)
+}
+
+fn query(
+    binary: &Path,
+    mut command: Command,
+    target: &Ledger,
+    whose: &str,
+) -> Result<u64, anyhow::Error> {
+    let output = command
+        .output()
+        .map_err(|e| anyhow::anyhow!("Could not run {:?}: {}", binary, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{:?} failed ({}), could not get the balance of {}: {}",
+            binary,
+            output.status,
+            whose,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // Balances are printed one per line, as in `1000000000 MFX (<token>)`.
+    // A token the identity doesn't hold can be left out.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().find(|line| {
+        line.split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| word == target.token)
+    });
+    let Some(line) = line else {
+        return Ok(0);
+    };
+    line.split_whitespace()
+        .find_map(|word| word.parse::<u64>().ok())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{:?} didn't print the balance of {}: {}",
+                binary,
+                target.token,
+                line.trim()
+            )
+        })
+}
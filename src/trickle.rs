@@ -0,0 +1,59 @@
+//! Submissions spread over time, for `mint --execute --trickle 10/min`.
+//! Instead of a single transaction minting to every recipient, each recipient
+//! is minted to in its own transaction, at most at the given rate, so large
+//! runs don't land on the ledger as one burst. Each transaction is recorded
+//! as a part of the run as soon as it succeeds, so a run that is interrupted
+//! midway records what was minted.
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A number of submissions per period of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub count: u32,
+    pub period: Duration,
+}
+
+impl Rate {
+    /// The time to wait between two submissions.
+    pub fn interval(&self) -> Duration {
+        self.period / self.count
+    }
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected a rate as in '10/min', got '{s}'");
+        let (count, unit) = s.split_once('/').ok_or_else(invalid)?;
+        let count = count
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|c| *c > 0)
+            .ok_or_else(invalid)?;
+        let seconds = match unit.trim() {
+            "s" | "sec" | "second" => 1,
+            "min" | "minute" => 60,
+            "h" | "hour" => 3600,
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            count,
+            period: Duration::from_secs(seconds),
+        })
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.period.as_secs() {
+            1 => write!(f, "{}/s", self.count),
+            60 => write!(f, "{}/min", self.count),
+            3600 => write!(f, "{}/h", self.count),
+            seconds => write!(f, "{} per {seconds}s", self.count),
+        }
+    }
+}
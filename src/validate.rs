@@ -0,0 +1,220 @@
+//! Validation of the input files. Unlike reading the balances, which stops at
+//! the first bad entry, every problem of every file is reported, with the
+//! line it is on, so they can all be fixed in one go.
+//!
+//! Errors are entries the balances can't be read with: invalid JSON, values
+//! that aren't amounts, amounts over the sanity cap, amounts in tokens without
+//! a rate and malformed ids.
+//! Warnings are entries that read, but are likely mistakes: amounts earlier
+//! versions read differently, and identities that were minted more than they
+//! were allocated.
+use crate::error::ReadError;
+use crate::identity::Identity;
+use crate::storage::Storage;
+use crate::{
+    convert, entry_disabled, entry_status, format_tokens, input_entries, input_files, managed,
+    read_entry, restate, EFFECTIVE_KEY,
+};
+use chrono::NaiveDate;
+use clap::Parser;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Parser)]
+pub struct ValidateOpt {
+    /// Fail on warnings too, e.g. in CI.
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn error(&mut self, location: &str, message: impl std::fmt::Display) {
+        self.errors += 1;
+        println!("{location}: error: {message}");
+    }
+
+    fn warning(&mut self, location: &str, message: impl std::fmt::Display) {
+        self.warnings += 1;
+        println!("{location}: warning: {message}");
+    }
+}
+
+/// The line (1-based) of the entry `key` in the JSON text of a file.
+fn line_of(text: &str, key: &str) -> Option<usize> {
+    let quoted = Value::from(key).to_string();
+    text.lines()
+        .position(|line| {
+            line.split_once(&quoted)
+                .is_some_and(|(_, rest)| rest.trim_start().starts_with(':'))
+        })
+        .map(|i| i + 1)
+}
+
+/// `path:line` of the entry `key`, or just `path` if it can't be found.
+fn location(path: &Path, text: &str, key: &str) -> String {
+    match line_of(text, key) {
+        Some(line) => format!("{}:{line}", path.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Check a single file, adding its amounts to `totals`.
+fn validate_file(
+    storage: &dyn Storage,
+    path: &Path,
+    rates: &convert::Rates,
+    report: &mut Report,
+    totals: &mut BTreeMap<String, i128>,
+) {
+    let text = match storage.read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            let error = ReadError::Unreadable {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            };
+            return report.error(&path.display().to_string(), error.message());
+        }
+    };
+    let entries = match input_entries(storage, path, &text) {
+        Ok(entries) => entries,
+        Err(error) => {
+            let location = match &error {
+                ReadError::InvalidLine { line, .. } => format!("{}:{line}", path.display()),
+                ReadError::InvalidJson { source, .. } => {
+                    format!("{}:{}", path.display(), source.line())
+                }
+                _ => path.display().to_string(),
+            };
+            return report.error(&location, error.message());
+        }
+    };
+
+    for (line, key, value) in entries {
+        let location = match line {
+            Some(line) => format!("{}:{line}", path.display()),
+            None => location(path, &text, &key),
+        };
+        if key == managed::COMMENTS_KEY || key == restate::RESTATES_KEY {
+            continue;
+        }
+        if key == EFFECTIVE_KEY {
+            let valid = value
+                .as_str()
+                .is_some_and(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").is_ok());
+            if !valid {
+                let error = ReadError::InvalidDate {
+                    path: path.to_path_buf(),
+                    value,
+                };
+                report.error(&location, error.message());
+            }
+            continue;
+        }
+
+        match key.parse::<Identity>() {
+            Ok(identity) if !identity.is_addressable() => report.error(
+                &location,
+                format!("'{key}': anonymous identity cannot hold tokens"),
+            ),
+            Ok(_) => {}
+            Err(e) => report.error(&location, format!("'{key}': {e}")),
+        }
+
+        let (value, disabled) = match entry_disabled(path, &key, value) {
+            Ok(entry) => entry,
+            Err(error) => {
+                report.error(&location, error.message());
+                continue;
+            }
+        };
+        let (value, token) = match convert::entry_token(path, &key, value) {
+            Ok(entry) => entry,
+            Err(error) => {
+                report.error(&location, error.message());
+                continue;
+            }
+        };
+        let value = match entry_status(path, &key, value) {
+            Ok((value, _)) => value,
+            Err(error) => {
+                report.error(&location, error.message());
+                continue;
+            }
+        };
+        let read = read_entry(path, &key, value).and_then(|(units, loss)| match &token {
+            Some(token) => {
+                let (units, _) = rates.convert(path, &key, token, units)?;
+                Ok((units, None))
+            }
+            None => Ok((units, loss)),
+        });
+        match read {
+            Ok((units, loss)) => {
+                if let Some(loss) = loss {
+                    report.warning(
+                        &location,
+                        format!(
+                            "'{key}': {} was read as {} tokens by earlier versions, now {}",
+                            loss.value,
+                            format_tokens(loss.parsed),
+                            format_tokens(loss.exact)
+                        ),
+                    );
+                }
+                if !disabled {
+                    *totals.entry(key).or_default() += units;
+                }
+            }
+            Err(error) => report.error(&location, error.message()),
+        }
+    }
+}
+
+/// Check every input file, reporting all the problems found. Fails if there
+/// are errors, or warnings with `--strict`.
+pub fn validate(storage: &dyn Storage, opts: ValidateOpt) -> Result<(), anyhow::Error> {
+    let files = input_files(storage)?;
+    let mut report = Report::default();
+    let mut totals = BTreeMap::new();
+    let rates = convert::Rates::load(storage).unwrap_or_else(|e| {
+        report.error(convert::RATES_FILE, e);
+        convert::Rates::default()
+    });
+    for path in &files {
+        validate_file(storage, path, &rates, &mut report, &mut totals);
+    }
+    for (id, total) in totals.iter().filter(|(_, total)| **total < 0) {
+        report.warning(
+            "(all files)",
+            format!(
+                "'{id}': net balance of {} tokens, more was minted than allocated",
+                format_tokens(*total)
+            ),
+        );
+    }
+
+    eprintln!(
+        "{} file(s) checked, {} error(s), {} warning(s).",
+        files.len(),
+        report.errors,
+        report.warnings
+    );
+    if report.errors > 0 {
+        anyhow::bail!("Found {} error(s) in the input files.", report.errors);
+    }
+    if opts.strict && report.warnings > 0 {
+        anyhow::bail!(
+            "Found {} warning(s) in the input files (--strict).",
+            report.warnings
+        );
+    }
+    Ok(())
+}
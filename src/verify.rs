@@ -0,0 +1,75 @@
+//! Verification of the mint history against the ledger. The on-chain balance
+//! of each recipient is queried with the `ledger` binary (see
+//! [`treasury`](crate::treasury)), and compared with the lifetime totals of
+//! the mint files. A recipient with less on-chain than was minted usually
+//! means a mint command was printed but never run.
+//!
+//! Offline, `--onchain` gives the balances instead, as a JSON object of base
+//! units per identity, the same format as a plan.
+use crate::plan::read_plan;
+use crate::storage::Storage;
+use crate::{format_tokens, totals, treasury, Ledger};
+use clap::Parser;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub struct VerifyOpt {
+    /// The on-chain balance of each identity, in base units, to compare with
+    /// instead of querying the ledger.
+    #[arg(long)]
+    onchain: Option<PathBuf>,
+
+    /// The `ledger` binary to query the balances with.
+    #[arg(long, default_value = "ledger", conflicts_with = "onchain")]
+    ledger: PathBuf,
+
+    #[command(flatten)]
+    target: Ledger,
+
+    /// Only flag identities that have less on-chain than was minted. Others
+    /// may have received tokens from elsewhere.
+    #[arg(long)]
+    missing_only: bool,
+}
+
+pub fn verify(storage: &dyn Storage, opts: VerifyOpt) -> Result<(), anyhow::Error> {
+    let minted = totals::lifetime(storage)?;
+    let onchain = match &opts.onchain {
+        Some(path) => read_plan(path)?,
+        None => {
+            let mut onchain = BTreeMap::new();
+            for id in minted.keys() {
+                let balance = treasury::balance_of(&opts.ledger, id, &opts.target)?;
+                onchain.insert(id.clone(), balance);
+            }
+            onchain
+        }
+    };
+
+    let mut discrepancies = 0;
+    let ids = minted.keys().chain(onchain.keys()).collect::<BTreeSet<_>>();
+    for id in ids {
+        let expected = minted.get(id).copied().unwrap_or_default() as i128;
+        let actual = onchain.get(id).copied().unwrap_or_default() as i128;
+        if actual == expected || (opts.missing_only && actual > expected) {
+            continue;
+        }
+        let delta = actual - expected;
+        println!(
+            "{}: minted {}, on-chain {} ({}{})",
+            id,
+            format_tokens(expected),
+            format_tokens(actual),
+            if delta > 0 { "+" } else { "" },
+            format_tokens(delta)
+        );
+        discrepancies += 1;
+    }
+
+    if discrepancies > 0 {
+        anyhow::bail!("{discrepancies} discrepancy(ies) between the mint files and the ledger.");
+    }
+    eprintln!("The ledger matches the mint files.");
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Versioning of the data directory. A `VERSION` file records which version of
+//! the tool last wrote to the directory, so that an older binary doesn't
+//! silently misinterpret files written with newer conventions.
+use crate::storage::Storage;
+use std::path::Path;
+
+pub const VERSION_FILE: &str = "VERSION";
+
+fn parse(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.trim().splitn(3, '.').map(|p| p.parse::<u64>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch))) => Some((major, minor, patch)),
+        _ => None,
+    }
+}
+
+fn current() -> (u64, u64, u64) {
+    parse(env!("CARGO_PKG_VERSION")).expect("Invalid crate version")
+}
+
+/// Check the directory version against ours. Refuses directories written by a
+/// newer major version, and migrates older directories (including those that
+/// predate the `VERSION` file) unless `read_only` is set.
+pub fn check(storage: &dyn Storage, read_only: bool) -> Result<(), anyhow::Error> {
+    let path = Path::new(VERSION_FILE);
+    let ours = current();
+
+    let theirs =
+        if storage.exists(path) {
+            let content = storage.read_to_string(path)?;
+            Some(parse(&content).ok_or_else(|| {
+                anyhow::anyhow!("Invalid version '{}' in {:?}", content.trim(), path)
+            })?)
+        } else {
+            None
+        };
+
+    match theirs {
+        Some(theirs) if theirs.0 > ours.0 => anyhow::bail!(
+            "Directory was written by version {}.{}.{}, which is newer than this binary ({}). Please upgrade.",
+            theirs.0,
+            theirs.1,
+            theirs.2,
+            env!("CARGO_PKG_VERSION"),
+        ),
+        Some(theirs) if theirs >= ours => Ok(()),
+        _ if read_only => Ok(()),
+        _ => {
+            // There are no file convention changes to migrate yet; recording
+            // the version is all that's needed.
+            storage.write(path, format!("{}\n", env!("CARGO_PKG_VERSION")).as_bytes())
+        }
+    }
+}
@@ -0,0 +1,591 @@
+//! Golden-file tests for the generated output. Each case runs the binary
+//! against a fixture directory and compares its output with
+//! `tests/golden/<case>.txt`. Run with `UPDATE_GOLDEN=1` to accept changes.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn run(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_many-after8"))
+        .arg("--dir")
+        .arg(dir)
+        .args(args)
+        .env_remove("MANY_AFTER8_READ_ONLY")
+        .env_remove("MANY_AFTER8_URL")
+        .env_remove("MANY_AFTER8_TOKEN")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Run the binary, expecting it to fail. Returns its stderr.
+fn fail(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_many-after8"))
+        .arg("--dir")
+        .arg(dir)
+        .args(args)
+        .env_remove("MANY_AFTER8_READ_ONLY")
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{args:?} succeeded");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+fn compare(case: &str, actual: &str) {
+    let golden = tests_dir().join("golden").join(format!("{case}.txt"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&golden)
+        .unwrap_or_else(|_| panic!("Missing {golden:?}, run with UPDATE_GOLDEN=1"));
+    assert_eq!(
+        actual, expected,
+        "Output of '{case}' changed. Run with UPDATE_GOLDEN=1 to accept."
+    );
+}
+
+/// Replace the values of `"date"` fields, which change with every run.
+fn mask_dates(output: &str) -> String {
+    output
+        .lines()
+        .map(|line| match line.split_once("\"date\": \"") {
+            Some((before, after)) => {
+                let rest = after.split_once('"').map_or("", |(_, rest)| rest);
+                format!("{before}\"date\": \"<date>\"{rest}\n")
+            }
+            None => format!("{line}\n"),
+        })
+        .collect()
+}
+
+/// Run read-only against a fixture, so the fixture is never modified.
+fn check(case: &str, fixture: &str, args: &[&str]) {
+    let dir = tests_dir().join("fixtures").join(fixture);
+    let mut all = vec!["--read-only"];
+    all.extend_from_slice(args);
+    compare(case, &mask_dates(&run(&dir, &all)));
+}
+
+#[test]
+fn mint_command() {
+    check(
+        "mint_command",
+        "basic",
+        &["mint", "--dry-run", "--pem", "id.pem", "--max", "5"],
+    );
+}
+
+#[test]
+fn mint_command_memo() {
+    check(
+        "mint_command_memo",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--memo",
+            "Q1 grants",
+        ],
+    );
+}
+
+#[test]
+fn mint_command_target() {
+    check(
+        "mint_command_target",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--url",
+            "http://localhost:8000",
+            "--token",
+            "mahtxhiqknrn3ttw4cp4uyimzyhupqmwjycavfjmu24plwdabl",
+        ],
+    );
+}
+
+#[test]
+fn mint_json() {
+    check(
+        "mint_json",
+        "basic",
+        &["mint", "--dry-run", "--pem", "id.pem", "--format", "json"],
+    );
+}
+
+#[test]
+fn mint_payload() {
+    check(
+        "mint_payload",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--format",
+            "payload",
+        ],
+    );
+}
+
+#[test]
+fn mint_supply() {
+    check(
+        "mint_supply",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--max",
+            "5",
+            "--supply",
+            "tests/fixtures/supply.json",
+            "--truncate-to-supply",
+            "--format",
+            "payload",
+        ],
+    );
+}
+
+#[test]
+fn mint_order_amount() {
+    check(
+        "mint_order_amount",
+        "basic",
+        &["mint", "--dry-run", "--pem", "id.pem", "--order", "amount"],
+    );
+}
+
+#[test]
+fn mint_min() {
+    check(
+        "mint_min",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--max",
+            "5",
+            "--min",
+            "4",
+        ],
+    );
+}
+
+#[test]
+fn mint_chunks() {
+    check(
+        "mint_chunks",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--max",
+            "5",
+            "--chunk-size",
+            "2",
+        ],
+    );
+}
+
+#[test]
+fn mint_report() {
+    check(
+        "mint_report",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--max",
+            "5",
+            "--report-format",
+            "json",
+        ],
+    );
+}
+
+#[test]
+fn mint_report_is_only_written_outside_the_directory() {
+    let dir = tests_dir().join("fixtures").join("basic");
+    let out = std::env::temp_dir().join(format!("after8-report-{}.json", std::process::id()));
+    let report = out.display().to_string();
+    let mint = ["mint", "--dry-run", "--pem", "id.pem", "--report"];
+
+    let read_only = [&["--read-only"][..], &mint, &[&report]].concat();
+    assert!(fail(&dir, &read_only).contains("read-only"));
+    assert!(!out.exists());
+
+    let inside = dir.join("report.json").display().to_string();
+    assert!(fail(&dir, &[&mint[..], &[&inside]].concat()).contains("data directory"));
+    assert!(!dir.join("report.json").exists());
+}
+
+#[test]
+fn mint_is_refused_during_a_blackout() {
+    let dir = std::env::temp_dir().join(format!("after8-blackout-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let grants = tests_dir().join("fixtures/basic/grants.json");
+    std::fs::copy(grants, dir.join("grants.json")).unwrap();
+    std::fs::write(
+        dir.join("calendar.yaml"),
+        "blackouts:\n  - start: 2000-01-01\n    end: 2999-12-31\n    reason: legal hold\n",
+    )
+    .unwrap();
+
+    let stderr = fail(&dir, &["mint", "--pem", "id.pem"]);
+    assert!(stderr.contains("Refusing to mint during a blackout from 2000-01-01 to 2999-12-31"));
+    let files = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name());
+    assert!(!files
+        .filter_map(|name| name.into_string().ok())
+        .any(|name| name.starts_with("mint-")));
+
+    // Dry runs and overrides only warn.
+    run(&dir, &["mint", "--dry-run", "--pem", "id.pem"]);
+    run(
+        &dir,
+        &[
+            "mint",
+            "--override-blackout",
+            "--bootstrap",
+            "--pem",
+            "id.pem",
+        ],
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plan_comments_are_refused_read_only() {
+    let dir = tests_dir().join("fixtures").join("basic");
+    let plan = std::env::temp_dir().join(format!("after8-plan-{}.json", std::process::id()));
+    std::fs::write(&plan, "{}\n").unwrap();
+    let path = plan.display().to_string();
+
+    let args = [
+        "--read-only",
+        "plan",
+        "comment",
+        &path,
+        "-m",
+        "Looks good.",
+        "--author",
+        "alice",
+    ];
+    assert!(fail(&dir, &args).contains("read-only"));
+    let comments = PathBuf::from(format!("{path}.comments"));
+    assert!(!comments.exists());
+    std::fs::remove_file(&plan).unwrap();
+}
+
+#[test]
+fn mint_canonical() {
+    check(
+        "mint_canonical",
+        "basic",
+        &["mint", "--dry-run", "--pem", "id.pem", "--canonical"],
+    );
+}
+
+#[test]
+fn balances() {
+    check("balances", "basic", &["balances"]);
+}
+
+#[test]
+fn balances_skip() {
+    check(
+        "balances_skip",
+        "basic",
+        &[
+            "balances",
+            "--skip",
+            "magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e",
+        ],
+    );
+}
+
+#[test]
+fn mint_only() {
+    check(
+        "mint_only",
+        "basic",
+        &[
+            "mint",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--only",
+            "@tests/fixtures/only.txt",
+            "--format",
+            "payload",
+        ],
+    );
+}
+
+#[test]
+fn balances_rounded() {
+    check(
+        "balances_rounded",
+        "basic",
+        &["balances", "--amounts", "rounded", "--ticker", "MFX"],
+    );
+}
+
+#[test]
+fn plan_diff_preview() {
+    check(
+        "plan_diff_preview",
+        "basic",
+        &[
+            "plan",
+            "diff",
+            "tests/fixtures/plans/preview.json",
+            "tests/fixtures/plans/b.json",
+        ],
+    );
+}
+
+#[test]
+fn plan_diff() {
+    check(
+        "plan_diff",
+        "basic",
+        &[
+            "plan",
+            "diff",
+            "tests/fixtures/plans/a.json",
+            "tests/fixtures/plans/b.json",
+        ],
+    );
+}
+
+#[test]
+fn plan_show() {
+    check(
+        "plan_show",
+        "basic",
+        &["plan", "show", "tests/fixtures/plans/a.json"],
+    );
+}
+
+#[test]
+fn plan_diff_toml() {
+    // Plans shown as TOML read back the same.
+    check(
+        "plan_diff",
+        "basic",
+        &[
+            "plan",
+            "diff",
+            "tests/fixtures/plans/a.json",
+            "tests/fixtures/plans/b.toml",
+        ],
+    );
+}
+
+#[test]
+fn mint_file() {
+    // Mint for real in a copy of the fixture, and compare the mint file.
+    let fixture = tests_dir().join("fixtures").join("basic");
+    let dir = std::env::temp_dir().join(format!("after8-golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for entry in std::fs::read_dir(&fixture).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+
+    run(&dir, &["mint", "--pem", "id.pem", "--max", "5"]);
+    let mut written = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| {
+            let name = p.file_name().unwrap().to_string_lossy();
+            name.starts_with("mint-") && !fixture.join(&*name).exists()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(written.len(), 1, "Expected exactly one new mint file");
+    let content = std::fs::read_to_string(written.pop().unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    compare("mint_file", &content);
+}
+
+#[test]
+fn disable() {
+    // Disable an entry in a copy of the fixture, then enable it again.
+    let fixture = tests_dir().join("fixtures").join("basic");
+    let dir = std::env::temp_dir().join(format!("after8-disable-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for entry in std::fs::read_dir(&fixture).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+    let bob = "magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e";
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+    let parse = |text: &str| serde_json::from_str::<serde_json::Value>(text).unwrap();
+    let original = parse(&read("grants.json"));
+
+    run(&dir, &["disable", bob, "--in", "grants.json"]);
+    let disabled = read("grants.json");
+    assert!(!run(&dir, &["balances"]).contains(bob));
+    run(&dir, &["enable", bob, "--in", "grants.json"]);
+    assert_eq!(parse(&read("grants.json")), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    compare("disable", &disabled);
+}
+
+#[test]
+fn history() {
+    check("history", "basic", &["history", "--show-context"]);
+}
+
+#[test]
+fn history_verbose() {
+    check("history_verbose", "sessions", &["history", "--verbose"]);
+}
+
+#[test]
+fn report_heatmap() {
+    check("report_heatmap", "heatmap", &["report", "--heatmap"]);
+}
+
+#[test]
+fn report_heatmap_csv() {
+    check(
+        "report_heatmap_csv",
+        "heatmap",
+        &["report", "--heatmap", "--csv"],
+    );
+}
+
+#[test]
+fn history_per_id() {
+    check(
+        "history_per_id",
+        "basic",
+        &[
+            "history",
+            "--per-id",
+            "--since",
+            "2024-01-01",
+            "--id",
+            "magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e",
+        ],
+    );
+}
+
+#[test]
+fn burn_preview() {
+    check(
+        "burn_preview",
+        "overminted",
+        &[
+            "burn",
+            "--dry-run",
+            "--pem",
+            "id.pem",
+            "--format",
+            "json",
+            "--memo",
+            "Clawback of {total} from {recipients} recipient(s)",
+        ],
+    );
+}
+
+#[test]
+fn rollback_burn() {
+    check(
+        "rollback_burn",
+        "basic",
+        &[
+            "rollback",
+            "mint-20240101-120000.json",
+            "--dry-run",
+            "--burn",
+            "--pem",
+            "id.pem",
+        ],
+    );
+}
+
+#[test]
+fn validate() {
+    check("validate", "basic", &["validate", "--strict"]);
+}
+
+#[test]
+fn mint_help() {
+    check("mint_help", "basic", &["mint", "--help"]);
+}
+
+#[test]
+fn balances_draft() {
+    check(
+        "balances_draft",
+        "basic",
+        &["balances", "--status", "draft"],
+    );
+}
+
+#[test]
+fn submitted_batches_are_reported() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("after8-batches-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for file in ["grants.json", "mint-20240101-120000.json"] {
+        std::fs::copy(
+            tests_dir().join("fixtures/basic").join(file),
+            dir.join(file),
+        )
+        .unwrap();
+    }
+    let ledger = dir.join("ledger");
+    std::fs::write(&ledger, "#!/bin/sh\nexit 0\n").unwrap();
+    std::fs::set_permissions(&ledger, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_many-after8"))
+        .arg("--dir")
+        .arg(&dir)
+        .args(["--progress", "json", "mint", "--pem", "id.pem", "--execute"])
+        .args(["--chunk-size", "1", "--ledger"])
+        .arg(&ledger)
+        .env_remove("MANY_AFTER8_READ_ONLY")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let batches = stderr
+        .lines()
+        .filter(|line| line.starts_with(r#"{"event":"batch_submitted""#))
+        .collect::<Vec<_>>();
+    assert_eq!(batches.len(), 2, "{stderr}");
+    assert!(batches[1].contains(r#""index":2"#), "{stderr}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,2111 @@
+//! Tests of the library API, as another tool would embed it: against an
+//! in-memory directory, without going through the binary.
+use many_after8::amounts::{AmountFormat, AmountStyle};
+use many_after8::error::ReadError;
+use many_after8::progress::{Progress, ProgressMode};
+use many_after8::search::{self, Entry};
+use many_after8::storage::{MemoryStorage, Storage};
+use many_after8::{history, BalanceSet, MintOptions, MintPlan, Order, ReadOptions, DENOMINATOR};
+use std::path::Path;
+
+const ALICE: &str = "maf4tknhxa4asdguaongy7blnbaqjzvaptqusbrb2s4u7iuq3f";
+const BOB: &str = "magil7jlg3fmcijqzb4rnzeymvdus3j6fy5x7hs3w6xqkkhi4e";
+
+fn storage() -> MemoryStorage {
+    let storage = MemoryStorage::new();
+    storage
+        .write(
+            Path::new("grants.json"),
+            format!(r#"{{"{ALICE}": "3.5", "{BOB}": 250}}"#).as_bytes(),
+        )
+        .unwrap();
+    storage
+        .write(
+            Path::new("mint-20240101-120000.json"),
+            format!(r#"{{"{BOB}": "-100"}}"#).as_bytes(),
+        )
+        .unwrap();
+    storage
+}
+
+fn balances(storage: &dyn Storage) -> BalanceSet {
+    BalanceSet::read(
+        storage,
+        &ReadOptions::default(),
+        &Progress::new(ProgressMode::None),
+    )
+    .unwrap()
+}
+
+#[test]
+fn balances_subtract_mints() {
+    let balances = balances(&storage());
+    assert_eq!(balances.get(ALICE), Some(3_500_000_000));
+    assert_eq!(balances.get(BOB), Some(150_000_000_000));
+    assert_eq!(balances.len(), 2);
+}
+
+#[test]
+fn plan_caps_and_orders() {
+    let options = MintOptions {
+        max: 100 * DENOMINATOR,
+        order: Order::Amount,
+        ..MintOptions::default()
+    };
+    let plan = MintPlan::new(&balances(&storage()), &options, &mut rand::thread_rng());
+    assert_eq!(
+        plan.entries(),
+        &[
+            (BOB.to_string(), 100_000_000_000),
+            (ALICE.to_string(), 3_500_000_000)
+        ]
+    );
+    assert_eq!(
+        plan.canonical_payload(),
+        format!(r#"{{"{ALICE}":3500000000,"{BOB}":100000000000}}"#)
+    );
+}
+
+#[test]
+fn writing_a_plan_records_the_run() {
+    let storage = storage();
+    let plan = MintPlan::new(
+        &balances(&storage),
+        &MintOptions::default(),
+        &mut rand::thread_rng(),
+    );
+    let path = plan.write(&storage, chrono::Local::now()).unwrap();
+    assert!(storage.exists(&path));
+
+    let after = balances(&storage);
+    assert_eq!(after.get(ALICE), None);
+    assert_eq!(after.get(BOB), Some(50_000_000_000));
+}
+
+#[test]
+fn writing_a_plan_logs_its_context() {
+    let storage = storage();
+    storage
+        .write(Path::new(".git/HEAD"), b"ref: refs/heads/main\n")
+        .unwrap();
+    storage
+        .write(Path::new(".git/packed-refs"), b"0123abcd refs/heads/main\n")
+        .unwrap();
+    let plan = MintPlan::new(
+        &balances(&storage),
+        &MintOptions::default(),
+        &mut rand::thread_rng(),
+    );
+    let path = plan.write(&storage, chrono::Local::now()).unwrap();
+
+    let log = storage
+        .read_to_string(Path::new(history::RUNS_LOG))
+        .unwrap();
+    let entry: serde_json::Value = serde_json::from_str(log.trim()).unwrap();
+    assert_eq!(entry["run"], path.display().to_string());
+    assert_eq!(entry["total"], 103_500_000_000u64);
+    assert_eq!(entry["context"]["data_commit"], "0123abcd");
+    assert_eq!(entry["context"]["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn invalid_amounts_name_the_entry() {
+    let storage = storage();
+    storage
+        .write(
+            Path::new("typo.json"),
+            format!(r#"{{"{ALICE}": "1.2.3"}}"#).as_bytes(),
+        )
+        .unwrap();
+    let error = BalanceSet::read(
+        &storage,
+        &ReadOptions::default(),
+        &Progress::new(ProgressMode::None),
+    )
+    .unwrap_err();
+    match error.downcast_ref::<ReadError>() {
+        Some(ReadError::InvalidAmount { path, key, value }) => {
+            assert_eq!(path, Path::new("typo.json"));
+            assert_eq!(key, ALICE);
+            assert_eq!(value, "1.2.3");
+        }
+        _ => panic!("Unexpected error: {error}"),
+    }
+}
+
+#[test]
+fn amount_formats() {
+    let format = |style, ticker: Option<&str>| AmountFormat {
+        style,
+        ticker: ticker.map(str::to_string),
+    };
+    let amount = 1_234_567_890_123;
+    assert_eq!(
+        format(AmountStyle::Full, None).format(amount),
+        "1234.567890123"
+    );
+    assert_eq!(
+        format(AmountStyle::Rounded, Some("MFX")).format(amount),
+        "1234.57 MFX"
+    );
+    assert_eq!(
+        format(AmountStyle::Rounded, None).format(-4_999_999),
+        "0.00"
+    );
+    assert_eq!(
+        format(AmountStyle::Engineering, None).format(amount),
+        "1.235e3"
+    );
+    assert_eq!(
+        format(AmountStyle::Engineering, None).format(999_999_900_000),
+        "1.000e3"
+    );
+}
+
+#[test]
+fn fuzzy_search_ranks_tighter_matches_first() {
+    let entry = |keys: &[&str]| Entry {
+        keys: keys.iter().map(|k| k.to_string()).collect(),
+        line: keys[0].to_string(),
+    };
+    let entries = [
+        entry(&["maxbob"]),
+        entry(&["mabc", "alice"]),
+        entry(&["mcarol"]),
+    ];
+    let found = search::matches(&entries, "ab")
+        .iter()
+        .map(|e| e.line.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(found, ["mabc", "maxbob"]);
+    assert_eq!(search::matches(&entries, "ALI").len(), 1);
+    assert_eq!(search::matches(&entries, "").len(), 3);
+}
+
+#[test]
+fn large_mint_files_are_split() {
+    let storage = storage();
+    let plan = MintPlan::new(
+        &balances(&storage),
+        &MintOptions::default(),
+        &mut rand::thread_rng(),
+    );
+    let paths = plan
+        .write_split(&storage, chrono::Local::now(), 80)
+        .unwrap();
+    assert_eq!(paths.len(), 2);
+    for path in &paths {
+        assert!(storage.read(path).unwrap().len() <= 80);
+    }
+
+    let after = balances(&storage);
+    assert_eq!(after.get(ALICE), None);
+    assert_eq!(after.get(BOB), Some(50_000_000_000));
+}
+
+#[test]
+fn trickled_runs_are_recorded_part_by_part() {
+    use many_after8::trickle::Rate;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    let rate = "10/min".parse::<Rate>().unwrap();
+    assert_eq!(rate.interval(), Duration::from_secs(6));
+    assert_eq!(rate.to_string(), "10/min");
+    assert!("0/s".parse::<Rate>().is_err());
+    assert!("10/day".parse::<Rate>().is_err());
+
+    let storage = storage();
+    let plan = MintPlan::new(
+        &balances(&storage),
+        &MintOptions::default(),
+        &mut rand::thread_rng(),
+    );
+    let date = chrono::Local::now();
+    let mut submitted = Vec::new();
+    for (id, amount) in plan.entries() {
+        submitted.push(BTreeMap::from([(id.clone(), *amount)]));
+        plan.write_part(&storage, date, &submitted).unwrap();
+    }
+    let manifest = format!("mint-{}.manifest", date.format("%Y%m%d-%H%M%S"));
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&storage.read(Path::new(&manifest)).unwrap()).unwrap();
+    assert_eq!(manifest["parts"].as_array().unwrap().len(), 2);
+    assert_eq!(manifest["total"], plan.total());
+    assert_eq!(many_after8::state::current(&storage).unwrap(), 1);
+
+    let after = balances(&storage);
+    assert_eq!(after.get(ALICE), None);
+    assert_eq!(after.get(BOB), Some(50_000_000_000));
+}
+
+#[test]
+fn subdirectories_are_scanned_with_recursive() {
+    use many_after8::scan::{Glob, Scan, Scanned};
+
+    let glob = |s: &str| s.parse::<Glob>().unwrap();
+    assert!(glob("2024/**/*.json").matches(Path::new("2024/q1/grants.json")));
+    assert!(glob("2024/**/*.json").matches(Path::new("2024/grants.json")));
+    assert!(!glob("2024/*.json").matches(Path::new("2024/q1/grants.json")));
+    assert!(glob("mint-2023*.json").matches(Path::new("old/mint-20230101-120000.json")));
+    assert!(glob("q?/*").matches(Path::new("q1/a.csv")));
+    assert!("/etc/*".parse::<Glob>().is_err());
+
+    let storage = storage();
+    for path in [
+        "2024/q1/extra.json",
+        "receipts/mint-x/a.json",
+        ".git/x.json",
+    ] {
+        storage
+            .write(Path::new(path), format!(r#"{{"{ALICE}": 1}}"#).as_bytes())
+            .unwrap();
+    }
+    assert_eq!(balances(&storage).get(ALICE), Some(3_500_000_000));
+
+    let recursive = Scanned {
+        storage,
+        scan: Scan {
+            recursive: true,
+            ..Scan::default()
+        },
+    };
+    assert_eq!(
+        many_after8::input_files(&recursive).unwrap(),
+        [
+            "2024/q1/extra.json",
+            "grants.json",
+            "mint-20240101-120000.json"
+        ]
+        .map(std::path::PathBuf::from)
+    );
+    assert_eq!(balances(&recursive).get(ALICE), Some(4_500_000_000));
+
+    let filtered = Scanned {
+        storage: recursive.storage,
+        scan: Scan {
+            recursive: true,
+            include: vec![glob("2024/**"), glob("mint-*.json")],
+            exclude: vec![glob("extra.json")],
+        },
+    };
+    assert_eq!(
+        many_after8::input_files(&filtered).unwrap(),
+        [std::path::PathBuf::from("mint-20240101-120000.json")]
+    );
+}
+
+#[test]
+fn seeded_plans_are_reproducible() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let storage = storage();
+    let options = MintOptions {
+        max: 10 * DENOMINATOR,
+        randomize: true,
+        order: Order::Shuffle,
+        ..MintOptions::default()
+    };
+    assert!(options.is_random());
+    let plan = |seed| {
+        MintPlan::new(
+            &balances(&storage),
+            &options,
+            &mut StdRng::seed_from_u64(seed),
+        )
+        .with_seed(seed)
+    };
+    assert_eq!(plan(7).entries(), plan(7).entries());
+    assert_ne!(plan(7).amounts(), plan(8).amounts());
+
+    plan(7).write(&storage, chrono::Local::now()).unwrap();
+    let log = storage
+        .read_to_string(Path::new(history::RUNS_LOG))
+        .unwrap();
+    let entry: serde_json::Value = serde_json::from_str(log.lines().last().unwrap()).unwrap();
+    assert_eq!(entry["seed"], 7);
+}
+
+#[test]
+fn jitter_is_configured_per_id() {
+    use many_after8::jitter::{self, Distribution, Jitter};
+
+    let storage = storage();
+    storage
+        .write(
+            Path::new(jitter::JITTER_FILE),
+            format!(r#"{{"{BOB}": {{"range": "2..2", "dist": "normal"}}}}"#).as_bytes(),
+        )
+        .unwrap();
+    let jitter = Jitter {
+        range: "0.1..0.1".parse().unwrap(),
+        dist: Distribution::Uniform,
+    };
+    let overrides = jitter::load(&storage, jitter).unwrap();
+    assert_eq!(overrides[BOB].dist, Distribution::Normal);
+    let options = MintOptions {
+        max: 10 * DENOMINATOR,
+        randomize: true,
+        jitter,
+        jitter_overrides: overrides,
+        ..MintOptions::default()
+    };
+    let plan = MintPlan::new(&balances(&storage), &options, &mut rand::thread_rng());
+    assert_eq!(plan.amounts()[ALICE], DENOMINATOR);
+    assert_eq!(plan.amounts()[BOB], 20 * DENOMINATOR);
+
+    assert!("1.2..0.8".parse::<jitter::Range>().is_err());
+    storage
+        .write(Path::new(jitter::JITTER_FILE), br#"{"nobody": "one..two"}"#)
+        .unwrap();
+    assert!(jitter::load(&storage, jitter).is_err());
+}
+
+#[test]
+fn hmac_draws_only_depend_on_the_run_id_and_the_id() {
+    use many_after8::rng::Hmac;
+
+    let storage = storage();
+    let options = MintOptions {
+        max: 10 * DENOMINATOR,
+        randomize: true,
+        ..MintOptions::default()
+    };
+    let plan = |run_id| MintPlan::new(&balances(&storage), &options, &mut Hmac::new(run_id));
+    let before = plan("2024-03");
+    assert_eq!(before.amounts(), plan("2024-03").amounts());
+    assert_ne!(before.amounts(), plan("2024-04").amounts());
+
+    // Another identity joining the run doesn't change the draws of the others.
+    storage
+        .write(
+            Path::new("more.json"),
+            br#"{"maffskv362vjlxyrgoizucphs6emc55fqolwt7hwrkuzzllibk": 500}"#,
+        )
+        .unwrap();
+    assert_eq!(plan("2024-03").amounts()[BOB], before.amounts()[BOB]);
+}
+
+#[test]
+fn disabled_entries_are_not_counted() {
+    let storage = storage();
+    storage
+        .write(
+            Path::new("grants.json"),
+            format!(r#"{{"{ALICE}": "3.5", "{BOB}": {{"amount": 250, "disabled": true}}}}"#)
+                .as_bytes(),
+        )
+        .unwrap();
+    let file = many_after8::read_allocation(&storage, Path::new("grants.json")).unwrap();
+    assert!(file.disabled.contains(BOB));
+    assert!(!file.amounts.contains_key(BOB));
+    // Only the mint of 100 is left for Bob, so there is nothing to mint.
+    assert_eq!(balances(&storage).get(BOB), None);
+
+    storage
+        .write(
+            Path::new("grants.json"),
+            format!(r#"{{"{BOB}": {{"amount": 250, "disabled": "yes"}}}}"#).as_bytes(),
+        )
+        .unwrap();
+    let Err(error) = many_after8::read_allocation(&storage, Path::new("grants.json")) else {
+        panic!("a disabled flag that isn't a boolean was read");
+    };
+    assert_eq!(
+        error.downcast_ref::<ReadError>().and_then(ReadError::key),
+        Some(BOB)
+    );
+}
+
+#[test]
+fn capped_runs_serve_priorities_first() {
+    use many_after8::priority;
+
+    let storage = storage();
+    let plan = || {
+        MintPlan::new(
+            &balances(&storage),
+            &MintOptions::default(),
+            &mut rand::thread_rng(),
+        )
+    };
+    let fair = plan().capped(50 * DENOMINATOR);
+    assert_eq!(fair.total(), 50 * DENOMINATOR);
+    assert!(fair.amounts()[ALICE] < 3_500_000_000);
+
+    storage
+        .write(
+            Path::new(priority::PRIORITY_FILE),
+            format!(r#"{{"{BOB}": 1}}"#).as_bytes(),
+        )
+        .unwrap();
+    let priorities = priority::load(&storage).unwrap();
+    let served = plan().capped_by(50 * DENOMINATOR, &priorities);
+    assert_eq!(served.entries(), [(BOB.to_string(), 50 * DENOMINATOR)]);
+    let served = plan().capped_by(102 * DENOMINATOR, &priorities);
+    assert_eq!(served.amounts()[BOB], 100 * DENOMINATOR);
+    assert_eq!(served.amounts()[ALICE], 2 * DENOMINATOR);
+}
+
+#[test]
+fn managed_files_keep_their_order_and_comments() {
+    use many_after8::managed::Entries;
+
+    let storage = storage();
+    let text = format!(
+        "{{\n  \"{BOB}\": \"250\",\n  \"_comments\": {{\n    \"{BOB}\": \"Q3 grant\"\n  }},\n  \"{ALICE}\": \"3.5\"\n}}\n"
+    );
+    storage
+        .write(Path::new("grants.json"), text.as_bytes())
+        .unwrap();
+    assert_eq!(balances(&storage).get(BOB), Some(150 * DENOMINATOR));
+
+    let entries = Entries::read(&storage, Path::new("grants.json")).unwrap();
+    assert_eq!(
+        entries.keys().collect::<Vec<_>>(),
+        [BOB, "_comments", ALICE]
+    );
+    entries.write(&storage, Path::new("grants.json")).unwrap();
+    assert_eq!(
+        storage.read_to_string(Path::new("grants.json")).unwrap(),
+        text
+    );
+}
+
+#[test]
+fn chunked_runs_are_written_in_parts() {
+    let storage = storage();
+    let before = balances(&storage);
+    let plan = MintPlan::new(&before, &MintOptions::default(), &mut rand::thread_rng());
+    let chunks = plan.chunks(1);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].entries(), &plan.entries()[..1]);
+
+    let date = chrono::Local::now();
+    let paths = plan.write_chunks(&storage, date, &chunks).unwrap();
+    assert_eq!(paths.len(), 2);
+    assert!(paths[1].to_string_lossy().ends_with("-part2.json"));
+    assert!(storage.exists(Path::new(&format!(
+        "mint-{}.{}",
+        date.format("%Y%m%d-%H%M%S"),
+        many_after8::MANIFEST_EXTENSION
+    ))));
+    let after = balances(&storage);
+    assert_eq!(after.get(ALICE), None);
+    assert_eq!(after.get(BOB), Some(50 * DENOMINATOR));
+}
+
+#[test]
+fn reviews_skip_and_edit_recipients() {
+    use many_after8::review::review;
+
+    let balances = balances(&storage());
+    let plan = MintPlan::new(&balances, &MintOptions::default(), &mut rand::thread_rng());
+    let aliases = [("bob".to_string(), BOB.to_string())].into();
+    let mut input =
+        std::io::Cursor::new("skip maf4\nset bob 200\nset magil 120\nfrobnicate\nyes\n");
+    let reviewed = review(plan.clone(), &balances, &aliases, &mut input)
+        .unwrap()
+        .unwrap();
+    // Bob only has 150 tokens left, so the first edit is refused.
+    assert_eq!(reviewed.entries(), &[(BOB.to_string(), 120 * DENOMINATOR)]);
+
+    let mut input = std::io::Cursor::new("set bob 1\n");
+    assert!(review(plan, &balances, &aliases, &mut input)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn memos_render_amounts_for_humans() {
+    use many_after8::memo::{render, Locale, MemoFormat};
+
+    let format = |locale, precision| MemoFormat { locale, precision };
+    let amount = 1_234_567_505_000_000;
+    assert_eq!(format(Locale::Plain, None).format(amount), "1234567.505");
+    assert_eq!(format(Locale::En, Some(2)).format(amount), "1,234,567.51");
+    assert_eq!(format(Locale::De, Some(0)).format(amount), "1.234.568");
+    assert_eq!(format(Locale::Ch, Some(9)).format(999), "0.000000999");
+
+    let plan = MintPlan::from_amounts([(BOB.to_string(), 1500 * DENOMINATOR)].into());
+    let aliases = [("bob".to_string(), BOB.to_string())].into();
+    let memo = render(
+        "{{Q3}}: {total} to {recipients}, {amount:bob} to Bob",
+        &plan,
+        chrono::Local::now(),
+        &aliases,
+        &format(Locale::En, None),
+    )
+    .unwrap();
+    assert_eq!(memo, "{Q3}: 1,500 to 1, 1,500 to Bob");
+    for invalid in ["{totals}", "{amount:alice}", "{total", "total}"] {
+        let format = format(Locale::En, None);
+        assert!(render(invalid, &plan, chrono::Local::now(), &aliases, &format).is_err());
+    }
+}
+
+#[test]
+fn files_are_replaced_atomically() {
+    use many_after8::storage::FsStorage;
+
+    let dir = std::env::temp_dir().join(format!("many-after8-atomic-{}", std::process::id()));
+    let storage = FsStorage::new(&dir);
+    let path = Path::new("runs/mint-20240101-120000.json");
+    storage.write(path, b"{}\n").unwrap();
+    storage.write(path, b"{\"a\": \"1\"}\n").unwrap();
+    assert_eq!(storage.read(path).unwrap(), b"{\"a\": \"1\"}\n");
+    // No temporary file is left behind.
+    assert_eq!(
+        storage.list(Path::new("runs")).unwrap(),
+        vec![path.to_path_buf()]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn conversions_are_pinned_at_the_rate_they_are_minted_at() {
+    let storage = storage();
+    let write =
+        |path: &str, content: String| storage.write(Path::new(path), content.as_bytes()).unwrap();
+    write(
+        "usdc.json",
+        format!(r#"{{"{ALICE}": {{"amount": "100", "in": "USDC"}}}}"#),
+    );
+    write("rates.json", r#"{"USDC": "0.25"}"#.to_string());
+    let before = balances(&storage);
+    assert_eq!(before.get(ALICE), Some(28_500_000_000));
+
+    let plan = MintPlan::new(&before, &MintOptions::default(), &mut rand::thread_rng());
+    plan.write(&storage, chrono::Local::now()).unwrap();
+    let log = storage.read_to_string(Path::new("runs.log")).unwrap();
+    assert!(log.contains(r#""rate":"0.25""#));
+
+    // The minted entry keeps its rate, a new one converts at the current one.
+    write("rates.json", r#"{"USDC": "0.5"}"#.to_string());
+    assert_eq!(balances(&storage).get(ALICE), None);
+    write(
+        "usdc-2.json",
+        format!(r#"{{"{ALICE}": {{"amount": "10", "in": "USDC"}}}}"#),
+    );
+    assert_eq!(balances(&storage).get(ALICE), Some(5 * DENOMINATOR));
+    write("rates.json", "{}".to_string());
+    assert!(BalanceSet::read(
+        &storage,
+        &ReadOptions::default(),
+        &Progress::new(ProgressMode::None)
+    )
+    .is_err());
+}
+
+#[test]
+fn stale_locks_are_taken_over() {
+    use many_after8::session::hostname;
+    use many_after8::storage::{self, FsStorage};
+
+    let dir = std::env::temp_dir().join(format!("many-after8-lock-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let storage = FsStorage::new(&dir);
+    let holder = |pid: u32| format!(r#"{{"pid": {pid}, "host": "{}"}}"#, hostname());
+
+    // A live process holds the lock.
+    std::fs::write(dir.join(".after8.lock"), holder(std::process::id())).unwrap();
+    let error = storage::lock(&storage).err().unwrap().to_string();
+    assert!(
+        error.contains(&format!("process {}", std::process::id())),
+        "{error}"
+    );
+
+    // A process that is gone doesn't, unless another process is taking
+    // its lock over.
+    std::fs::write(dir.join(".after8.lock"), holder(u32::MAX)).unwrap();
+    std::fs::write(dir.join(".after8.lock.takeover"), holder(1)).unwrap();
+    let error = storage::lock(&storage).err().unwrap().to_string();
+    assert!(error.contains("taking over"), "{error}");
+    std::fs::remove_file(dir.join(".after8.lock.takeover")).unwrap();
+
+    let lock = storage::lock(&storage).unwrap();
+    let content = std::fs::read_to_string(dir.join(".after8.lock")).unwrap();
+    assert!(content.contains(&format!(r#""pid":{}"#, std::process::id())));
+    drop(lock);
+    assert!(!dir.join(".after8.lock").exists());
+    assert!(!dir.join(".after8.lock.takeover").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plan_comments_are_threaded() {
+    use many_after8::plan::{add_comment, format_comments, read_comments};
+
+    let dir = std::env::temp_dir().join(format!("many-after8-comments-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let plan = dir.join("plan.json");
+    std::fs::write(&plan, format!(r#"{{"{BOB}": 100}}"#)).unwrap();
+
+    add_comment(&plan, "alice", "Bob's amount looks low.", None).unwrap();
+    std::fs::write(&plan, format!(r#"{{"{BOB}": 200}}"#)).unwrap();
+    let reply = add_comment(&plan, "bob", "Doubled it.", Some(1)).unwrap();
+    add_comment(&plan, "carol", "Approved.", None).unwrap();
+    assert!(add_comment(&plan, "carol", "Where?", Some(9)).is_err());
+
+    let comments = read_comments(&plan).unwrap();
+    assert_eq!(comments.len(), 3);
+    let lines = format_comments(&reply.plan_sha256, &comments)
+        .lines()
+        .map(|line| {
+            line.split_once(", ")
+                .map_or(line, |(who, _)| who)
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "#",
+            "# Comments:",
+            "# [1] alice",
+            "#   [2] bob",
+            "# [3] carol"
+        ]
+    );
+    assert!(format_comments(&reply.plan_sha256, &comments[..1]).contains("(on an earlier version)"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn integrity_catches_rewritten_history() {
+    use many_after8::integrity;
+
+    let storage = storage();
+    let plan = MintPlan::new(
+        &balances(&storage),
+        &MintOptions {
+            max: DENOMINATOR,
+            ..MintOptions::default()
+        },
+        &mut rand::thread_rng(),
+    );
+    let date = chrono::Local::now();
+    let run = format!("mint-{}", date.format("%Y%m%d-%H%M%S"));
+    plan.write(&storage, date).unwrap();
+    assert_eq!(integrity::check(&storage).unwrap(), (1, vec![]));
+
+    // Editing an input of the run, or removing its mint file, is caught.
+    storage
+        .write(
+            Path::new("grants.json"),
+            format!(r#"{{"{BOB}": 250}}"#).as_bytes(),
+        )
+        .unwrap();
+    storage.remove(Path::new(&format!("{run}.json"))).unwrap();
+    let (_, findings) = integrity::check(&storage).unwrap();
+    let found = findings.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            format!("grants.json: modified after {run}"),
+            format!("{run}.json: removed after {run}"),
+        ]
+    );
+}
+
+#[test]
+fn tokens_parse_exactly() {
+    use many_after8::parse_tokens;
+
+    assert_eq!(parse_tokens("1234.5"), Some(1_234_500_000_000));
+    assert_eq!(parse_tokens("-1e-9"), Some(-1));
+    assert_eq!(parse_tokens("1.5e2"), Some(150 * DENOMINATOR as i128));
+    assert_eq!(parse_tokens("1e-10"), Some(0));
+    // Exponents out of range are invalid amounts, not overflows.
+    assert_eq!(parse_tokens("1e2147483647"), None);
+    assert_eq!(parse_tokens("1e-2147483648"), Some(0));
+    assert_eq!(parse_tokens("1e40"), None);
+}
+
+#[test]
+fn pruning_is_not_reported_as_tampering() {
+    use clap::Parser;
+    use many_after8::prune::{self, PruneOpt};
+    use many_after8::signatures::{self, Signer, VerifySignaturesOpt};
+    use many_after8::{integrity, totals};
+
+    let dir = std::env::temp_dir().join(format!("many-after8-prune-{}", std::process::id()));
+    let openssl = fake_openssl(&dir);
+
+    // Alice completed over two runs, the second of which only minted her.
+    let storage = MemoryStorage::default();
+    let (first, second) = (
+        Path::new("mint-20240101-120000.json"),
+        Path::new("mint-20240201-120000.json"),
+    );
+    let files = [
+        (
+            "grants.json",
+            format!(r#"{{"{ALICE}": "5", "{BOB}": 250}}"#),
+        ),
+        (
+            "mint-20240101-120000.json",
+            format!(r#"{{"{ALICE}": "-3.5", "{BOB}": "-100"}}"#),
+        ),
+        (
+            "mint-20240201-120000.json",
+            format!(r#"{{"{ALICE}": "-1.5"}}"#),
+        ),
+    ];
+    let signer = Signer {
+        openssl: openssl.clone(),
+        pem: "id.pem".into(),
+    };
+    for (name, content) in &files {
+        storage.write(Path::new(name), content.as_bytes()).unwrap();
+        if name.starts_with("mint-") {
+            integrity::record(&storage, Path::new(name)).unwrap();
+            signer.sign_file(&storage, Path::new(name)).unwrap();
+        }
+    }
+    totals::rebuild(&storage).unwrap();
+    let verify = || {
+        let openssl = openssl.display().to_string();
+        let opts = VerifySignaturesOpt::parse_from([
+            "verify-signatures",
+            "--public-key",
+            "id.pub",
+            "--openssl",
+            &openssl,
+            "--require",
+        ]);
+        signatures::verify_signatures(&storage, opts)
+    };
+
+    let opts = PruneOpt::parse_from(["prune", "--completed"]);
+    prune::prune(&storage, opts, false).unwrap();
+    assert!(!storage.read_to_string(first).unwrap().contains(ALICE));
+    assert!(!storage.exists(second));
+    assert!(!storage.exists(&signatures::signature_path(second)));
+    assert_eq!(balances(&storage).get(BOB), Some(150 * DENOMINATOR));
+
+    // The pruned files are recorded: neither check reports them.
+    assert_eq!(integrity::check(&storage).unwrap(), (2, vec![]));
+    assert!(integrity::is_pruned(&storage, first).unwrap());
+    verify().unwrap();
+
+    // Changes after pruning still are.
+    storage
+        .write(first, format!(r#"{{"{BOB}": "-10"}}"#).as_bytes())
+        .unwrap();
+    let (_, findings) = integrity::check(&storage).unwrap();
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].file, first.display().to_string());
+    assert!(findings[0].after.starts_with("prune-"));
+    assert!(verify().is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn interest_accrues_on_unpaid_principal() {
+    use chrono::{Local, TimeZone};
+    use many_after8::interest;
+
+    let storage = MemoryStorage::new();
+    storage
+        .write(
+            Path::new("interest.json"),
+            br#"{ "due": "2024-01-01", "rate": 10, "period_days": 10 }"#,
+        )
+        .unwrap();
+    storage
+        .write(
+            Path::new("grants.json"),
+            format!(r#"{{"{ALICE}": "100", "{BOB}": "0.000000015"}}"#).as_bytes(),
+        )
+        .unwrap();
+    let rule = interest::load(&storage).unwrap().unwrap();
+    let at = |day| Local.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap();
+    let accrued = |day| interest::accrued(&storage, &rule, at(day)).unwrap();
+
+    // Nothing accrues until the due date.
+    assert!(accrued(1).is_empty());
+
+    // 10% per whole period. Fractions of a base unit are dropped.
+    let expected = [
+        (ALICE.to_string(), 10 * DENOMINATOR as i128),
+        (BOB.to_string(), 1),
+    ];
+    assert_eq!(accrued(11), expected.into());
+    let expected = [
+        (ALICE.to_string(), 20 * DENOMINATOR as i128),
+        (BOB.to_string(), 3),
+    ];
+    assert_eq!(accrued(21), expected.into());
+
+    // Half a period on the whole principal, then a period and a half on
+    // what the mint left unpaid. Bob's 0.75 base unit isn't listed.
+    storage
+        .write(
+            Path::new("mint-20240106-000000.json"),
+            format!(r#"{{"{ALICE}": "-50", "{BOB}": "-0.000000015"}}"#).as_bytes(),
+        )
+        .unwrap();
+    let expected = [(ALICE.to_string(), 12_500_000_000)];
+    assert_eq!(accrued(21), expected.into());
+}
+
+#[test]
+fn closed_periods_are_frozen() {
+    use clap::Parser;
+    use many_after8::periods::{self, ClosePeriodOpt};
+    use many_after8::prune::{self, PruneOpt};
+
+    let storage = MemoryStorage::new();
+    storage
+        .write(Path::new("periods.json"), br#"{"length": "monthly"}"#)
+        .unwrap();
+    storage
+        .write(
+            Path::new("grants.json"),
+            format!(r#"{{"{BOB}": 100}}"#).as_bytes(),
+        )
+        .unwrap();
+    let run = Path::new("mint-20240101-120000.json");
+    storage
+        .write(run, format!(r#"{{"{BOB}": "-100"}}"#).as_bytes())
+        .unwrap();
+    let close = |args: &[&str], read_only| {
+        let opts = ClosePeriodOpt::parse_from(["close-period"].iter().chain(args));
+        periods::close_period(&storage, opts, read_only)
+    };
+
+    assert!(close(&["2024-01"], true).is_err());
+    assert!(close(&["2024-13"], false).is_err());
+    let current = chrono::Local::now().format("%Y-%m").to_string();
+    assert!(close(&[&current], false).is_err());
+    close(&["2024-01"], false).unwrap();
+    assert!(close(&["2024-01"], false).is_err());
+
+    // The run is frozen, the allocation dated today isn't.
+    assert_eq!(
+        periods::frozen_in(&storage, run).unwrap().as_deref(),
+        Some("2024-01")
+    );
+    assert_eq!(
+        periods::frozen_in(&storage, Path::new("grants.json")).unwrap(),
+        None
+    );
+    periods::check(&storage).unwrap();
+
+    // Modifying, adding or removing a file of the period is refused.
+    let frozen = storage.read(run).unwrap();
+    storage
+        .write(run, format!(r#"{{"{BOB}": "-90"}}"#).as_bytes())
+        .unwrap();
+    assert!(periods::check(&storage).is_err());
+    storage.write(run, &frozen).unwrap();
+    periods::check(&storage).unwrap();
+    let added = Path::new("mint-20240115-120000.json");
+    storage
+        .write(added, format!(r#"{{"{ALICE}": "-1"}}"#).as_bytes())
+        .unwrap();
+    assert!(periods::check(&storage).is_err());
+    storage.remove(added).unwrap();
+    storage.remove(run).unwrap();
+    assert!(periods::check(&storage).is_err());
+    storage.write(run, &frozen).unwrap();
+
+    // Bob completed, but pruning would rewrite the frozen run.
+    let opts = PruneOpt::parse_from(["prune", "--completed"]);
+    let error = prune::prune(&storage, opts, false).unwrap_err();
+    assert!(error.to_string().contains("closed period 2024-01"));
+    assert_eq!(storage.read(run).unwrap(), frozen);
+
+    // Reports freeze the SHA-256 of each file, and still accept the FNV-1a
+    // hashes of reports closed by earlier versions.
+    let report_path = Path::new("periods/2024-01.json");
+    let mut report: serde_json::Value =
+        serde_json::from_slice(&storage.read(report_path).unwrap()).unwrap();
+    let name = run.display().to_string();
+    assert_eq!(
+        report["files"][&name]["hash"],
+        many_after8::sha256::hex_digest(&frozen)
+    );
+    let legacy = format!("{:016x}", many_after8::session::fnv1a(&frozen));
+    report["files"][&name]["hash"] = legacy.into();
+    storage
+        .write(report_path, report.to_string().as_bytes())
+        .unwrap();
+    periods::check(&storage).unwrap();
+    storage
+        .write(run, format!(r#"{{"{BOB}": "-90"}}"#).as_bytes())
+        .unwrap();
+    assert!(periods::check(&storage).is_err());
+    storage.write(run, &frozen).unwrap();
+
+    // Reopening accepts changes again.
+    close(&["2024-01", "--reopen"], false).unwrap();
+    assert!(close(&["2024-01", "--reopen"], false).is_err());
+    storage.write(added, b"{}").unwrap();
+    periods::check(&storage).unwrap();
+}
+
+#[test]
+fn rescaled_amounts_add_up_to_the_total() {
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    const CAROL: &str = "maffskv362vjlxyrgoizucphs6emc55fqolwt7hwrkuzzllibk";
+
+    // What's left from rounding goes one base unit at a time, in id order.
+    let amounts = [(ALICE, 10), (BOB, 10), (CAROL, 10)]
+        .map(|(id, amount)| (id.to_string(), amount))
+        .into_iter()
+        .collect::<BTreeMap<_, u64>>();
+    let plan = MintPlan::from_amounts(amounts).capped(20);
+    let expected = [(ALICE, 7), (CAROL, 7), (BOB, 6)].map(|(id, a)| (id.to_string(), a));
+    assert_eq!(plan.amounts(), &expected.into_iter().collect());
+
+    let amounts = [(ALICE, 1), (BOB, 2), (CAROL, 1_000_000_007)]
+        .map(|(id, amount)| (id.to_string(), amount))
+        .into_iter()
+        .collect::<BTreeMap<_, u64>>();
+    assert_eq!(
+        MintPlan::from_amounts(amounts).capped(1_000_000).total(),
+        1_000_000
+    );
+
+    // Randomized runs with --preserve-total mint exactly what a run without
+    // randomization would, without going over any balance.
+    let storage = storage();
+    let balances = balances(&storage);
+    for (max, total) in [
+        (DENOMINATOR, 2 * DENOMINATOR),
+        (100 * DENOMINATOR, 103_500_000_000),
+    ] {
+        let options = MintOptions {
+            max,
+            randomize: true,
+            preserve_total: true,
+            ..MintOptions::default()
+        };
+        for seed in 0..20 {
+            let plan = MintPlan::new(&balances, &options, &mut StdRng::seed_from_u64(seed));
+            assert_eq!(plan.total(), total);
+            assert!(plan.amounts()[ALICE] <= 3_500_000_000);
+            assert!(plan.amounts()[BOB] <= 150 * DENOMINATOR);
+        }
+    }
+}
+
+#[test]
+fn blackouts_cover_their_dates() {
+    use chrono::NaiveDate;
+    use many_after8::calendar;
+
+    let storage = MemoryStorage::new();
+    let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    let active = |s| {
+        calendar::active(&storage, date(s))
+            .unwrap()
+            .map(|b| b.to_string())
+    };
+    assert_eq!(active("2024-03-25"), None);
+
+    let calendar = "\
+# Freezes
+blackouts:
+  - start: 2024-03-25
+    end: 2024-04-05
+    reason: Q1 freeze
+  - date: \"2024-12-25\"
+";
+    storage
+        .write(Path::new(calendar::CALENDAR_FILE), calendar.as_bytes())
+        .unwrap();
+    let freeze = Some("blackout from 2024-03-25 to 2024-04-05 (Q1 freeze)".to_string());
+    assert_eq!(active("2024-03-24"), None);
+    assert_eq!(active("2024-03-25"), freeze);
+    assert_eq!(active("2024-04-05"), freeze);
+    assert_eq!(active("2024-04-06"), None);
+    assert_eq!(
+        active("2024-12-25"),
+        Some("blackout on 2024-12-25".to_string())
+    );
+
+    for invalid in [
+        "blackouts:\n  - start: 2024-04-05\n    end: 2024-03-25\n",
+        "blackouts:\n  - start: 2024-03-25\n",
+        "blackouts:\n  - date: tomorrow\n",
+        "holidays:\n  - date: 2024-12-25\n",
+    ] {
+        storage
+            .write(Path::new(calendar::CALENDAR_FILE), invalid.as_bytes())
+            .unwrap();
+        assert!(calendar::load(&storage).is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn laplace_noise_is_seeded_and_bounded() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let storage = storage();
+    let before = balances(&storage);
+    let options = MintOptions {
+        max: 10 * DENOMINATOR,
+        noise: Some(1.0),
+        ..MintOptions::default()
+    };
+    let plan = |seed| MintPlan::new(&before, &options, &mut StdRng::seed_from_u64(seed));
+
+    assert_eq!(plan(7).amounts(), plan(7).amounts());
+    assert!((0..20).any(|seed| plan(seed).amounts() != plan(7).amounts()));
+
+    // The noise is at most half the maximum either way, and amounts stay
+    // between nothing and the balance. Entries left with nothing are
+    // dropped.
+    for seed in 0..50 {
+        let plan = plan(seed);
+        assert!(plan.amounts().values().all(|amount| *amount > 0));
+        if let Some(alice) = plan.amounts().get(ALICE) {
+            assert!(*alice <= 3_500_000_000);
+        }
+        let bob = plan.amounts()[BOB];
+        assert!((5 * DENOMINATOR..=15 * DENOMINATOR).contains(&bob));
+    }
+
+    // What was minted comes out of the balances, which never go negative.
+    let plan = plan(7);
+    plan.write(&storage, chrono::Local::now()).unwrap();
+    let after = balances(&storage);
+    assert_eq!(
+        after.get(BOB),
+        Some(150 * DENOMINATOR - plan.amounts()[BOB])
+    );
+    let alice = plan.amounts().get(ALICE).copied().unwrap_or_default();
+    assert_eq!(after.get(ALICE).unwrap_or_default(), 3_500_000_000 - alice);
+}
+
+#[test]
+fn audit_flags_each_kind_of_anomaly() {
+    use clap::Parser;
+    use many_after8::audit::{self, AnomaliesOpt, Anomaly};
+
+    let storage = MemoryStorage::new();
+    let runs = [
+        // Tuesday to Thursday, at 10:00.
+        ("mint-20240102-100000.json", BOB, "-10"),
+        ("mint-20240103-100000.json", BOB, "-10"),
+        ("mint-20240104-100000.json", BOB, "-50"),
+        // Friday, at 10:00 and 23:00.
+        ("mint-20240105-100000.json", ALICE, "-130"),
+        ("mint-20240105-230000.json", ALICE, "-1"),
+        // Saturday.
+        ("mint-20240106-100000.json", BOB, "-10"),
+    ];
+    for (name, id, amount) in runs {
+        storage
+            .write(
+                Path::new(name),
+                format!(r#"{{"{id}": "{amount}"}}"#).as_bytes(),
+            )
+            .unwrap();
+    }
+    let find = |args: &[&str]| {
+        let opts = AnomaliesOpt::parse_from(["anomalies"].iter().chain(args));
+        audit::find_anomalies(&storage, &opts).unwrap()
+    };
+
+    let (checked, found) = find(&[]);
+    assert_eq!(checked, 6);
+    let expected = [
+        (
+            "mint-20240104-100000.json",
+            Anomaly::AboveUsual {
+                id: BOB.to_string(),
+                amount: 50 * DENOMINATOR as i128,
+                factor: 3.0,
+                median: 10 * DENOMINATOR as i128,
+            },
+        ),
+        (
+            "mint-20240105-100000.json",
+            Anomaly::AboveMax {
+                id: ALICE.to_string(),
+                amount: 130 * DENOMINATOR as i128,
+                max: 100 * DENOMINATOR as i128,
+            },
+        ),
+        (
+            "mint-20240105-230000.json",
+            Anomaly::OffHours {
+                date: "Fri 23:00".to_string(),
+            },
+        ),
+        (
+            "mint-20240106-100000.json",
+            Anomaly::OffHours {
+                date: "Sat 10:00".to_string(),
+            },
+        ),
+    ]
+    .map(|(path, anomaly)| (Path::new(path).to_path_buf(), anomaly));
+    assert_eq!(found, expected);
+    assert_eq!(
+        found[1].1.to_string(),
+        format!("{ALICE}: 130.000000000 is above the configured max of 100.000000000")
+    );
+
+    // The thresholds are configurable.
+    let (_, found) = find(&[
+        "--factor",
+        "5",
+        "--max",
+        "200",
+        "--business-start",
+        "0",
+        "--business-end",
+        "24",
+    ]);
+    assert_eq!(
+        found,
+        [(
+            Path::new("mint-20240106-100000.json").to_path_buf(),
+            Anomaly::OffHours {
+                date: "Sat 10:00".to_string()
+            }
+        )]
+    );
+}
+
+#[test]
+fn shells_get_arguments_verbatim() {
+    use many_after8::shell::Shell;
+
+    assert_eq!(Shell::Posix.quote("it's $HOME"), r"'it'\''s $HOME'");
+    assert_eq!(Shell::Powershell.quote("it's $HOME"), "'it''s $HOME'");
+    for shell in [Shell::Posix, Shell::Powershell, Shell::Cmd] {
+        assert_eq!(shell.quote_if_needed("keys/id.pem"), "keys/id.pem");
+    }
+    assert_eq!(Shell::Posix.quote_if_needed("my id.pem"), "'my id.pem'");
+    assert_eq!(Shell::Posix.quote_if_needed(""), "''");
+    assert_eq!(Shell::Posix.quote_if_needed("100%"), "100%");
+
+    // cmd expands variables in quotes too, and drops line breaks.
+    let cmd = |arg| Shell::Cmd.quote(arg);
+    assert_eq!(cmd("Q1 %USERNAME%"), r#""Q1 "^%"USERNAME"^%"""#);
+    assert_eq!(Shell::Cmd.quote_if_needed("100%"), r#""100"^%"""#);
+    assert_eq!(cmd("Q1\r\ngrants"), r#""Q1grants""#);
+    // After an odd number of quotes, cmd is outside quotes.
+    assert_eq!(cmd(r#"{"a&b": "%x%"}"#), r#""{\"a^&b\": \"^%x^%\"}""#);
+    // Backslashes before quotes are doubled.
+    assert_eq!(cmd(r"C:\keys\"), r#""C:\keys\\""#);
+    assert_eq!(cmd(r"C:\%x"), r#""C:\\"^%"x""#);
+    assert_eq!(cmd(r#"a\"b"#), r#""a\\\"b""#);
+}
+
+#[test]
+fn cbor_mint_args_are_byte_exact() {
+    use many_after8::cbor;
+    use many_after8::identity::Identity;
+    use std::collections::BTreeMap;
+
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    let id = |bytes: &[u8]| Identity::from_bytes(bytes.to_vec()).unwrap();
+    let token = id(&[0x80; 32]);
+    let (a, b) = (
+        id(&[[1].as_slice(), &[0xaa; 28]].concat()),
+        id(&[[1].as_slice(), &[0x11; 28]].concat()),
+    );
+    let distribution = [(a.clone(), 1_000_000_000), (b.clone(), 24)];
+
+    // {0: token, 1: {id: amount}, 2: [memo]}, identities tagged 10000 and
+    // the distribution sorted by identity bytes.
+    let expected = [
+        "a3",
+        "00",
+        "d92710",
+        "5820",
+        &"80".repeat(32),
+        "01",
+        "a2",
+        "d92710",
+        "581d",
+        "01",
+        &"11".repeat(28),
+        "1818",
+        "d92710",
+        "581d",
+        "01",
+        &"aa".repeat(28),
+        "1a3b9aca00",
+        "02",
+        "81",
+        "625131",
+    ]
+    .concat();
+    assert_eq!(
+        hex(&cbor::mint_args(&token, &distribution, Some("Q1"))),
+        expected
+    );
+    assert_eq!(
+        hex(&cbor::burn_args(&token, &distribution, Some("Q1"))),
+        expected
+    );
+
+    // Without a memo, the map has two entries.
+    let without_memo = hex(&cbor::mint_args(&token, &distribution, None));
+    assert_eq!(
+        without_memo,
+        format!("a2{}", expected[2..].strip_suffix("0281625131").unwrap())
+    );
+
+    // Plans encode their entries the same way, and refuse invalid ids.
+    let ledger = many_after8::Ledger {
+        url: "https://alberto.app/api".to_string(),
+        token: token.to_string(),
+    };
+    let amounts = distribution
+        .iter()
+        .map(|(id, amount)| (id.to_string(), *amount))
+        .collect::<BTreeMap<_, _>>();
+    let plan = MintPlan::from_amounts(amounts);
+    assert_eq!(hex(&plan.cbor(&ledger, Some("Q1")).unwrap()), expected);
+    assert_eq!(hex(&plan.burn_cbor(&ledger, Some("Q1")).unwrap()), expected);
+    let invalid = MintPlan::from_amounts([("alice".to_string(), 1)].into());
+    assert!(invalid.cbor(&ledger, None).is_err());
+}
+
+#[test]
+fn address_books_merge_field_by_field() {
+    use clap::Parser;
+    use many_after8::addressbook::{self, AddressbookOpt};
+    use many_after8::aliases::{self, ALIASES_FILE};
+    use many_after8::recipients::{self, RECIPIENTS_FILE};
+    use serde_json::json;
+
+    let dir = std::env::temp_dir().join(format!("many-after8-addressbook-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("theirs.json");
+    let exported = json!({
+        "format": "many-after8-addressbook",
+        "version": 1,
+        "recipients": {
+            ALICE: { "name": "Alice", "team": "ops", "tags": ["grant", "q1"] },
+            BOB: { "email": "bob@example.com" },
+        },
+        "aliases": { "bob": BOB, "carol": "maffskv362vjlxyrgoizucphs6emc55fqolwt7hwrkuzzllibk" },
+    });
+    std::fs::write(&file, exported.to_string()).unwrap();
+
+    let storage = MemoryStorage::new();
+    let ours = json!({ ALICE: { "name": "Alice", "team": "eng", "tags": ["grant"] } });
+    storage
+        .write(Path::new(RECIPIENTS_FILE), ours.to_string().as_bytes())
+        .unwrap();
+    let our_aliases = json!({ "alice": ALICE, "bob": ALICE });
+    storage
+        .write(Path::new(ALIASES_FILE), our_aliases.to_string().as_bytes())
+        .unwrap();
+    let import = |args: &[&str], read_only| {
+        let file = file.display().to_string();
+        let base = ["addressbook", "import", &file];
+        let opts = AddressbookOpt::parse_from(base.iter().chain(args));
+        addressbook::addressbook(&storage, opts, read_only)
+    };
+    let metadata = || serde_json::to_value(recipients::load_metadata(&storage).unwrap()).unwrap();
+    let aliases = || serde_json::to_value(aliases::load(&storage).unwrap()).unwrap();
+
+    // The teams conflict, and nothing is written until they are resolved.
+    assert!(import(&[], false).is_err());
+    assert!(import(&["--prefer", "theirs"], true).is_err());
+    import(&["--prefer", "theirs", "--dry-run"], true).unwrap();
+    assert_eq!(metadata(), ours);
+    assert_eq!(aliases(), our_aliases);
+
+    import(&["--prefer", "ours"], false).unwrap();
+    assert_eq!(
+        metadata(),
+        json!({
+            ALICE: { "name": "Alice", "team": "eng", "tags": ["grant", "q1"] },
+            BOB: { "email": "bob@example.com" },
+        })
+    );
+    assert_eq!(aliases()["bob"], ALICE);
+    assert_eq!(aliases()["carol"], exported["aliases"]["carol"]);
+    import(&["--prefer", "theirs"], false).unwrap();
+    assert_eq!(metadata()[ALICE]["team"], "ops");
+    assert_eq!(aliases()["bob"], BOB);
+    // Merging again changes nothing.
+    import(&[], false).unwrap();
+    assert_eq!(metadata()[ALICE]["tags"], json!(["grant", "q1"]));
+
+    // An export imports as it was into an empty directory.
+    let out = dir.join("ours.json");
+    let opts =
+        AddressbookOpt::parse_from(["addressbook", "export", "--out", out.to_str().unwrap()]);
+    let read_only =
+        AddressbookOpt::parse_from(["addressbook", "export", "--out", out.to_str().unwrap()]);
+    assert!(addressbook::addressbook(&storage, read_only, true).is_err());
+    assert!(!out.exists());
+    addressbook::addressbook(&storage, opts, false).unwrap();
+    let copy = MemoryStorage::new();
+    let opts = AddressbookOpt::parse_from(["addressbook", "import", out.to_str().unwrap()]);
+    addressbook::addressbook(&copy, opts, false).unwrap();
+    assert_eq!(
+        recipients::load_metadata(&copy).unwrap(),
+        recipients::load_metadata(&storage).unwrap()
+    );
+    assert_eq!(
+        aliases::load(&copy).unwrap(),
+        aliases::load(&storage).unwrap()
+    );
+
+    std::fs::write(&file, r#"{"format": "something-else"}"#).unwrap();
+    assert!(import(&[], false).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn verify_queries_the_ledger() {
+    use clap::Parser;
+    use many_after8::verify::{self, VerifyOpt};
+    use std::os::unix::fs::PermissionsExt;
+
+    // A `ledger` that prints what Bob holds, and nothing for others.
+    let dir = std::env::temp_dir().join(format!("many-after8-verify-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let binary = dir.join("ledger");
+    let balance = dir.join("balance");
+    let script = format!(
+        "#!/bin/sh\n[ \"$2\" = balance ] || exit 1\n[ \"$3\" = {BOB} ] && echo \"  $(cat {}) MFX ($4)\"\nexit 0\n",
+        balance.display()
+    );
+    std::fs::write(&binary, script).unwrap();
+    std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let storage = storage();
+    let verify = |args: &[&str]| {
+        let opts = VerifyOpt::parse_from(["verify"].iter().chain(args));
+        verify::verify(&storage, opts)
+    };
+    let ledger = binary.display().to_string();
+    std::fs::write(&balance, (100 * DENOMINATOR).to_string()).unwrap();
+    verify(&["--ledger", &ledger]).unwrap();
+
+    // Only 90 of the 100 minted made it on-chain.
+    std::fs::write(&balance, (90 * DENOMINATOR).to_string()).unwrap();
+    assert!(verify(&["--ledger", &ledger]).is_err());
+    assert!(verify(&["--ledger", &dir.join("missing").display().to_string()]).is_err());
+
+    // Offline, the balances come from a file.
+    let onchain = dir.join("onchain.json");
+    std::fs::write(&onchain, format!(r#"{{"{BOB}": {}}}"#, 100 * DENOMINATOR)).unwrap();
+    verify(&["--onchain", &onchain.display().to_string()]).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn supply_is_queried_or_read_fresh() {
+    use many_after8::{supply, Ledger};
+    use std::os::unix::fs::PermissionsExt;
+
+    // A `ledger` that prints the token info.
+    let dir = std::env::temp_dir().join(format!("many-after8-supply-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let binary = dir.join("ledger");
+    let script = "#!/bin/sh\n[ \"$2 $3\" = \"token info\" ] || exit 1\necho '{\"supply\": {\"maximum\": \"1000\", \"circulating\": \"990\"}}'\n";
+    std::fs::write(&binary, script).unwrap();
+    std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let queried = supply::query(&binary, &Ledger::default()).unwrap();
+    assert_eq!(queried.headroom(), Some(10));
+    assert!(supply::query(&dir.join("missing"), &Ledger::default()).is_err());
+
+    // A saved supply is only trusted if it was saved after the last run.
+    let storage = storage();
+    let saved = dir.join("supply.json");
+    std::fs::write(&saved, r#"{"maximum": 1000, "circulating": 990}"#).unwrap();
+    assert_eq!(supply::read(&storage, &saved).unwrap().headroom(), Some(10));
+    storage
+        .write(Path::new("mint-20991231-000000.json"), b"{}")
+        .unwrap();
+    let error = supply::read(&storage, &saved).unwrap_err().to_string();
+    assert!(error.contains("mint-20991231-000000.json"), "{error}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn receipts_are_signed_with_pem() {
+    use clap::Parser;
+    use many_after8::receipts::{self, ReceiptsOpt};
+
+    let dir = std::env::temp_dir().join(format!("many-after8-receipts-{}", std::process::id()));
+    let openssl = fake_openssl(&dir).display().to_string();
+    let storage = storage();
+    let opts = ReceiptsOpt::parse_from(["receipts", "--pem", "key.pem", "--openssl", &openssl]);
+    receipts::receipts(&storage, opts, false).unwrap();
+
+    let receipt = Path::new("receipts/mint-20240101-120000").join(format!("{BOB}.json"));
+    let signature = storage
+        .read(Path::new(&format!("{}.sig", receipt.display())))
+        .unwrap();
+    let input = dir.join("receipt.json");
+    std::fs::write(&input, storage.read(&receipt).unwrap()).unwrap();
+    let cksum = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("cksum < {}", input.display()))
+        .output()
+        .unwrap();
+    assert_eq!(signature, cksum.stdout);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn signing_goes_through_a_private_temporary_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // An `openssl` that records the file it is given, and fails.
+    let dir = std::env::temp_dir().join(format!("many-after8-temp-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let openssl = dir.join("openssl");
+    let seen = dir.join("seen");
+    let script = format!(
+        "#!/bin/sh\nwhile [ $# -gt 0 ]; do [ \"$1\" = -in ] && echo \"$2 $(stat -c %a \"$2\")\" > {}; shift; done\necho 'no key' >&2\nexit 1\n",
+        seen.display()
+    );
+    std::fs::write(&openssl, script).unwrap();
+    std::fs::set_permissions(&openssl, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let error = many_after8::signatures::sign(&openssl, Path::new("key.pem"), b"{}")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("no key"), "{error}");
+    let seen = std::fs::read_to_string(&seen).unwrap();
+    let (input, mode) = seen.trim().rsplit_once(' ').unwrap();
+    assert_eq!(mode, "600");
+    assert!(!Path::new(input).exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn config_hashes_follow_the_configuration_file() {
+    use many_after8::session;
+
+    let storage = storage();
+    let empty = session::config_hash(&MemoryStorage::new()).unwrap();
+    assert_eq!(session::config_hash(&storage).unwrap(), empty);
+
+    storage
+        .write(Path::new("after8.toml"), b"max = \"250\"\n")
+        .unwrap();
+    let configured = session::config_hash(&storage).unwrap();
+    assert_ne!(configured, empty);
+    storage
+        .write(Path::new("after8.toml"), b"max = \"100\"\n")
+        .unwrap();
+    assert_ne!(session::config_hash(&storage).unwrap(), configured);
+
+    // An invalid configuration has no hash.
+    storage
+        .write(Path::new("after8.toml"), b"unknown = 1\n")
+        .unwrap();
+    assert!(session::config_hash(&storage).is_err());
+}
+
+#[test]
+fn recipients_are_validated_through_aliases_and_live() {
+    use clap::Parser;
+    use many_after8::recipients::{self, ValidateRecipientsOpt, RECIPIENTS_FILE};
+    use std::os::unix::fs::PermissionsExt;
+
+    let storage = storage();
+    storage
+        .write(
+            Path::new("aliases.json"),
+            format!(r#"{{"alice": "{ALICE}"}}"#).as_bytes(),
+        )
+        .unwrap();
+    storage
+        .write(Path::new(RECIPIENTS_FILE), br#"{"alice": {"team": "ops"}}"#)
+        .unwrap();
+    let validate = |args: &[&str], allow_unknown_ids| {
+        let opts = ValidateRecipientsOpt::parse_from(["validate-recipients"].iter().chain(args));
+        recipients::validate_recipients(&storage, opts, allow_unknown_ids)
+    };
+    validate(&[], false).unwrap();
+
+    // A `ledger` that only knows Bob.
+    let dir = std::env::temp_dir().join(format!("many-after8-recipients-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let binary = dir.join("ledger");
+    let script = format!("#!/bin/sh\n[ \"$3\" = {BOB} ] || exit 1\necho \"  100 MFX ($4)\"\n");
+    std::fs::write(&binary, script).unwrap();
+    std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let ledger = binary.display().to_string();
+    assert!(validate(&["--live", "--ledger", &ledger], false).is_err());
+    validate(&["--live", "--ledger", &ledger], true).unwrap();
+
+    // Unknown ids only fail without --allow-unknown-ids.
+    storage
+        .write(Path::new(RECIPIENTS_FILE), br#"{"bob": {}}"#)
+        .unwrap();
+    assert!(validate(&[], false).is_err());
+    validate(&[], true).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "chaos")]
+#[test]
+fn chaos_truncates_reads_and_writes() {
+    use many_after8::chaos::{self, Chaotic};
+
+    let chaotic = Chaotic(Box::new(storage()));
+    chaos::install(1.0, Some(1));
+    chaotic.write(Path::new("new.json"), b"{}\n\n").unwrap_err();
+    assert!(chaotic.read(Path::new("grants.json")).is_ok());
+    chaotic.unlock().unwrap_err();
+    chaos::install(0.0, None);
+    assert_eq!(chaotic.read(Path::new("new.json")).unwrap(), b"{}");
+    chaos::install(1.0, None);
+    BalanceSet::read(
+        &chaotic,
+        &ReadOptions::default(),
+        &Progress::new(ProgressMode::None),
+    )
+    .unwrap_err();
+    chaos::install(0.0, None);
+}
+
+#[test]
+fn burns_compensate_for_reduced_allocations() {
+    let storage = storage();
+    storage
+        .write(
+            Path::new("cut.json"),
+            format!(r#"{{"{BOB}": "-160"}}"#).as_bytes(),
+        )
+        .unwrap();
+    let before = balances(&storage);
+    assert_eq!(before.get(BOB), None);
+    assert_eq!(before.overminted().get(BOB), Some(&10_000_000_000));
+
+    let plan = MintPlan::from_amounts(before.overminted().clone());
+    plan.write_burn(&storage, chrono::Local::now()).unwrap();
+    let after = balances(&storage);
+    assert_eq!(after.get(BOB), None);
+    assert!(after.overminted().is_empty());
+}
+
+#[test]
+fn amounts_in_minor_units_are_taken_as_is() {
+    let storage = MemoryStorage::new();
+    storage
+        .write(
+            Path::new("units.json"),
+            format!(r#"{{"{ALICE}": "u:1234567890", "{BOB}": {{"amount_minor": 7}}}}"#).as_bytes(),
+        )
+        .unwrap();
+    let balances = balances(&storage);
+    assert_eq!(balances.get(ALICE), Some(1_234_567_890));
+    assert_eq!(balances.get(BOB), Some(7));
+}
+
+#[test]
+fn runs_computed_from_a_stale_directory_are_refused() {
+    let storage = storage();
+    let loaded = balances(&storage);
+    let first = MintPlan::new(&loaded, &MintOptions::default(), &mut rand::thread_rng());
+    let second = MintPlan::new(&loaded, &MintOptions::default(), &mut rand::thread_rng());
+
+    let date = chrono::Local::now();
+    first.write(&storage, date).unwrap();
+    let error = second
+        .write(&storage, date + chrono::Duration::seconds(1))
+        .unwrap_err();
+    assert!(error.to_string().contains("changed since it was loaded"));
+    assert_eq!(many_after8::state::current(&storage).unwrap(), 1);
+}
+
+#[test]
+fn validation_reports_every_problem() {
+    use clap::Parser;
+    use many_after8::validate::{validate, ValidateOpt};
+
+    let storage = storage();
+    storage
+        .write(
+            Path::new("bad.json"),
+            format!("{{\n  \"{ALICE}\": true,\n  \"not-an-id\": \"1\",\n  \"{BOB}\": \"1e30\"\n}}")
+                .as_bytes(),
+        )
+        .unwrap();
+    let error = validate(&storage, ValidateOpt::parse_from(["validate"])).unwrap_err();
+    assert_eq!(error.to_string(), "Found 3 error(s) in the input files.");
+
+    // Overminting is only a warning, unless strict.
+    let storage = self::storage();
+    storage
+        .write(
+            Path::new("mint-20240201-120000.json"),
+            format!(r#"{{"{ALICE}": "-10"}}"#).as_bytes(),
+        )
+        .unwrap();
+    validate(&storage, ValidateOpt::parse_from(["validate"])).unwrap();
+    assert!(validate(&storage, ValidateOpt::parse_from(["validate", "--strict"])).is_err());
+}
+
+#[test]
+fn config_provides_option_defaults() {
+    use many_after8::config::Config;
+
+    let storage = storage();
+    storage
+        .write(
+            Path::new("after8.toml"),
+            b"# Operator defaults\nmax = \"12.5\"\nrandomize = true # for now\nnoise = 0.1\n",
+        )
+        .unwrap();
+    let config = Config::load(&storage).unwrap();
+    assert_eq!(config.get("max"), Some("12.5"));
+    assert_eq!(config.get("randomize"), Some("true"));
+    assert_eq!(config.get("noise"), Some("0.1"));
+
+    let command = clap::Command::new("mint")
+        .arg(clap::Arg::new("max").long("max").default_value("100"))
+        .arg(clap::Arg::new("memo").long("memo"));
+    let command = config.apply(command);
+    let matches = command.clone().get_matches_from(["mint"]);
+    assert_eq!(matches.get_one::<String>("max").unwrap(), "12.5");
+    let matches = command.get_matches_from(["mint", "--max", "3"]);
+    assert_eq!(matches.get_one::<String>("max").unwrap(), "3");
+
+    storage
+        .write(Path::new("after8.toml"), b"maximum = 1\n")
+        .unwrap();
+    assert!(Config::load(&storage).is_err());
+}
+
+#[test]
+fn csv_files_are_read_with_the_json_files() {
+    let storage = storage();
+    storage
+        .write(
+            Path::new("finance.csv"),
+            format!("Name,ID,Amount,Status\nAlice,{ALICE},\"1,000.5\",\nBob,{BOB},7,draft\n")
+                .as_bytes(),
+        )
+        .unwrap();
+    let set = balances(&storage);
+    assert_eq!(set.get(ALICE), Some(1_004 * DENOMINATOR));
+    assert_eq!(set.get(BOB), Some(150 * DENOMINATOR));
+
+    // The columns can be renamed in the configuration.
+    storage
+        .write(
+            Path::new("finance.csv"),
+            format!("wallet,tokens\n{ALICE},1\n").as_bytes(),
+        )
+        .unwrap();
+    storage
+        .write(
+            Path::new("after8.toml"),
+            b"csv-id-column = \"wallet\"\ncsv-amount-column = \"tokens\"\n",
+        )
+        .unwrap();
+    assert_eq!(balances(&storage).get(ALICE), Some(4_500_000_000));
+
+    storage
+        .write(
+            Path::new("finance.csv"),
+            format!("wallet,tokens\n{ALICE},1\n{ALICE},2\n").as_bytes(),
+        )
+        .unwrap();
+    let error = many_after8::read_json(&storage, Path::new("finance.csv")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("finance.csv: line 3: '{ALICE}' is already on line 2")
+    );
+}
+
+#[test]
+fn yaml_and_toml_files_are_read_with_the_json_files() {
+    let storage = storage();
+    storage
+        .write(
+            Path::new("q2.yaml"),
+            format!(
+                "# Q2 grants\n{ALICE}: 1.5  # rounded up\n{BOB}:\n  amount: 20\n  status: draft\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    storage
+        .write(
+            Path::new("q3.toml"),
+            format!("effective = \"2099-01-01\"\n\"{ALICE}\" = 7\n").as_bytes(),
+        )
+        .unwrap();
+    storage
+        .write(
+            Path::new("q4.toml"),
+            format!("{BOB} = {{ amount = \"1,000\", status = \"approved\" }}\n").as_bytes(),
+        )
+        .unwrap();
+    let set = balances(&storage);
+    // The draft and the entries not yet effective are left out.
+    assert_eq!(set.get(ALICE), Some(5 * DENOMINATOR));
+    assert_eq!(set.get(BOB), Some(1_150 * DENOMINATOR));
+
+    storage
+        .write(
+            Path::new("q2.yaml"),
+            format!("{ALICE}: 1\n\n{ALICE}: 2\n").as_bytes(),
+        )
+        .unwrap();
+    let error = many_after8::read_json(&storage, Path::new("q2.yaml")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("q2.yaml: line 3: '{ALICE}' is already on line 1")
+    );
+}
+
+#[test]
+fn identities_are_read_as_hex_and_dids() {
+    use many_after8::identity::Identity;
+
+    let alice = ALICE.parse::<Identity>().unwrap();
+    let hex = alice
+        .as_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    assert_eq!(format!("0x{hex}").parse::<Identity>().unwrap(), alice);
+    assert_eq!(format!("did:many:{ALICE}").parse(), Ok(alice));
+
+    let storage = storage();
+    storage
+        .write(
+            Path::new("exported.json"),
+            format!(r#"{{"0x{hex}": 1, "did:many:{BOB}": 2}}"#).as_bytes(),
+        )
+        .unwrap();
+    let set = balances(&storage);
+    assert_eq!(set.get(ALICE), Some(4_500_000_000));
+    assert_eq!(set.get(BOB), Some(152 * DENOMINATOR));
+
+    storage
+        .write(
+            Path::new("exported.json"),
+            format!(r#"{{"{hex}": 1, "{ALICE}": 2}}"#).as_bytes(),
+        )
+        .unwrap();
+    let error = many_after8::read_json(&storage, Path::new("exported.json")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("exported.json: '{ALICE}' is given twice, as '{hex}' and as '{ALICE}'")
+    );
+}
+
+#[test]
+fn unknown_ids_are_refused_unless_allowed() {
+    let storage = storage();
+    // A typo in Alice's identity, which no longer matches its checksum.
+    let typo = ALICE.replacen("maf4", "maf5", 1);
+    storage
+        .write(
+            Path::new("typo.json"),
+            format!(r#"{{"{typo}": 1}}"#).as_bytes(),
+        )
+        .unwrap();
+    let read = |options: &ReadOptions| {
+        BalanceSet::read(&storage, options, &Progress::new(ProgressMode::None))
+    };
+    let error = read(&ReadOptions::default()).unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ReadError>().unwrap().key(),
+        Some(typo.as_str())
+    );
+    assert!(error.to_string().contains("checksum mismatch"));
+
+    let options = ReadOptions {
+        allow_unknown_ids: true,
+        ..ReadOptions::default()
+    };
+    assert_eq!(read(&options).unwrap().get(&typo), Some(DENOMINATOR));
+}
+
+#[test]
+fn aliases_stand_for_their_identities() {
+    let storage = storage();
+    storage
+        .write(
+            Path::new("aliases.json"),
+            format!(r#"{{"alice": "{ALICE}", "bob": "did:many:{BOB}"}}"#).as_bytes(),
+        )
+        .unwrap();
+    storage
+        .write(Path::new("named.yaml"), b"alice: 1\nbob: 2\n")
+        .unwrap();
+    let set = balances(&storage);
+    assert_eq!(set.get(ALICE), Some(4_500_000_000));
+    assert_eq!(set.get(BOB), Some(152 * DENOMINATOR));
+    assert_eq!(set.get("alice"), None);
+
+    storage
+        .write(Path::new("named.yaml"), b"carol: 1\n")
+        .unwrap();
+    let error = BalanceSet::read(
+        &storage,
+        &ReadOptions::default(),
+        &Progress::new(ProgressMode::None),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "named.yaml: 'carol' is not a valid identity (not an identity, nor a name of aliases.json), tokens minted to it would be lost. Use --allow-unknown-ids to read it anyway"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn devnet_tokens_are_created_once() {
+    use many_after8::devnet;
+    use std::os::unix::fs::PermissionsExt;
+
+    // A `ledger` that prints the address of a new token, the first time only.
+    let dir = std::env::temp_dir().join(format!("many-after8-devnet-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let binary = dir.join("ledger");
+    let script = format!(
+        "#!/bin/sh\nset -e\n[ ! -e {0} ]\ntouch {0}\necho \"Token 00 created: {BOB} (DEV)\"\n",
+        dir.join("created").display()
+    );
+    std::fs::write(&binary, script).unwrap();
+    std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let storage = MemoryStorage::new();
+    let pem = Path::new("id.pem");
+    assert!(devnet::token(&storage, &binary, pem, true).is_err());
+    assert_eq!(devnet::token(&storage, &binary, pem, false).unwrap(), BOB);
+    assert_eq!(devnet::token(&storage, &binary, pem, false).unwrap(), BOB);
+    assert!(storage.exists(Path::new(devnet::TOKEN_FILE)));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn minter_balances_are_read_from_the_ledger() {
+    use many_after8::{treasury, Ledger};
+    use std::os::unix::fs::PermissionsExt;
+
+    // A `ledger` that prints the balances of the minter.
+    let dir = std::env::temp_dir().join(format!("many-after8-treasury-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let binary = dir.join("ledger");
+    let script = format!(
+        "#!/bin/sh\n[ \"$4\" = balance ] || exit 1\necho \"  42 OTHER ({ALICE})\"\necho \"  3000000000 MFX ({BOB})\"\n"
+    );
+    std::fs::write(&binary, script).unwrap();
+    std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let pem = Path::new("id.pem");
+    let target = |token: &str| Ledger {
+        token: token.to_string(),
+        ..Ledger::default()
+    };
+    assert_eq!(
+        treasury::minter_balance(&binary, pem, &target(BOB)).unwrap(),
+        3 * DENOMINATOR
+    );
+    assert_eq!(
+        treasury::minter_balance(&binary, pem, &target(ALICE)).unwrap(),
+        42
+    );
+    let missing = "mqbh742x4s356ddaryrxaowt4wxtlocekzpufodvowrirfrqaaaaa3l";
+    assert_eq!(
+        treasury::minter_balance(&binary, pem, &target(missing)).unwrap(),
+        0
+    );
+    assert!(treasury::minter_balance(&dir.join("missing"), pem, &target(BOB)).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn restatements_correct_past_runs() {
+    use clap::Parser;
+    use many_after8::restate::{self, RestateOpt};
+    use many_after8::totals;
+
+    let storage = storage();
+    let run = Path::new("mint-20240101-120000.json");
+    let restate = |args: &[&str]| {
+        let opts = RestateOpt::parse_from(["restate", "mint-20240101-120000"].iter().chain(args));
+        restate::restate(&storage, opts, false)
+    };
+
+    // Bob was minted 90 and Alice 1, not 100 and nothing.
+    let bob = format!("{BOB}=90");
+    let alice = format!("{ALICE}=1");
+    restate(&["--set", &bob, "--set", &alice, "--reason", "Typo"]).unwrap();
+    let restatements = restate::load(&storage).unwrap();
+    let corrections = &restatements[&run.display().to_string()];
+    assert_eq!(corrections.len(), 1);
+    assert_eq!(
+        corrections[0].file,
+        Path::new("restate-1-mint-20240101-120000.json")
+    );
+    assert_eq!(corrections[0].reason.as_deref(), Some("Typo"));
+    assert_eq!(
+        restate::run_amounts(&storage, run, &restatements).unwrap(),
+        [
+            (ALICE.to_string(), -(DENOMINATOR as i128)),
+            (BOB.to_string(), -90 * DENOMINATOR as i128)
+        ]
+        .into()
+    );
+
+    let after = balances(&storage);
+    assert_eq!(after.get(ALICE), Some(2_500_000_000));
+    assert_eq!(after.get(BOB), Some(160 * DENOMINATOR));
+    let lifetime = totals::lifetime(&storage).unwrap();
+    assert_eq!(lifetime[ALICE], DENOMINATOR);
+    assert_eq!(lifetime[BOB], 90 * DENOMINATOR);
+
+    // Corrections are from the run as last restated.
+    restate(&["--set", &bob]).unwrap();
+    assert!(!storage.exists(Path::new("restate-2-mint-20240101-120000.json")));
+    restate(&["--set", &format!("{BOB}=95")]).unwrap();
+    assert_eq!(balances(&storage).get(BOB), Some(155 * DENOMINATOR));
+    assert_eq!(totals::lifetime(&storage).unwrap()[BOB], 95 * DENOMINATOR);
+
+    let opts = RestateOpt::parse_from(["restate", "grants.json", "--set", &bob]);
+    assert!(restate::restate(&storage, opts, false).is_err());
+}
+
+/// An `openssl` in `dir` whose signatures are checksums of the input.
+fn fake_openssl(dir: &Path) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::create_dir_all(dir).unwrap();
+    let openssl = dir.join("openssl");
+    let script = r#"#!/bin/sh
+while [ $# -gt 0 ]; do
+  case "$1" in
+    -verify) verify=1 ;;
+    -in) input=$2; shift ;;
+    -sigfile) sigfile=$2; shift ;;
+  esac
+  shift
+done
+if [ -z "$verify" ]; then cksum < "$input"; exit; fi
+[ "$(cksum < "$input")" = "$(cat "$sigfile")" ] && exit
+echo "Signature Verification Failure"
+exit 1
+"#;
+    std::fs::write(&openssl, script).unwrap();
+    std::fs::set_permissions(&openssl, std::fs::Permissions::from_mode(0o755)).unwrap();
+    openssl
+}
+
+#[test]
+fn mint_file_signatures_catch_tampering() {
+    use clap::Parser;
+    use many_after8::signatures::{self, Signer, VerifySignaturesOpt};
+
+    let dir = std::env::temp_dir().join(format!("many-after8-signatures-{}", std::process::id()));
+    let openssl = fake_openssl(&dir);
+
+    let storage = storage();
+    let run = Path::new("mint-20240101-120000.json");
+    let verify = |args: &[&str]| {
+        let openssl = openssl.display().to_string();
+        let base = [
+            "verify-signatures",
+            "--public-key",
+            "id.pub",
+            "--openssl",
+            &openssl,
+        ];
+        let opts = VerifySignaturesOpt::parse_from(base.iter().chain(args));
+        signatures::verify_signatures(&storage, opts)
+    };
+    verify(&[]).unwrap();
+    assert!(verify(&["--require"]).is_err());
+
+    let signer = Signer {
+        openssl: openssl.clone(),
+        pem: "id.pem".into(),
+    };
+    let signature = signer.sign_file(&storage, run).unwrap();
+    assert_eq!(signature, Path::new("mint-20240101-120000.json.sig"));
+    verify(&["--require"]).unwrap();
+
+    storage
+        .write(run, format!(r#"{{"{BOB}": "-10"}}"#).as_bytes())
+        .unwrap();
+    assert!(verify(&[]).is_err());
+    storage.remove(run).unwrap();
+    assert!(verify(&[]).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sha256_matches_the_test_vectors() {
+    use many_after8::sha256::{hex_digest, hmac};
+
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+    assert_eq!(
+        hex_digest(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex_digest(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hex_digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    assert_eq!(
+        hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert_eq!(
+        hex(&hmac(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First"
+        )),
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    );
+    assert_eq!(
+        hex_digest(&[b'a'; 1000]),
+        "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+    );
+}
+
+#[test]
+fn bundles_hold_the_run_and_its_inputs() {
+    let storage = storage();
+    let bundle =
+        many_after8::bundle::build(&storage, "20240101-120000", Path::new("receipts")).unwrap();
+    assert_eq!(bundle["schema"], "many-after8/bundle");
+    assert_eq!(bundle["plan"][BOB], "100000000000");
+    assert_eq!(bundle["total"], "100000000000");
+    let inputs = bundle["inputs"].as_array().unwrap();
+    assert_eq!(inputs.len(), 2);
+    assert_eq!(
+        bundle["files"]["mint-20240101-120000.json"]["sha256"],
+        many_after8::sha256::hex_digest(
+            &storage
+                .read(Path::new("mint-20240101-120000.json"))
+                .unwrap()
+        )
+    );
+
+    // Bundling again gives the same bundle.
+    let again =
+        many_after8::bundle::build(&storage, "20240101-120000", Path::new("receipts")).unwrap();
+    assert_eq!(bundle, again);
+    assert!(
+        many_after8::bundle::build(&storage, "20240102-120000", Path::new("receipts")).is_err()
+    );
+}